@@ -0,0 +1,101 @@
+//! The checking pipeline of the server, for tools that embed it without speaking LSP, eg:
+//! pre-commit hooks, custom editors, and test harnesses.
+//!
+//! [`Router`] resolves the config of files in a set of workspace folders the same way as the
+//! server does, and checks them the same way too. The building blocks it uses are exported as
+//! well: an [`Instance`] is the config of a directory, whose policies are passed to [`check_str`],
+//! and a [`LineIndex`] converts the byte offsets of typos to LSP positions. [`Backend`] is the
+//! server itself, eg: to serve it over a transport of your own.
+//!
+//! Enabled by the `api` feature.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use serde_json::Value;
+use tower_lsp::lsp_types::{Url, WorkspaceFolder};
+
+pub use crate::codetypo::{
+    check_str, ConfigSources, Filters, Instance, LineIndex, PositionEncoding,
+};
+pub use crate::lsp::Backend;
+use crate::lsp::CheckRequest;
+use crate::state::BackendState;
+
+/// A typo found by [`Router::check`], with its zero-based line and column, in UTF-16 code units
+/// like LSP positions.
+pub type Typo = (codetypo::Typo<'static>, usize, usize);
+
+/// Routes files to the config [`Instance`] of the nearest directory with a config file in the
+/// workspace folders, like the server does, and checks them.
+pub struct Router {
+    state: BackendState,
+}
+
+impl Router {
+    /// Constructs a router for the workspace folders, with settings in the same format as the
+    /// server's `initializationOptions`, eg: `{ "wordLists": ["words.txt"] }`.
+    pub fn new(folders: &[PathBuf], settings: &Value) -> anyhow::Result<Router, anyhow::Error> {
+        let state = BackendState::default();
+        state.settings.write().unwrap().apply_settings(settings);
+        let folders = folders
+            .iter()
+            .map(|folder| {
+                let uri = Url::from_file_path(folder)
+                    .map_err(|_| anyhow!("Cannot convert {} to a URI", folder.display()))?;
+                Ok(WorkspaceFolder {
+                    uri,
+                    name: folder.display().to_string(),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>, anyhow::Error>>()?;
+        state.set_workspace_folders(folders)?;
+        Ok(Router { state })
+    }
+
+    /// Reads the config files again, eg: after they changed.
+    pub fn reload(&self) -> anyhow::Result<(), anyhow::Error> {
+        self.state.update_router()
+    }
+
+    /// Returns the workspace folder containing `path`, if any.
+    pub fn workspace_folder(&self, path: &Path) -> Option<PathBuf> {
+        self.state.workspace_folder(path)
+    }
+
+    /// Returns the config instance `path` is checked with, or `None` if the default policy applies.
+    pub fn instance(&self, path: &Path) -> Option<Arc<Instance>> {
+        self.state
+            .workspace
+            .read()
+            .unwrap()
+            .instance_at(&crate::state::canonicalize(path))
+    }
+
+    /// Checks `text`, the content of the file at `path`, with the config of the file and the
+    /// settings, returning the typos that would be reported as diagnostics by the server.
+    ///
+    /// The file is checked by the same code as the server's open documents, so suppression
+    /// comments, the `diffBase` setting, and git commit messages are handled the same way.
+    pub fn check(&self, path: &Path, text: &str) -> Vec<Typo> {
+        let Some(request) = Url::from_file_path(path)
+            .ok()
+            .and_then(|uri| CheckRequest::new(&self.state, &uri))
+        else {
+            return Vec::new();
+        };
+        request
+            .run(&self.state, text, &AtomicBool::default())
+            .into_iter()
+            .map(|(range, typo)| {
+                (
+                    typo,
+                    range.start.line as usize,
+                    range.start.character as usize,
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,175 @@
+//! Headless checking of files without an LSP client, used by the `check` subcommand to verify
+//! that editor results match CI results.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+
+use anyhow::anyhow;
+use tower_lsp::lsp_types::{Url, WorkspaceFolder};
+
+use crate::lsp::CheckRequest;
+use crate::state::{canonicalize, BackendState, Settings};
+
+/// How [`check`] writes typos.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// A `path:line:column: message` line for each typo.
+    #[default]
+    Text,
+    /// A GitHub Actions `::warning` workflow command for each typo, so typos found by a workflow
+    /// step are shown inline on pull requests.
+    Github,
+}
+
+/// Options for [`check`], mirroring the server settings of the same names.
+#[derive(Debug, Default)]
+pub struct CheckOptions {
+    /// The workspace folder config files are resolved from, defaults to the current directory.
+    pub workspace_folder: Option<PathBuf>,
+    /// A custom config file, taking precedence over the workspace config files.
+    pub config: Option<PathBuf>,
+    /// The user dictionary, defaults to `~/.config/codetypo/dictionary.toml`.
+    pub user_dictionary: Option<PathBuf>,
+    /// Names of config files discovered in addition to the supported ones, eg: `spelling.toml`.
+    pub config_file_names: Vec<String>,
+    /// Files of accepted words, one per line, relative to the workspace folder.
+    pub word_lists: Vec<PathBuf>,
+    /// Whether to only check comments and string literals in code.
+    pub syntax_aware: bool,
+    /// Typos shorter than this many chars aren't reported.
+    pub min_word_length: usize,
+    /// Whether to skip identifiers without lowercase letters.
+    pub ignore_all_caps: bool,
+    /// Whether to skip hexadecimal strings.
+    pub ignore_hex: bool,
+    /// Whether to skip long base64-like blobs.
+    pub ignore_base64: bool,
+    /// Git ref, eg: `origin/main`, that only lines changed since are checked against.
+    pub diff_base: Option<String>,
+    /// Files larger than this many bytes aren't checked, unless 0.
+    pub max_file_size: usize,
+    /// Lines longer than this many chars aren't checked, unless 0.
+    pub max_line_length: usize,
+    /// How typos are written.
+    pub format: OutputFormat,
+}
+
+/// Checks the files at `paths`, and the files in any directories, writing each typo to `out` in the
+/// [`OutputFormat`] of the options. Returns the number of typos found.
+///
+/// Config files are resolved the same way as the server does for the workspace folder, and files
+/// outside of it are checked like the server checks files outside of the workspace folders.
+pub fn check(
+    paths: &[PathBuf],
+    options: &CheckOptions,
+    out: &mut dyn Write,
+) -> anyhow::Result<usize, anyhow::Error> {
+    let settings = Settings {
+        config: options.config.clone(),
+        user_dictionary: options.user_dictionary.clone(),
+        config_file_names: options.config_file_names.clone(),
+        word_lists: options.word_lists.clone(),
+        syntax_aware: options.syntax_aware,
+        min_word_length: options.min_word_length,
+        ignore_all_caps: options.ignore_all_caps,
+        ignore_hex: options.ignore_hex,
+        ignore_base64: options.ignore_base64,
+        diff_base: options.diff_base.clone(),
+        max_file_size: options.max_file_size,
+        max_line_length: options.max_line_length,
+        ..Settings::default()
+    };
+    let cwd = std::env::current_dir()?;
+    let workspace_folder = match &options.workspace_folder {
+        Some(folder) => cwd.join(folder),
+        None => cwd.clone(),
+    };
+    let uri = Url::from_file_path(&workspace_folder)
+        .map_err(|_| anyhow!("Cannot convert {} to a URI", workspace_folder.display()))?;
+
+    // files are routed to their config and checked by the server's own code
+    let state = BackendState::default();
+    *state.settings.write().unwrap() = settings;
+    state.set_workspace_folders(vec![WorkspaceFolder {
+        uri,
+        name: workspace_folder.display().to_string(),
+    }])?;
+
+    let mut count = 0;
+    for path in paths {
+        let path = cwd.join(path);
+        let instance = state
+            .workspace
+            .read()
+            .unwrap()
+            .instance_at(&canonicalize(&path));
+        // without a route, files are walked and checked with the defaults, like the server does
+        let files = match instance {
+            Some(instance) => instance.walk(&path).build(),
+            None => ignore::WalkBuilder::new(&path).build(),
+        };
+        for entry in files {
+            let entry = entry?;
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let file = entry.path();
+            let Some(request) = Url::from_file_path(file)
+                .ok()
+                .and_then(|uri| CheckRequest::new(&state, &uri))
+            else {
+                continue;
+            };
+            // skip files that can't be decoded, like the server does
+            let Ok(bytes) = std::fs::read(file) else {
+                continue;
+            };
+            let Some(text) = crate::codetypo::decode(&bytes) else {
+                continue;
+            };
+
+            let typos = request.run(&state, &text, &AtomicBool::default());
+            let display = file.strip_prefix(&cwd).unwrap_or(file).display();
+            for (range, typo) in typos {
+                let (line_num, line_pos) = (range.start.line, range.start.character);
+                let message = crate::lsp::message(&typo, 0);
+                match options.format {
+                    OutputFormat::Text => writeln!(
+                        out,
+                        "{}:{}:{}: {}",
+                        display,
+                        line_num + 1,
+                        line_pos + 1,
+                        message
+                    )?,
+                    OutputFormat::Github => writeln!(
+                        out,
+                        "::warning file={},line={},col={},title=Typo::{}",
+                        escape_property(&display.to_string()),
+                        line_num + 1,
+                        line_pos + 1,
+                        escape_data(&message)
+                    )?,
+                }
+                count += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+/// Escapes the message of a GitHub Actions workflow command, so it isn't cut at a newline.
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property of a GitHub Actions workflow command, eg: a file path containing a comma.
+fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
@@ -1,45 +1,191 @@
 //! Core logic for Codetypo spell checking, ignore handling, and position calculations.
 
-use std::path::Path;
+use std::mem::ManuallyDrop;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use bstr::ByteSlice;
 use codetypo_cli::policy;
 use ignore::overrides::{Override, OverrideBuilder};
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+
+/// Where an instance's config comes from, in addition to the config file found from its path.
+#[derive(Debug, Default, Clone)]
+pub struct ConfigSources {
+    /// Names of config files discovered in the path or its parents, in addition to the supported
+    /// ones.
+    pub names: Vec<String>,
+    /// Config files applied in order, each taking precedence over the previous ones.
+    pub files: Vec<PathBuf>,
+    /// Config taking precedence over all the config files.
+    pub inline: Option<codetypo_cli::config::Config>,
+    /// Files of accepted words, one per line. Relative paths are resolved against the path.
+    pub word_lists: Vec<PathBuf>,
+    /// The locale of the dictionary, unless a config file sets one.
+    pub locale: Option<codetypo_cli::config::Locale>,
+}
+
 /// Represents a Codetypo spell-checking instance with ignore rules and engine configuration.
-pub struct Instance<'s> {
+pub struct Instance {
+    /// The `files.extend-exclude` patterns, matching files that aren't checked.
     pub ignores: Override,
-    pub engine: policy::ConfigEngine<'s>,
+    /// The config files that were loaded, in order of precedence from lowest to highest.
+    pub config_files: Vec<PathBuf>,
+    /// The word lists that were loaded.
+    pub word_lists: Vec<PathBuf>,
+    /// The config resulting from merging the config files, for debugging.
+    pub config: codetypo_cli::config::Config,
+    /// The words accepted by the word lists and the `extend-words` of the config, which typos
+    /// are compared to for suggestions, see [`crate::suggest`].
+    pub(crate) vocabulary: Vec<String>,
+    /// The lowercase words accepted in single files, by the path of the file, see
+    /// [`crate::config::FILE_CONFIG`].
+    file_words: std::collections::HashMap<PathBuf, std::collections::HashSet<String>>,
+    /// The `files` config fields that apply to the path, used when walking it.
+    walk: codetypo_cli::config::Walk,
+    /// Borrows from `_storage`, so it's dropped explicitly before the storage, see the `Drop` impl.
+    /// Only borrow it through [`Instance::engine`], which bounds its lifetime to the instance.
+    engine: ManuallyDrop<policy::ConfigEngine<'static>>,
+    _storage: Arc<policy::ConfigStorage>,
 }
 
-impl Instance<'_> {
-    /// Constructs a new `Instance` with configuration from the given path and optional config file.
-    pub fn new<'s>(
-        path: &Path,
-        config: Option<&Path>,
-    ) -> anyhow::Result<Instance<'s>, anyhow::Error> {
-        // leak to get a 'static which is needed to satisfy the 's lifetime
-        // but does mean memory will grow unbounded
-        let storage = Box::leak(Box::new(policy::ConfigStorage::new()));
-        let mut engine = codetypo_cli::policy::ConfigEngine::new(storage);
+impl Drop for Instance {
+    fn drop(&mut self) {
+        // SAFETY: the engine is dropped once, here, while the storage it borrows from is alive, as
+        // the fields are only dropped after this
+        unsafe { ManuallyDrop::drop(&mut self.engine) };
+    }
+}
+
+impl Instance {
+    /// Constructs a new `Instance` with configuration from the given path and additional sources.
+    ///
+    /// The nearest config file with each of the source names in the path or its parents is found
+    /// like the supported config files are, then the source config files and inline config are
+    /// applied in order, each taking precedence over the previous ones and the config file from the
+    /// path. The words of the word lists are accepted, below the source config files and inline
+    /// config but above the config file from the path.
+    pub fn new(path: &Path, sources: &ConfigSources) -> anyhow::Result<Instance, anyhow::Error> {
+        let storage = Arc::new(policy::ConfigStorage::new());
+        // SAFETY: the storage is heap allocated so it doesn't move with the instance, and it
+        // outlives the engine because the instance owns both and drops the engine first, see
+        // `impl Drop for Instance`. The 'static lifetime never escapes the instance, see
+        // `Instance::engine`.
+        let storage_ref: &'static policy::ConfigStorage = unsafe { &*Arc::as_ptr(&storage) };
+        let mut engine = codetypo_cli::policy::ConfigEngine::new(storage_ref);
 
         // TODO: currently mimicking codetypo here but do we need to create and update
         // a default config?
         let mut c = codetypo_cli::config::Config::default();
-        if let Some(config_path) = config {
+        let mut has_overrides = false;
+        let mut config_files = Vec::new();
+
+        // word lists are applied as overrides below the config files, but left out of `config`
+        // which is for debugging, as they can be large
+        let mut words = codetypo_cli::config::Config::default();
+        let mut word_lists = Vec::new();
+        let mut vocabulary = Vec::new();
+        for word_list in &sources.word_lists {
+            let word_list = path.join(word_list);
+            if !word_list.is_file() {
+                tracing::debug!("Skipping missing word list {}", word_list.display());
+                continue;
+            }
+            match read_word_list(&word_list) {
+                Ok(list) => {
+                    vocabulary.extend(list.iter().cloned());
+                    words.default.dict.extend_words.extend(
+                        list.into_iter()
+                            .map(|word| (word.clone().into(), word.into())),
+                    );
+                    word_lists.push(word_list);
+                    has_overrides = true;
+                }
+                Err(e) => tracing::warn!("Cannot read word list {}: {}", word_list.display(), e),
+            }
+        }
+
+        // the engine only discovers the supported config files, so custom named ones are applied
+        // as overrides, below the explicit config files
+        let named_configs = sources.names.iter().filter_map(|name| {
+            path.ancestors()
+                .map(|dir| dir.join(name))
+                .find(|config_path| config_path.is_file())
+        });
+        for config_path in named_configs
+            .collect::<Vec<_>>()
+            .iter()
+            .chain(&sources.files)
+        {
             let custom = codetypo_cli::config::Config::from_file(config_path)?;
             if let Some(custom) = custom {
                 c.update(&custom);
-                engine.set_overrides(c);
+                has_overrides = true;
+                config_files.push(config_path.clone());
+            }
+        }
+        if let Some(inline) = &sources.inline {
+            c.update(inline);
+            has_overrides = true;
+        }
+
+        // the nearest config file to path, like the engine uses, which the overrides take
+        // precedence over
+        let mut config = codetypo_cli::config::Config::default();
+        let dir_config = path
+            .ancestors()
+            .flat_map(|dir| {
+                codetypo_cli::config::SUPPORTED_FILE_NAMES
+                    .iter()
+                    .map(move |name| dir.join(name))
+            })
+            .filter(|config_path| config_path.is_file())
+            .find_map(|config_path| {
+                codetypo_cli::config::Config::from_file(&config_path)
+                    .ok()
+                    .flatten()
+                    .map(|dir_config| (config_path, dir_config))
+            });
+        if let Some((config_path, dir_config)) = dir_config {
+            config.update(&dir_config);
+            config_files.insert(0, config_path);
+        }
+
+        // the locale setting is a default, so it only applies when no config file sets one
+        if let Some(locale) = sources.locale {
+            if config.default.dict.locale.is_none() && c.default.dict.locale.is_none() {
+                c.default.dict.locale = Some(locale);
+                has_overrides = true;
             }
         }
 
+        if has_overrides {
+            words.update(&c);
+            engine.set_overrides(words);
+        }
+        config.update(&c);
+        // words mapped to other words are typos, not accepted words
+        vocabulary.extend(
+            config
+                .default
+                .dict
+                .extend_words
+                .iter()
+                .filter(|(word, correction)| word == correction)
+                .map(|(word, _)| word.to_string()),
+        );
+
         // initialise an engine and overrides using the config file from path or its parent
         engine.init_dir(path)?;
         let walk_policy = engine.walk(path);
 
         let mut ignores = OverrideBuilder::new(path);
         // always ignore the config files like codetypo cli does
-        for f in codetypo_cli::config::SUPPORTED_FILE_NAMES {
+        for f in codetypo_cli::config::SUPPORTED_FILE_NAMES
+            .iter()
+            .chain(&[crate::config::FILE_CONFIG])
+        {
             ignores.add(&format!("!{}", f))?;
         }
 
@@ -48,29 +194,203 @@ impl Instance<'_> {
             ignores.add(&format!("!{}", pattern))?;
         }
         let ignore = ignores.build()?;
+        let walk = walk_policy.clone();
+
+        // words accepted in single files, which codetypo config can't express
+        let file_config = path.join(crate::config::FILE_CONFIG);
+        let mut file_words = std::collections::HashMap::new();
+        if file_config.is_file() {
+            match crate::config::file_words(&file_config) {
+                Ok(words) => {
+                    file_words = words;
+                    config_files.push(file_config);
+                }
+                Err(e) => tracing::warn!("Cannot read {}: {}", file_config.display(), e),
+            }
+        }
 
         Ok(Instance {
             ignores: ignore,
-            engine,
+            config_files,
+            word_lists,
+            config,
+            vocabulary,
+            file_words,
+            walk,
+            engine: ManuallyDrop::new(engine),
+            _storage: storage,
         })
     }
+
+    /// Discovers the config files in the directories nested in `path`, like codetypo cli does, so
+    /// files are checked with the config of their nearest enclosing directory.
+    ///
+    /// Directories are walked like workspace scans, so ignored directories aren't searched.
+    pub fn with_nested_configs(mut self, path: &Path) -> Self {
+        let dirs = self
+            .walk(path)
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.depth() > 0 && entry.file_type().is_some_and(|t| t.is_dir()))
+            .map(|entry| entry.into_path())
+            .collect::<Vec<_>>();
+
+        for dir in dirs {
+            match codetypo_cli::config::Config::from_dir(&dir) {
+                Ok(Some(_)) => {
+                    tracing::debug!("Using nested config in {}", dir.display());
+                    if let Err(e) = self.engine.init_dir(&dir) {
+                        tracing::warn!("Cannot use config in {}: {}", dir.display(), e);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Cannot read config in {}: {}", dir.display(), e),
+            }
+        }
+        self
+    }
+
+    /// Returns true if `word` is accepted in the file at the canonical `path` only, case
+    /// insensitively.
+    pub(crate) fn is_ignored_in_file(&self, path: &Path, word: &str) -> bool {
+        self.file_words
+            .get(path)
+            .is_some_and(|words| words.contains(&word.to_lowercase()))
+    }
+
+    /// Returns the engine, with its lifetime bound to the instance that owns its storage.
+    pub fn engine(&self) -> &policy::ConfigEngine<'_> {
+        &self.engine
+    }
+
+    /// Returns a builder for walking the files at `path` like codetypo cli does, skipping hidden
+    /// files and those matched by `.gitignore` or `.ignore` files unless the `files.ignore-*`
+    /// config fields say otherwise.
+    pub fn walk(&self, path: &Path) -> ignore::WalkBuilder {
+        let mut builder = ignore::WalkBuilder::new(path);
+        builder
+            .skip_stdout(true)
+            .git_global(self.walk.ignore_global())
+            .git_ignore(self.walk.ignore_vcs())
+            .git_exclude(self.walk.ignore_vcs())
+            .hidden(self.walk.ignore_hidden())
+            .ignore(self.walk.ignore_dot())
+            .parents(self.walk.ignore_parent());
+        builder
+    }
+}
+
+/// Returns the words of a word list, one per line, skipping blank lines and `#` comments.
+///
+/// Hunspell dictionaries, ie: `.dic` files, are read with their affix file instead, and cspell
+/// dictionaries, ie: gzipped word lists and `.json` manifests, in the cspell format.
+fn read_word_list(path: &Path) -> anyhow::Result<Vec<String>, anyhow::Error> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("dic") => return crate::hunspell::words(path),
+        Some("gz" | "json") => return crate::cspell::words(path),
+        _ => {}
+    }
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
 }
 
 // mimics codetypo_cli::file::FileChecker::check_file
 // see https://github.com/khulnasoft/codetypo/blob/c15b28fff9a814f9c12bd24cb1cfc114037e9187/crates/codetypo-cli/src/file.rs#L43
 // but using check_str instead of check_bytes
-/// Checks the given string for typos, returning an iterator over found typos and their positions.
+/// Checks the given byte ranges of the string for typos, returning an iterator over found typos
+/// and their positions in the whole string.
+///
+/// The ranges must be in order, not overlap, and start and end on char boundaries. Tokens skipped
+/// by the `filters` are removed from the ranges before they're checked.
 pub fn check_str<'b, 's: 'b>(
     buffer: &'b str,
+    ranges: Vec<std::ops::Range<usize>>,
     tokenizer: &'s codetypo::tokens::Tokenizer,
     dictionary: &'s dyn codetypo::Dictionary,
     ignore: &'s [regex::Regex],
+    filters: Filters,
+) -> impl Iterator<Item = (codetypo::Typo<'b>, usize, usize)> {
+    report(
+        buffer,
+        find_typos(buffer, ranges, tokenizer, dictionary, filters),
+        ignore,
+        PositionEncoding::Utf16,
+    )
+}
+
+/// Finds the typos in the given byte ranges of the string, without skipping those that are
+/// ignored or suppressed, which depends on the rest of the string.
+///
+/// Tokens don't span lines, so the typos of a line only depend on the line itself.
+pub(crate) fn find_typos<'b, 's: 'b>(
+    buffer: &'b str,
+    ranges: Vec<std::ops::Range<usize>>,
+    tokenizer: &'s codetypo::tokens::Tokenizer,
+    dictionary: &'s dyn codetypo::Dictionary,
+    filters: Filters,
+) -> impl Iterator<Item = codetypo::Typo<'b>> {
+    filters
+        .apply(buffer, ranges, tokenizer)
+        .into_iter()
+        .flat_map(move |range| {
+            codetypo::check_str(&buffer[range.clone()], tokenizer, dictionary).map(
+                move |mut typo| {
+                    // offsets are relative to the range, so make them relative to the buffer
+                    typo.byte_offset += range.start;
+                    if let codetypo::Status::Corrections(corrections) = &mut typo.corrections {
+                        crate::frequency::sort(corrections);
+                        // so edits keep the case of the typo
+                        for correction in corrections.iter_mut() {
+                            *correction = match_case(&typo.typo, correction).into();
+                        }
+                    }
+                    typo
+                },
+            )
+        })
+        .filter(move |typo| typo.typo.chars().count() >= filters.min_word_length)
+}
+
+/// Cases `word` like `typo`: uppercase when the typo is, eg: `TEH` → `THE`, capitalized when the
+/// typo is, eg: `Teh` → `The`, and as is otherwise.
+pub(crate) fn match_case(typo: &str, word: &str) -> String {
+    let mut chars = typo.chars();
+    match chars.next() {
+        Some(first) if first.is_uppercase() => {
+            if typo.chars().count() > 1 && chars.all(|c| !c.is_lowercase()) {
+                word.to_uppercase()
+            } else {
+                let mut word_chars = word.chars();
+                word_chars
+                    .next()
+                    .map(|c| c.to_uppercase().chain(word_chars).collect())
+                    .unwrap_or_default()
+            }
+        }
+        _ => word.to_string(),
+    }
+}
+
+/// Skips the typos found by [`find_typos`] that match `extend-ignore-re` or are suppressed by a
+/// comment, returning the others with their positions in the string.
+///
+/// Columns are counted in code units of `encoding`.
+pub(crate) fn report<'b>(
+    buffer: &'b str,
+    typos: impl Iterator<Item = codetypo::Typo<'b>> + 'b,
+    ignore: &'b [regex::Regex],
+    encoding: PositionEncoding,
 ) -> impl Iterator<Item = (codetypo::Typo<'b>, usize, usize)> {
-    let mut accum = AccumulatePosition::new();
+    let mut index = LineIndex::new(buffer, encoding);
 
     let mut ignores: Option<Ignores> = None;
+    let mut suppressions: Option<Suppressions> = None;
 
-    codetypo::check_str(buffer, tokenizer, dictionary)
+    typos
         .filter(move |typo| {
             // skip typo if it matches extend-ignore-re
             let is_ignored = ignores
@@ -80,9 +400,379 @@ pub fn check_str<'b, 's: 'b>(
             !is_ignored
         })
         .map(move |typo| {
-            let (line_num, line_pos) = accum.pos(buffer.as_bytes(), typo.byte_offset);
+            let (line_num, line_pos) = index.pos(typo.byte_offset);
             (typo, line_num, line_pos)
         })
+        .filter(move |(typo, line_num, _)| {
+            // skip typo if it's suppressed by a comment
+            !suppressions
+                .get_or_insert_with(|| Suppressions::new(buffer))
+                .is_suppressed(typo.span(), *line_num)
+        })
+}
+
+/// Lines checked again around the changed text, as a change can join or split lines.
+const CONTEXT_LINES: usize = 1;
+
+/// A version of a buffer and the typos [`find_typos`] found in all of it, so the typos of the
+/// lines that are unchanged in the next version can be reused.
+#[derive(Debug)]
+pub(crate) struct CheckedBuffer {
+    /// The [`crate::state::BackendState::generation`] of the config used to check the buffer.
+    pub generation: u64,
+    pub text: String,
+    pub typos: Vec<codetypo::Typo<'static>>,
+}
+
+impl CheckedBuffer {
+    /// Returns the typos of the lines of `buffer` that are unchanged from this version, with
+    /// offsets in `buffer`, and the byte range of the lines between that need checking again.
+    ///
+    /// Changes are found by comparing the start and end of the texts, so several changes far
+    /// apart, eg: with multiple cursors, are checked again as one range.
+    pub(crate) fn unchanged(
+        &self,
+        buffer: &str,
+    ) -> (Vec<codetypo::Typo<'static>>, std::ops::Range<usize>) {
+        let old = self.text.as_bytes();
+        let new = buffer.as_bytes();
+        let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        // widen the change to whole lines, whose starts are char boundaries
+        let line_start = |offset: usize| new[..offset].rfind_byte(b'\n').map_or(0, |i| i + 1);
+        let mut start = line_start(prefix);
+        for _ in 0..CONTEXT_LINES {
+            start = line_start(start.saturating_sub(1));
+        }
+        let mut end = new.len() - suffix;
+        for _ in 0..=CONTEXT_LINES {
+            end = new[end..]
+                .find_byte(b'\n')
+                .map_or(new.len(), |i| end + i + 1);
+        }
+        // the lines after the change are the same in both versions
+        let old_end = end + old.len() - new.len();
+
+        let typos = self
+            .typos
+            .iter()
+            .filter(|typo| typo.span().end <= start || old_end <= typo.byte_offset)
+            .map(|typo| {
+                let mut typo = typo.clone();
+                if old_end <= typo.byte_offset {
+                    typo.byte_offset = typo.byte_offset + new.len() - old.len();
+                }
+                typo
+            })
+            .collect();
+        (typos, start..end)
+    }
+}
+
+/// Tokens that aren't checked, so eg: keys and hashes don't produce garbage diagnostics.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Filters {
+    /// Typos shorter than this many chars are skipped.
+    pub min_word_length: usize,
+    /// Skip identifiers without lowercase letters, eg: `MAX_BUFER_SIZE`.
+    pub ignore_all_caps: bool,
+    /// Skip hexadecimal strings, eg: `0xdeadbeef` or `3f2a9c1e`.
+    pub ignore_hex: bool,
+    /// Skip long base64-like blobs, eg: `dGhpcyBpcyBhIHNlY3JldCBrZXk=`.
+    pub ignore_base64: bool,
+    /// Skip lines longer than this many chars, eg: minified code and data URIs, unless 0.
+    pub max_line_length: usize,
+}
+
+/// Runs of chars that may be hexadecimal strings or base64 blobs.
+static BLOB: Lazy<regex::Regex> = Lazy::new(|| regex::Regex::new(r"[0-9A-Za-z+/]+={0,2}").unwrap());
+
+/// Base64 blobs shorter than this are too likely to be words.
+const MIN_BASE64_LEN: usize = 20;
+
+impl Filters {
+    /// Removes the spans of the skipped tokens from the ranges of the buffer to check.
+    fn apply(
+        &self,
+        buffer: &str,
+        ranges: Vec<std::ops::Range<usize>>,
+        tokenizer: &codetypo::tokens::Tokenizer,
+    ) -> Vec<std::ops::Range<usize>> {
+        let mut skipped = self.long_lines(buffer).collect::<Vec<_>>();
+        if self.ignore_hex || self.ignore_base64 {
+            skipped.extend(
+                BLOB.find_iter(buffer)
+                    .filter(|blob| {
+                        (self.ignore_hex && is_hex(blob.as_str()))
+                            || (self.ignore_base64 && is_base64(blob.as_str()))
+                    })
+                    .map(|blob| blob.range()),
+            );
+        }
+        if self.ignore_all_caps {
+            for range in &ranges {
+                skipped.extend(
+                    tokenizer
+                        .parse_str(&buffer[range.clone()])
+                        .filter(|ident| is_all_caps(ident.token()))
+                        .map(|ident| {
+                            let start = range.start + ident.offset();
+                            start..start + ident.token().len()
+                        }),
+                );
+            }
+        }
+        if skipped.is_empty() {
+            return ranges;
+        }
+        skipped.sort_by_key(|span| span.start);
+
+        let mut checked = Vec::new();
+        for range in ranges {
+            let mut start = range.start;
+            for span in skipped
+                .iter()
+                .filter(|span| span.start < range.end && span.end > range.start)
+            {
+                if span.start > start {
+                    checked.push(start..span.start);
+                }
+                start = start.max(span.end);
+            }
+            if start < range.end {
+                checked.push(start..range.end);
+            }
+        }
+        checked
+    }
+
+    /// Returns the byte ranges of the lines longer than `max_line_length`, which aren't checked.
+    pub(crate) fn long_lines<'b>(
+        &self,
+        buffer: &'b str,
+    ) -> impl Iterator<Item = std::ops::Range<usize>> + 'b {
+        let max_line_length = self.max_line_length;
+        line_ranges(buffer)
+            .map(|range| (range.clone(), &buffer[range]))
+            .filter(move |(_, line)| {
+                // chars are never more than bytes, so only count them when there are enough bytes
+                max_line_length > 0
+                    && line.len() > max_line_length
+                    && line.trim_end().chars().count() > max_line_length
+            })
+            .map(|(range, _)| range)
+    }
+}
+
+/// Whether the token has uppercase letters, but no lowercase letters.
+fn is_all_caps(token: &str) -> bool {
+    token.chars().filter(|c| c.is_uppercase()).count() > 1 && !token.chars().any(char::is_lowercase)
+}
+
+/// Whether the blob is a `0x` prefixed hexadecimal number, or a string of at least 8 hexadecimal
+/// digits including a decimal digit, so words like `facade` aren't skipped.
+fn is_hex(blob: &str) -> bool {
+    match blob.strip_prefix("0x").or_else(|| blob.strip_prefix("0X")) {
+        Some(digits) => !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit()),
+        None => {
+            blob.len() >= 8
+                && blob.chars().all(|c| c.is_ascii_hexdigit())
+                && blob.chars().any(|c| c.is_ascii_digit())
+        }
+    }
+}
+
+/// Whether the blob looks like base64, ie: it's long and mixes lowercase and uppercase letters
+/// with digits, switching between them as often as random data does, so long identifiers and
+/// paths, eg: `src/Components/Header2/index`, aren't skipped.
+fn is_base64(blob: &str) -> bool {
+    let chars = blob
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<Vec<_>>();
+    let kind = |c: char| (c.is_ascii_lowercase(), c.is_ascii_uppercase());
+    // about 3 in 5 adjacent chars of random base64 are of different kinds, but few are in words
+    let switches = chars
+        .windows(2)
+        .filter(|pair| kind(pair[0]) != kind(pair[1]))
+        .count();
+    blob.trim_end_matches('=').len() >= MIN_BASE64_LEN
+        && chars.iter().any(char::is_ascii_lowercase)
+        && chars.iter().any(char::is_ascii_uppercase)
+        && chars.iter().any(char::is_ascii_digit)
+        && switches * 5 >= (chars.len() - 1) * 2
+}
+
+/// Bytes at the start of a buffer looked at to tell whether it's binary, as many as the codetypo
+/// CLI looks at.
+const BINARY_SAMPLE_LEN: usize = 1024;
+
+/// Whether the buffer looks like the content of a binary file, ie: it starts with a NUL char, like
+/// the codetypo CLI checks, or with many replacement chars, which is how editors decode invalid
+/// UTF-8.
+pub(crate) fn is_binary(buffer: &str) -> bool {
+    let mut end = buffer.len().min(BINARY_SAMPLE_LEN);
+    while !buffer.is_char_boundary(end) {
+        end -= 1;
+    }
+    let (mut chars, mut replacements) = (0, 0);
+    for c in buffer[..end].chars() {
+        match c {
+            '\0' => return true,
+            char::REPLACEMENT_CHARACTER => replacements += 1,
+            _ => {}
+        }
+        chars += 1;
+    }
+    // more than a tenth of the chars
+    replacements * 10 > chars
+}
+
+/// Decodes the content of a file read from disk, like editors do when opening it: UTF-16 is
+/// detected from its byte order mark, and a UTF-8 byte order mark is dropped.
+///
+/// Returns `None` if the content isn't valid in its encoding, eg: UTF-8 without a byte order mark.
+pub(crate) fn decode(bytes: &[u8]) -> Option<std::borrow::Cow<'_, str>> {
+    let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        let pairs = bytes.chunks_exact(2);
+        if !pairs.remainder().is_empty() {
+            return None;
+        }
+        let units = pairs.map(|pair| from_bytes([pair[0], pair[1]]));
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .ok()
+            .map(std::borrow::Cow::Owned)
+    };
+    match bytes {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => std::str::from_utf8(rest).ok().map(Into::into),
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        _ => std::str::from_utf8(bytes).ok().map(Into::into),
+    }
+}
+
+/// Parts of a buffer exempted from checking by comments.
+///
+/// A `codetypo:disable-line` comment suppresses its line, a `codetypo:disable-next-line` comment
+/// the line after it, and `codetypo:off` everything up to the next `codetypo:on`, or the end of
+/// the buffer. Directives must end at a word boundary, so `codetypo:offset` isn't `codetypo:off`.
+struct Suppressions {
+    lines: std::collections::HashSet<usize>,
+    blocks: Vec<std::ops::Range<usize>>,
+}
+
+impl Suppressions {
+    /// Finds the suppression comments in the buffer.
+    fn new(buffer: &str) -> Self {
+        let mut lines = std::collections::HashSet::new();
+        for (line_num, line) in self::lines(buffer).enumerate() {
+            if find_directive(line, "codetypo:disable-line").is_some() {
+                lines.insert(line_num);
+            }
+            if find_directive(line, "codetypo:disable-next-line").is_some() {
+                lines.insert(line_num + 1);
+            }
+        }
+
+        let mut blocks = Vec::new();
+        let mut offset = 0;
+        while let Some(off) = find_directive(&buffer[offset..], "codetypo:off") {
+            let start = offset + off + "codetypo:off".len();
+            let end = find_directive(&buffer[start..], "codetypo:on")
+                .map_or(buffer.len(), |on| start + on);
+            blocks.push(start..end);
+            offset = end;
+        }
+
+        Self { lines, blocks }
+    }
+
+    /// Returns true if the span, which is on the given line, is suppressed.
+    fn is_suppressed(&self, span: std::ops::Range<usize>, line_num: usize) -> bool {
+        self.lines.contains(&line_num)
+            || self
+                .blocks
+                .iter()
+                .any(|block| block.start <= span.start && span.end <= block.end)
+    }
+}
+
+/// Returns the offset of the first `directive` in `text` that isn't followed by more of a word,
+/// eg: `codetypo:on` but not `codetypo:once`.
+fn find_directive(text: &str, directive: &str) -> Option<usize> {
+    text.match_indices(directive)
+        .find(|(i, _)| {
+            !text[i + directive.len()..]
+                .starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '-')
+        })
+        .map(|(i, _)| i)
+}
+
+/// How the characters of LSP positions are counted, negotiated with the client.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PositionEncoding {
+    /// UTF-8 code units, ie: bytes.
+    Utf8,
+    /// UTF-16 code units, the default of LSP.
+    #[default]
+    Utf16,
+    /// UTF-32 code units, ie: chars.
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Returns the length of `text` in code units.
+    pub fn code_units(self, text: &str) -> usize {
+        match self {
+            PositionEncoding::Utf8 => text.len(),
+            PositionEncoding::Utf16 => text.chars().map(char::len_utf16).sum(),
+            PositionEncoding::Utf32 => text.chars().count(),
+        }
+    }
+
+    /// Returns the length of `c` in code units.
+    pub(crate) fn char_units(self, c: char) -> usize {
+        match self {
+            PositionEncoding::Utf8 => c.len_utf8(),
+            PositionEncoding::Utf16 => c.len_utf16(),
+            PositionEncoding::Utf32 => 1,
+        }
+    }
+}
+
+/// Returns the byte offsets of the starts of the lines of `buffer`, ie: 0 and the offset after each
+/// line break, which like in LSP is `\n`, `\r\n`, or a lone `\r`, so line numbers match the
+/// positions of clients whatever the newline style.
+pub(crate) fn line_starts(buffer: &str) -> impl Iterator<Item = usize> + '_ {
+    let bytes = buffer.as_bytes();
+    let breaks = bytes
+        .iter()
+        .enumerate()
+        .filter(move |&(i, &b)| b == b'\n' || (b == b'\r' && bytes.get(i + 1) != Some(&b'\n')))
+        .map(|(i, _)| i + 1);
+    std::iter::once(0).chain(breaks)
+}
+
+/// Returns the byte ranges of the lines of `buffer`, including their line breaks, see
+/// [`line_starts`]. Like [`str::lines`], there's no empty line after a final line break.
+pub(crate) fn line_ranges(buffer: &str) -> impl Iterator<Item = std::ops::Range<usize>> + '_ {
+    line_starts(buffer)
+        .chain(std::iter::once(buffer.len()))
+        .tuple_windows()
+        .filter(|(start, end)| start < end)
+        .map(|(start, end)| start..end)
+}
+
+/// Returns the lines of `buffer` without their line breaks, see [`line_starts`].
+pub(crate) fn lines(buffer: &str) -> impl Iterator<Item = &str> + '_ {
+    line_ranges(buffer).map(|range| buffer[range].trim_end_matches(['\n', '\r']))
 }
 
 // copied from https://github.com/khulnasoft/codetypo/blob/c15b28fff9a814f9c12bd24cb1cfc114037e9187/crates/codetypo-cli/src/file.rs#L741
@@ -116,48 +806,53 @@ impl Ignores {
     }
 }
 
-/// Tracks line and character positions for typo reporting.
-pub struct AccumulatePosition {
-    line_num: usize,
-    line_pos: usize,
-    last_offset: usize,
+/// Maps byte offsets in a buffer to LSP (line number, character position) positions.
+///
+/// Line starts are indexed once per buffer, and the last position is remembered so increasing
+/// offsets on the same line only decode the characters between them.
+pub struct LineIndex<'b> {
+    buffer: &'b str,
+    encoding: PositionEncoding,
+    line_starts: Vec<usize>,
+    // the last (byte offset, character position) returned
+    last: (usize, usize),
 }
 
-impl AccumulatePosition {
-    /// Constructs a new `AccumulatePosition`.
-    pub fn new() -> Self {
+impl<'b> LineIndex<'b> {
+    /// Constructs a new `LineIndex` for the buffer, counting code units of `encoding`, eg: UTF-16
+    /// like LSP does by default.
+    pub fn new(buffer: &'b str, encoding: PositionEncoding) -> Self {
+        let line_starts = line_starts(buffer).collect();
         Self {
-            // LSP ranges are 0-indexed see https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#range
-            line_num: 0,
-            line_pos: 0,
-            last_offset: 0,
+            buffer,
+            encoding,
+            line_starts,
+            last: (0, 0),
         }
     }
 
     /// Returns the (line number, character position) for a given byte offset in the buffer.
-    pub fn pos(&mut self, buffer: &[u8], byte_offset: usize) -> (usize, usize) {
-        assert!(self.last_offset <= byte_offset);
-        let slice = &buffer[self.last_offset..byte_offset];
-        let newlines = slice.find_iter(b"\n").count();
-        let line_num = self.line_num + newlines;
-
-        let line_start = buffer[0..byte_offset]
-            .rfind_byte(b'\n')
-            // Skip the newline
-            .map(|s| s + 1)
-            .unwrap_or(0);
+    pub fn pos(&mut self, byte_offset: usize) -> (usize, usize) {
+        // LSP ranges are 0-indexed see https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#range
+        let line_num = self
+            .line_starts
+            .partition_point(|&start| start <= byte_offset)
+            - 1;
+        let line_start = self.line_starts[line_num];
 
-        let before_typo = String::from_utf8_lossy(&buffer[line_start..byte_offset]);
+        // continue from the last position when it's earlier on the same line
+        let (from, line_pos) = match self.last {
+            (offset, line_pos) if line_start <= offset && offset <= byte_offset => {
+                (offset, line_pos)
+            }
+            _ => (line_start, 0),
+        };
 
-        // count UTF-16 code units as per
+        // count code units of the negotiated encoding as per
         // https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocuments
-        // UTF-16 is the only position encoding we support for now
-        let line_pos = before_typo.chars().map(char::len_utf16).sum();
-
-        self.line_num = line_num;
-        self.line_pos = line_pos;
-        self.last_offset = byte_offset;
+        let line_pos = line_pos + self.encoding.code_units(&self.buffer[from..byte_offset]);
 
-        (self.line_num, self.line_pos)
+        self.last = (byte_offset, line_pos);
+        (line_num, line_pos)
     }
 }
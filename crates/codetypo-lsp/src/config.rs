@@ -0,0 +1,228 @@
+//! Reading and editing of codetypo config files, used to explain where a correction comes from
+//! and by code actions that persist changes to the config.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use itertools::Itertools;
+use toml_edit::{DocumentMut, ImDocument, Item, Table, TableLike};
+use tower_lsp::lsp_types::{Position, Range};
+
+use crate::codetypo::PositionEncoding;
+
+/// Config file names that hold codetypo settings at the top level, in order of preference.
+///
+/// Unlike `SUPPORTED_FILE_NAMES` this excludes `Cargo.toml` and `pyproject.toml`, which nest
+/// their settings under a tool specific table.
+const PROJECT_FILE_NAMES: &[&str] = &["codetypo.toml", "_codetypo.toml", ".codetypo.toml"];
+
+/// Returns the config file in `dir` that project level changes should be written to.
+///
+/// This is the first existing config file, or `codetypo.toml` when there is none yet.
+pub(crate) fn project_config(dir: &Path) -> PathBuf {
+    PROJECT_FILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| dir.join(PROJECT_FILE_NAMES[0]))
+}
+
+/// Returns the project config files in the directories containing `path`, nearest first.
+pub(crate) fn project_configs(path: &Path) -> Vec<PathBuf> {
+    path.ancestors()
+        .skip(1)
+        .flat_map(|dir| PROJECT_FILE_NAMES.iter().map(move |name| dir.join(name)))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// A table in a config file that defines how a word is corrected, eg: `[default.extend-words]`.
+pub(crate) struct WordRule {
+    pub path: PathBuf,
+    pub table: String,
+    /// The range of the word's key in the config file.
+    pub range: Range,
+}
+
+/// The config files that words may be defined in, parsed once to look up many words.
+pub(crate) struct WordRules {
+    docs: Vec<(PathBuf, ImDocument<String>)>,
+    encoding: PositionEncoding,
+}
+
+impl WordRules {
+    /// Reads the config files at `paths`, in order of precedence, skipping those that can't be
+    /// read or parsed. The ranges of words are counted in code units of `encoding`.
+    pub(crate) fn load(paths: &[PathBuf], encoding: PositionEncoding) -> Self {
+        let docs = paths
+            .iter()
+            .filter_map(|path| {
+                let text = std::fs::read_to_string(path).ok()?;
+                Some((path.clone(), ImDocument::parse(text).ok()?))
+            })
+            .collect();
+        Self { docs, encoding }
+    }
+
+    /// Returns the first table defining `word` in the `extend-words` or `extend-identifiers` of
+    /// the config files.
+    ///
+    /// Words are matched case insensitively, like codetypo does.
+    pub(crate) fn find(&self, word: &str) -> Option<WordRule> {
+        self.docs.iter().find_map(|(path, doc)| {
+            let default = doc
+                .get("default")
+                .map(|table| ("default".to_string(), table));
+            let types = doc
+                .get("type")
+                .and_then(Item::as_table_like)
+                .into_iter()
+                .flat_map(|types| types.iter())
+                .map(|(name, table)| (format!("type.{}", name), table));
+
+            let rule = default.into_iter().chain(types).find_map(|(name, table)| {
+                ["extend-words", "extend-identifiers"]
+                    .into_iter()
+                    .find_map(|field| {
+                        let words = table.get(field)?.as_table_like()?;
+                        let (key, _) = words
+                            .iter()
+                            .find(|(key, _)| key.eq_ignore_ascii_case(word))?;
+                        let (key, _) = words.get_key_value(key)?;
+                        let span = key.span().unwrap_or_default();
+                        let mut index = crate::codetypo::LineIndex::new(doc.raw(), self.encoding);
+                        let (start, end) = (index.pos(span.start), index.pos(span.end));
+                        Some(WordRule {
+                            path: path.clone(),
+                            table: format!("{}.{}", name, field),
+                            range: Range::new(
+                                Position::new(start.0 as u32, start.1 as u32),
+                                Position::new(end.0 as u32, end.1 as u32),
+                            ),
+                        })
+                    })
+            });
+            rule
+        })
+    }
+}
+
+/// Adds `word` to `[default.extend-words]` in the config file at `path` so it's accepted as valid.
+///
+/// The file is created if it doesn't exist, and existing formatting and comments are preserved.
+pub(crate) fn add_word(path: &Path, word: &str) -> anyhow::Result<(), anyhow::Error> {
+    update(path, |doc| {
+        table_mut(doc, &["default", "extend-words"])?.insert(word, toml_edit::value(word));
+        Ok(())
+    })
+}
+
+/// Adds `word` to `[default.extend-words]` in the config file at `path` so it's always corrected
+/// to `correction`, rather than offering a choice of corrections.
+pub(crate) fn add_correction(
+    path: &Path,
+    word: &str,
+    correction: &str,
+) -> anyhow::Result<(), anyhow::Error> {
+    update(path, |doc| {
+        table_mut(doc, &["default", "extend-words"])?.insert(word, toml_edit::value(correction));
+        Ok(())
+    })
+}
+
+/// The file in a workspace folder with the words accepted in single files of the folder, eg:
+/// `[file."src/main.rs".extend-words]`.
+///
+/// codetypo matches file types on the file name, so its config can't scope a word to one file, and
+/// this file is read by the server rather than codetypo.
+pub(crate) const FILE_CONFIG: &str = "codetypo-lsp.toml";
+
+/// Adds `word` to `[file."<file>".extend-words]` in the file config at `path`, so it's accepted in
+/// `file` but still flagged everywhere else.
+///
+/// `file` is the path of the file relative to the directory of the file config.
+pub(crate) fn ignore_word_in_file(
+    path: &Path,
+    file: &Path,
+    word: &str,
+) -> anyhow::Result<(), anyhow::Error> {
+    // with `/` separators, so the file config can be shared across platforms
+    let file = file
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .join("/");
+    update(path, |doc| {
+        table_mut(doc, &["file", &file, "extend-words"])?.insert(word, toml_edit::value(word));
+        Ok(())
+    })
+}
+
+/// Reads the lowercase words accepted in single files from the file config at `path`, by the path
+/// of each file. See [`FILE_CONFIG`].
+pub(crate) fn file_words(
+    path: &Path,
+) -> anyhow::Result<HashMap<PathBuf, HashSet<String>>, anyhow::Error> {
+    let dir = path.parent().unwrap_or(path);
+    let doc = std::fs::read_to_string(path)?.parse::<ImDocument<String>>()?;
+    let Some(files) = doc.get("file") else {
+        return Ok(HashMap::new());
+    };
+    let files = files
+        .as_table_like()
+        .ok_or_else(|| anyhow!("`file` is not a table"))?;
+    files
+        .iter()
+        .map(|(file, table)| {
+            let words = table
+                .get("extend-words")
+                .and_then(Item::as_table_like)
+                .ok_or_else(|| anyhow!("`file.\"{}\".extend-words` is not a table", file))?
+                .iter()
+                .map(|(word, _)| word.to_lowercase())
+                .collect();
+            Ok((dir.join(file), words))
+        })
+        .collect()
+}
+
+/// Reads the config file at `path`, applies `f` and writes it back.
+fn update(
+    path: &Path,
+    f: impl FnOnce(&mut DocumentMut) -> anyhow::Result<(), anyhow::Error>,
+) -> anyhow::Result<(), anyhow::Error> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut doc = content.parse::<DocumentMut>()?;
+    f(&mut doc)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, doc.to_string())?;
+    tracing::debug!("Updated config file {}", path.display());
+    Ok(())
+}
+
+/// Returns the table at `keys`, creating any missing tables along the way.
+fn table_mut<'d>(
+    doc: &'d mut DocumentMut,
+    keys: &[&str],
+) -> anyhow::Result<&'d mut dyn TableLike, anyhow::Error> {
+    let mut table: &mut dyn TableLike = doc.as_table_mut();
+    for key in keys {
+        table = table
+            .entry(key)
+            .or_insert_with(|| {
+                // implicit so only the innermost table gets a header, eg: [default.extend-words]
+                let mut table = Table::new();
+                table.set_implicit(true);
+                Item::Table(table)
+            })
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("`{}` is not a table", key))?;
+    }
+    Ok(table)
+}
@@ -0,0 +1,74 @@
+//! Reading of cspell dictionaries, so dictionaries curated for the Code Spell Checker extension
+//! can be reused as word lists.
+
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::anyhow;
+use serde_json::Value;
+
+/// Returns the words of the cspell dictionary at `path`.
+///
+/// This is either a word list, optionally gzipped, or a manifest, eg: the `cspell-ext.json` of a
+/// dictionary package, whose `dictionaryDefinitions` paths are read relative to it.
+pub(crate) fn words(path: &Path) -> anyhow::Result<Vec<String>, anyhow::Error> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        return manifest_words(path);
+    }
+
+    let mut text = String::new();
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        flate2::read::GzDecoder::new(std::fs::File::open(path)?).read_to_string(&mut text)?;
+    } else {
+        text = std::fs::read_to_string(path)?;
+    }
+    Ok(parse_words(&text).collect())
+}
+
+/// Returns the words of the dictionaries defined by a manifest.
+fn manifest_words(path: &Path) -> anyhow::Result<Vec<String>, anyhow::Error> {
+    let manifest: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow!("Cannot get the directory of {}", path.display()))?;
+
+    let mut list = Vec::new();
+    let definitions = manifest
+        .get("dictionaryDefinitions")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten();
+    for definition in definitions {
+        let Some(dictionary) = definition.get("path").and_then(Value::as_str) else {
+            continue;
+        };
+        // compiled dictionaries are in a format of their own
+        if dictionary.ends_with(".trie") || dictionary.ends_with(".trie.gz") {
+            tracing::warn!("Skipping unsupported cspell dictionary {}", dictionary);
+            continue;
+        }
+        match words(&dir.join(dictionary)) {
+            Ok(dictionary_words) => list.extend(dictionary_words),
+            Err(e) => tracing::warn!("Cannot read cspell dictionary {}: {}", dictionary, e),
+        }
+    }
+    Ok(list)
+}
+
+/// Returns the words of a cspell word list, skipping comments and forbidden words, ie: those
+/// prefixed with `!`, and removing the markers of case sensitivity and compounding.
+fn parse_words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(str::split_whitespace)
+        .filter(|word| !word.starts_with('!'))
+        .map(|word| {
+            word.trim_start_matches(['~', '*', '+'])
+                .trim_end_matches(['*', '+'])
+        })
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+}
@@ -0,0 +1,42 @@
+//! Ranking of corrections by how common they are in English, so the most likely fix is offered
+//! first rather than the first in dictionary order.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+
+/// Common English words, most common first, one per line, see the comments at the top for where
+/// they come from.
+const WORDS: &str = include_str!("frequency.txt");
+
+/// The rank of each word in [`WORDS`].
+static RANKS: Lazy<HashMap<&'static str, usize>> = Lazy::new(|| {
+    WORDS
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .enumerate()
+        .map(|(rank, word)| (word, rank))
+        .collect()
+});
+
+/// Returns the rank of a word, lower being more common, or `None` if it isn't a common word.
+fn rank(word: &str) -> Option<usize> {
+    RANKS.get(word.to_lowercase().as_str()).copied()
+}
+
+/// Orders corrections from the most to the least common, keeping the dictionary order of those
+/// that aren't common words, after the others.
+pub(crate) fn sort(corrections: &mut [Cow<'_, str>]) {
+    corrections.sort_by_key(|correction| rank(correction).unwrap_or(usize::MAX));
+}
+
+/// Whether the first of the sorted corrections is the most likely fix, ie: it's the only one, or
+/// it's a common word so more common than the others.
+pub(crate) fn is_most_likely(corrections: &[impl AsRef<str>]) -> bool {
+    match corrections {
+        [_] => true,
+        [first, ..] => rank(first.as_ref()).is_some(),
+        [] => false,
+    }
+}
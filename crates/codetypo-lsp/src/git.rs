@@ -0,0 +1,116 @@
+//! Lines changed since a git ref, so only the typos a contributor introduced are reported.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use similar::{Algorithm, DiffTag};
+
+/// The zero-based lines of a document changed since a git ref.
+#[derive(Debug)]
+pub(crate) struct ChangedLines(Vec<Range<usize>>);
+
+impl ChangedLines {
+    pub(crate) fn contains(&self, line: usize) -> bool {
+        self.0.iter().any(|lines| lines.contains(&line))
+    }
+}
+
+/// The text of a file at a git ref, or `None` when the file isn't in a git repository or the ref
+/// isn't a commit.
+type BaseText = Option<Arc<String>>;
+
+/// The text of files at git refs, read once rather than on every check, until they're invalidated,
+/// eg: when a file is saved or the config is reloaded.
+#[derive(Default)]
+pub(crate) struct BaseTexts {
+    /// Texts by path and ref.
+    texts: Mutex<HashMap<(PathBuf, String), BaseText>>,
+}
+
+impl BaseTexts {
+    /// Returns the lines of `text`, the content of the document at `path`, that differ from the
+    /// file at the git ref `base`, eg: `HEAD` or `origin/main`, reading the file at `base` only if
+    /// it isn't cached.
+    ///
+    /// The text is compared rather than the file, so unsaved changes count. Files that don't exist
+    /// at `base` are new, so all of their lines have changed. Returns `None` when the file isn't in
+    /// a git repository or `base` isn't a commit.
+    ///
+    /// Runs git, so is called from blocking threads.
+    pub(crate) fn changed_lines(
+        &self,
+        path: &Path,
+        text: &str,
+        base: &str,
+    ) -> Option<ChangedLines> {
+        let key = (path.to_path_buf(), base.to_string());
+        let cached = self.texts.lock().unwrap().get(&key).cloned();
+        let base_text = match cached {
+            Some(base_text) => base_text,
+            None => {
+                let base_text = base_text(path, base).map(Arc::new);
+                self.texts.lock().unwrap().insert(key, base_text.clone());
+                base_text
+            }
+        };
+        Some(diff(&base_text?, text))
+    }
+
+    /// Forgets the texts of the file at `path`, eg: after it's saved, in case it was committed.
+    pub(crate) fn invalidate(&self, path: &Path) {
+        self.texts
+            .lock()
+            .unwrap()
+            .retain(|(other, _), _| other != path);
+    }
+
+    /// Forgets the texts of every file, eg: after the config is reloaded.
+    pub(crate) fn clear(&self) {
+        self.texts.lock().unwrap().clear();
+    }
+}
+
+/// Returns the text of the file at `path` at the git ref `base`, which is empty when the file
+/// doesn't exist at `base`, or `None` when the file isn't in a git repository or `base` isn't a
+/// commit.
+fn base_text(path: &Path, base: &str) -> Option<String> {
+    let dir = path.parent()?;
+    let name = path.file_name()?.to_str()?;
+    let git = |args: &[&str]| Command::new("git").args(args).current_dir(dir).output();
+
+    let commit = git(&[
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        &format!("{}^{{commit}}", base),
+    ]);
+    if !commit.is_ok_and(|output| output.status.success()) {
+        tracing::debug!("Cannot diff {} against {}", path.display(), base);
+        return None;
+    }
+    // paths starting with `./` are relative to the current directory rather than the repository
+    match git(&["show", &format!("{}:./{}", base, name)]) {
+        Ok(output) if output.status.success() => {
+            Some(String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+        _ => Some(String::new()),
+    }
+}
+
+/// Returns the lines of `text` that differ from `base_text`.
+fn diff(base_text: &str, text: &str) -> ChangedLines {
+    // lines are compared without their line endings, which git may have converted
+    let old = crate::codetypo::lines(base_text).collect::<Vec<_>>();
+    let new = crate::codetypo::lines(text).collect::<Vec<_>>();
+    ChangedLines(
+        similar::capture_diff_slices(Algorithm::Myers, &old, &new)
+            .iter()
+            .filter(|op| op.tag() != DiffTag::Equal)
+            .map(|op| op.new_range())
+            .filter(|lines| !lines.is_empty())
+            .collect(),
+    )
+}
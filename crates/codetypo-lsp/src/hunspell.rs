@@ -0,0 +1,268 @@
+//! Reading of Hunspell dictionaries, whose words are accepted like the words of a word list.
+//!
+//! Only what's needed to list the words is supported: the words of the `.dic` file and the forms
+//! generated by the prefix and suffix rules of the `.aff` file next to it. Compounding rules are
+//! ignored.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use regex::Regex;
+
+/// How affix flags are encoded, set by the `FLAG` directive of the affix file.
+#[derive(Debug, Default, Clone, Copy)]
+enum FlagType {
+    /// Each char is a flag.
+    #[default]
+    Char,
+    /// Each pair of chars is a flag.
+    Long,
+    /// Flags are comma separated numbers.
+    Num,
+}
+
+impl FlagType {
+    fn split(self, flags: &str) -> Vec<String> {
+        match self {
+            FlagType::Char => flags.chars().map(String::from).collect(),
+            FlagType::Long => flags
+                .chars()
+                .collect::<Vec<_>>()
+                .chunks(2)
+                .map(|flag| flag.iter().collect())
+                .collect(),
+            FlagType::Num => flags
+                .split(',')
+                .map(str::trim)
+                .filter(|flag| !flag.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+/// A prefix or suffix rule of the affix file.
+#[derive(Debug)]
+struct Affix {
+    prefix: bool,
+    /// Whether the rule combines with rules of the other kind, eg: a prefix with a suffix.
+    cross_product: bool,
+    strip: String,
+    add: String,
+    condition: Regex,
+}
+
+impl Affix {
+    /// Applies the rule to `word`, returning `None` when the word doesn't meet its condition.
+    fn apply(&self, word: &str) -> Option<String> {
+        if !self.condition.is_match(word) {
+            return None;
+        }
+        if self.prefix {
+            let stem = word.strip_prefix(self.strip.as_str())?;
+            Some(format!("{}{}", self.add, stem))
+        } else {
+            let stem = word.strip_suffix(self.strip.as_str())?;
+            Some(format!("{}{}", stem, self.add))
+        }
+    }
+}
+
+/// The rules of an affix file.
+#[derive(Debug, Default)]
+struct Affixes {
+    flag_type: FlagType,
+    /// Flag of words that are forbidden, so aren't accepted.
+    forbidden: Option<String>,
+    /// Flag of words that are only accepted with an affix.
+    need_affix: Option<String>,
+    /// Flag sets that words can refer to by their number, starting at 1.
+    aliases: Vec<String>,
+    cross_products: HashMap<String, bool>,
+    rules: HashMap<String, Vec<Affix>>,
+}
+
+impl Affixes {
+    fn parse(text: &str) -> Affixes {
+        let mut affixes = Affixes::default();
+        let mut alias_count = None;
+        for line in text.lines() {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            match fields.as_slice() {
+                ["FLAG", flag_type, ..] => {
+                    affixes.flag_type = match *flag_type {
+                        "long" => FlagType::Long,
+                        "num" => FlagType::Num,
+                        _ => FlagType::Char,
+                    }
+                }
+                ["FORBIDDENWORD", flag, ..] => affixes.forbidden = Some(flag.to_string()),
+                ["NEEDAFFIX", flag, ..] => affixes.need_affix = Some(flag.to_string()),
+                // the first line is the number of aliases
+                ["AF", flags, ..] => match alias_count {
+                    None => alias_count = flags.parse::<usize>().ok(),
+                    Some(_) => affixes.aliases.push(flags.to_string()),
+                },
+                // the header of a rule's lines
+                ["PFX" | "SFX", flag, cross_product @ ("Y" | "N"), count]
+                    if count.parse::<usize>().is_ok() =>
+                {
+                    affixes
+                        .cross_products
+                        .insert(flag.to_string(), *cross_product == "Y");
+                }
+                [kind @ ("PFX" | "SFX"), flag, strip, add, rest @ ..] => {
+                    let prefix = *kind == "PFX";
+                    let condition = rest.first().copied().unwrap_or(".");
+                    let Some(condition) = condition_regex(condition, prefix) else {
+                        tracing::debug!("Skipping affix rule with invalid condition: {}", line);
+                        continue;
+                    };
+                    let rule = Affix {
+                        prefix,
+                        cross_product: affixes
+                            .cross_products
+                            .get(*flag)
+                            .copied()
+                            .unwrap_or_default(),
+                        strip: none_if_zero(strip).to_string(),
+                        // continuation flags after the `/` are ignored
+                        add: none_if_zero(add.split('/').next().unwrap_or_default()).to_string(),
+                        condition,
+                    };
+                    affixes
+                        .rules
+                        .entry(flag.to_string())
+                        .or_default()
+                        .push(rule);
+                }
+                _ => {}
+            }
+        }
+        affixes
+    }
+
+    /// Returns the flags of a word, resolving aliases.
+    fn flags(&self, flags: &str) -> Vec<String> {
+        if !self.aliases.is_empty() {
+            if let Some(alias) = flags
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| self.aliases.get(n.checked_sub(1)?))
+            {
+                return self.flag_type.split(alias);
+            }
+        }
+        self.flag_type.split(flags)
+    }
+
+    /// Adds the forms of `word` generated by the rules of its `flags` to `words`.
+    fn expand(&self, word: &str, flags: &[String], words: &mut Vec<String>) {
+        let rules = || {
+            flags
+                .iter()
+                .filter_map(|flag| self.rules.get(flag))
+                .flatten()
+        };
+
+        let mut suffixed = Vec::new();
+        for rule in rules().filter(|rule| !rule.prefix) {
+            if let Some(form) = rule.apply(word) {
+                if rule.cross_product {
+                    suffixed.push(form.clone());
+                }
+                words.push(form);
+            }
+        }
+        for rule in rules().filter(|rule| rule.prefix) {
+            words.extend(rule.apply(word));
+            if rule.cross_product {
+                words.extend(suffixed.iter().filter_map(|form| rule.apply(form)));
+            }
+        }
+    }
+}
+
+/// Returns `""` for the `0` that affix rules use for nothing.
+fn none_if_zero(value: &str) -> &str {
+    if value == "0" {
+        ""
+    } else {
+        value
+    }
+}
+
+/// Converts the condition of an affix rule, eg: `[^aeiou]y`, to a regex matching the start of
+/// words for prefixes and the end of words for suffixes.
+fn condition_regex(condition: &str, prefix: bool) -> Option<Regex> {
+    let mut pattern = String::new();
+    let mut in_class = false;
+    for c in condition.chars() {
+        match c {
+            '[' if !in_class => {
+                in_class = true;
+                pattern.push('[');
+            }
+            ']' if in_class => {
+                in_class = false;
+                pattern.push(']');
+            }
+            '^' if in_class && pattern.ends_with('[') => pattern.push('^'),
+            '.' if !in_class => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    let pattern = if prefix {
+        format!("^{}", pattern)
+    } else {
+        format!("{}$", pattern)
+    };
+    Regex::new(&pattern).ok()
+}
+
+/// Splits a dictionary entry into its word and flags, at the first `/` that isn't escaped.
+fn split_entry(entry: &str) -> (String, &str) {
+    let mut escaped = false;
+    for (i, c) in entry.char_indices() {
+        match c {
+            '\\' => escaped = !escaped,
+            '/' if !escaped => return (entry[..i].replace("\\/", "/"), &entry[i + 1..]),
+            _ => escaped = false,
+        }
+    }
+    (entry.replace("\\/", "/"), "")
+}
+
+/// Returns the words of the Hunspell dictionary at `dic`, including the forms generated by the
+/// affix file with the same name, eg: `en_US.aff` for `en_US.dic`, if there is one.
+///
+/// Files that aren't UTF-8 are read lossily, so words with other chars won't match.
+pub(crate) fn words(dic: &Path) -> anyhow::Result<Vec<String>, anyhow::Error> {
+    let aff = dic.with_extension("aff");
+    let affixes = if aff.is_file() {
+        Affixes::parse(&String::from_utf8_lossy(&std::fs::read(&aff)?))
+    } else {
+        Affixes::default()
+    };
+
+    let text = String::from_utf8_lossy(&std::fs::read(dic)?).into_owned();
+    let mut words = Vec::new();
+    // the first line is the number of words
+    for line in text.lines().skip(1) {
+        // morphological fields follow the entry after whitespace
+        let Some(entry) = line.split_whitespace().next() else {
+            continue;
+        };
+        let (word, flags) = split_entry(entry);
+        let flags = affixes.flags(flags);
+        let has_flag = |flag: &Option<String>| flag.as_ref().is_some_and(|f| flags.contains(f));
+        if has_flag(&affixes.forbidden) {
+            continue;
+        }
+        if !has_flag(&affixes.need_affix) {
+            words.push(word.clone());
+        }
+        affixes.expand(&word, &flags, &mut words);
+    }
+    Ok(words)
+}
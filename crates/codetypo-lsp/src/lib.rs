@@ -4,8 +4,24 @@
 //!
 //! This crate provides the backend for the Codetypo LSP server, enabling spell checking in editors via LSP.
 
+/// The checking pipeline of the server as a library, for tools that embed it without speaking LSP.
+#[cfg(feature = "api")]
+pub mod api;
+/// Headless checking of files, for the `check` subcommand.
+pub mod check;
 mod codetypo;
+mod config;
+mod cspell;
+mod frequency;
+mod git;
+mod hunspell;
+/// Log files that are rotated, for the `--log-file` flag.
+pub mod log_file;
 /// LSP server implementation and API for Codetypo.
 pub mod lsp;
 mod state;
+mod suggest;
+mod syntax;
+/// An in-process harness for integration tests of the server.
+pub mod testing;
 mod windows;
@@ -0,0 +1,136 @@
+//! Log files for the `--log-file` flag, rotated when they get too large or a day passes, so the
+//! logs of a long running server don't fill the disk.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// When a log file is rotated.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Rotation {
+    /// Never, the file grows without limit.
+    #[default]
+    Never,
+    /// When the first line of a new day (UTC) is logged, renaming the file after the day of its
+    /// logs, eg: `codetypo.log.2024-05-31`.
+    Daily,
+    /// When the file would grow past the max size, renaming it with a `.1` extension, eg:
+    /// `codetypo.log.1`, which replaces the previously rotated file.
+    Size,
+}
+
+/// A log file that's appended to, and rotated according to its [`Rotation`].
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    max_size: u64,
+    file: File,
+    /// Bytes in the file.
+    size: u64,
+    /// Days since the Unix epoch of the logs in the file.
+    day: u64,
+}
+
+impl RotatingFile {
+    /// Opens the log file at `path` for appending, creating it if it doesn't exist.
+    ///
+    /// `max_size` is the size in bytes files are rotated at, with [`Rotation::Size`].
+    pub fn open(path: &Path, rotation: Rotation, max_size: u64) -> io::Result<RotatingFile> {
+        let file = append(path)?;
+        let metadata = file.metadata()?;
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            rotation,
+            max_size,
+            file,
+            size: metadata.len(),
+            // so the logs of a previous day are rotated before today's are appended to them
+            day: metadata.modified().map_or_else(|_| today(), day),
+        })
+    }
+
+    /// Whether the file must be rotated before `len` more bytes are logged.
+    fn rotation_due(&self, len: usize) -> bool {
+        match self.rotation {
+            Rotation::Never => false,
+            Rotation::Daily => today() != self.day,
+            Rotation::Size => self.size > 0 && self.size + len as u64 > self.max_size,
+        }
+    }
+
+    /// Renames the file, then starts a new one at its path.
+    fn rotate(&mut self) -> io::Result<()> {
+        let mut rotated = self.path.clone().into_os_string();
+        match self.rotation {
+            Rotation::Daily => {
+                let (year, month, day) = date(self.day);
+                rotated.push(format!(".{:04}-{:02}-{:02}", year, month, day));
+            }
+            _ => rotated.push(".1"),
+        }
+        std::fs::rename(&self.path, rotated)?;
+        self.file = append(&self.path)?;
+        self.size = 0;
+        self.day = today();
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.rotation_due(buf.len()) {
+            // keep logging to the current file rather than losing the logs
+            if let Err(e) = self.rotate() {
+                eprintln!("Cannot rotate log file {}: {}", self.path.display(), e);
+            }
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Opens a file for appending, creating it if it doesn't exist.
+fn append(path: &Path) -> io::Result<File> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+}
+
+/// Returns the days since the Unix epoch of a time.
+fn day(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / (24 * 60 * 60)
+}
+
+/// Returns the days since the Unix epoch of now.
+fn today() -> u64 {
+    day(SystemTime::now())
+}
+
+/// Returns the year, month, and day of the month of a number of days since the Unix epoch.
+///
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn date(days: u64) -> (u64, u64, u64) {
+    // days since 0000-03-01, so leap days are at the end of years
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // months start at March
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    (year, month, day)
+}
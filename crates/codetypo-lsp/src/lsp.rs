@@ -1,39 +1,727 @@
 //! LSP server implementation using tower-lsp for Codetypo.
 
-use matchit::Match;
-
 use std::borrow::Cow;
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::Mutex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use codetypo_cli::policy;
+use rayon::prelude::*;
 use serde_json::{json, to_string};
 use tower_lsp::lsp_types::*;
 use tower_lsp::*;
 use tower_lsp::{Client, LanguageServer};
 
-use crate::state::{url_path_sanitised, BackendState};
+use crate::codetypo::{CheckedBuffer, Filters, Instance, PositionEncoding};
+use crate::config::WordRules;
+use crate::state::{
+    canonicalize, BackendState, LanguageSettings, PublishedDiagnostics, ServerState,
+};
+use crate::syntax::Syntax;
 /// LSP backend for Codetypo, managing client and workspace state.
-pub struct Backend<'s, 'p> {
+pub struct Backend<'p> {
     client: Client,
-    state: Mutex<crate::state::BackendState<'s>>,
+    state: Arc<crate::state::BackendState>,
     default_policy: policy::Policy<'p, 'p, 'p>,
+    /// When the server started, reported by `codetypo/ping`.
+    started: std::time::Instant,
+}
+
+/// The parts of a policy used for checking text: tokenizer, dictionary, and ignore patterns.
+type PolicyRefs<'a> = (
+    &'a codetypo::tokens::Tokenizer,
+    &'a dyn codetypo::Dictionary,
+    Cow<'a, [regex::Regex]>,
+);
+
+/// What's needed to check a document, gathered from the settings and open documents so the
+/// check itself can run on another thread.
+pub(crate) struct CheckRequest {
+    uri: Url,
+    syntax: Option<&'static Syntax>,
+    commit_message: bool,
+    language: Option<LanguageSettings>,
+    filters: Filters,
+    diff_base: Option<String>,
+    max_file_size: usize,
+    encoding: PositionEncoding,
+}
+
+impl CheckRequest {
+    /// Returns what's needed to check the document at `uri`, or `None` if it's never checked.
+    pub(crate) fn new(state: &BackendState, uri: &Url) -> Option<CheckRequest> {
+        if state.is_disabled(uri) {
+            return None;
+        }
+        let (filters, diff_base, max_file_size, encoding) = {
+            let settings = state.settings.read().unwrap();
+            (
+                settings.filters(),
+                settings.diff_base.clone(),
+                settings.max_file_size,
+                settings.position_encoding,
+            )
+        };
+        Some(CheckRequest {
+            uri: uri.clone(),
+            syntax: state.syntax(uri),
+            commit_message: crate::syntax::is_commit_message(
+                state.language_id(uri).as_deref(),
+                uri.path_segments()
+                    .and_then(|mut segments| segments.next_back()),
+            ),
+            language: state.language_settings(uri),
+            filters,
+            diff_base,
+            max_file_size,
+            encoding,
+        })
+    }
+
+    /// Returns an informational diagnostic explaining why `buffer`, or some of its lines, weren't
+    /// checked, when they're over the `maxFileSize` or `maxLineLength` limits.
+    ///
+    /// Binary content is skipped silently, like the codetypo CLI does.
+    fn skipped(&self, buffer: &str) -> Option<Diagnostic> {
+        if crate::codetypo::is_binary(buffer) {
+            return None;
+        }
+        let (range, message) = if self.max_file_size > 0 && buffer.len() > self.max_file_size {
+            (
+                Range::default(),
+                format!(
+                    "Not checked for typos, as it's larger than {} bytes (`maxFileSize`)",
+                    self.max_file_size
+                ),
+            )
+        } else {
+            let mut long_lines = self.filters.long_lines(buffer);
+            let first = long_lines.next()?;
+            let count = 1 + long_lines.count();
+            let (line, _) = crate::codetypo::LineIndex::new(buffer, self.encoding).pos(first.start);
+            (
+                Range::new(Position::new(line as u32, 0), Position::new(line as u32, 0)),
+                format!(
+                    "{} {} longer than {} characters not checked for typos (`maxLineLength`)",
+                    count,
+                    if count == 1 { "line" } else { "lines" },
+                    self.filters.max_line_length
+                ),
+            )
+        };
+        Some(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            source: Some("codetypo".to_string()),
+            message,
+            ..Diagnostic::default()
+        })
+    }
+
+    /// Checks `buffer`, the text of the document, for typos and returns each typo with its range,
+    /// stopping early once `cancelled` is set.
+    pub(crate) fn run(
+        &self,
+        state: &BackendState,
+        buffer: &str,
+        cancelled: &AtomicBool,
+    ) -> Vec<(Range, codetypo::Typo<'static>)> {
+        let uri = &self.uri;
+        if self.max_file_size > 0 && buffer.len() > self.max_file_size {
+            return Vec::default();
+        }
+        if crate::codetypo::is_binary(buffer) {
+            tracing::debug!("Skipping {}, which looks binary", uri);
+            return Vec::default();
+        }
+        // read before the instance, so typos found with an outdated instance are never reused
+        let generation = state.generation.load(Ordering::Relaxed);
+        // only hold the lock while looking up the instance, not while checking
+        let policy_uri = state.policy_uri(uri);
+        let path = state.file_path(&policy_uri).map(|path| canonicalize(&path));
+        let instance = path
+            .as_deref()
+            .and_then(|path| state.workspace.read().unwrap().instance_at(path));
+
+        let default_policy = policy::Policy::default();
+        let Some((tokenizer, dict, ignore)) = Backend::workspace_policy(
+            path.as_deref(),
+            instance.as_deref(),
+            self.language.as_ref(),
+            (
+                default_policy.tokenizer,
+                default_policy.dict,
+                Cow::Borrowed(default_policy.ignore),
+            ),
+        ) else {
+            // skip file because it matches extend-exclude
+            return Vec::default();
+        };
+        let filters = self.filters;
+
+        let ranges = match self.syntax {
+            _ if self.commit_message => crate::syntax::commit_message(buffer),
+            Some(syntax) => syntax.extract(buffer),
+            None => std::iter::once(0..buffer.len()).collect(),
+        };
+        // typos on lines that haven't changed since the diff base aren't reported
+        let changed_lines = self.diff_base.as_ref().and_then(|base| {
+            state
+                .base_texts
+                .changed_lines(path.as_deref()?, buffer, base)
+        });
+
+        // when the whole of an open document is checked, only the lines changed since its
+        // last check are checked again
+        let incremental = matches!(ranges.as_slice(), [range] if *range == (0..buffer.len()))
+            && state.documents.lock().unwrap().contains_key(uri);
+        let previous = incremental
+            .then(|| state.checked.lock().unwrap().get(uri).cloned())
+            .flatten()
+            .filter(|previous| previous.generation == generation);
+        let typos = match previous {
+            Some(previous) => {
+                let (mut typos, changed) = previous.unchanged(buffer);
+                typos.extend(
+                    crate::codetypo::find_typos(buffer, vec![changed], tokenizer, dict, filters)
+                        .map(codetypo::Typo::into_owned),
+                );
+                typos.sort_by_key(|typo| typo.byte_offset);
+                typos
+            }
+            None => crate::codetypo::find_typos(buffer, ranges, tokenizer, dict, filters)
+                .take_while(|_| !cancelled.load(Ordering::Relaxed))
+                .map(codetypo::Typo::into_owned)
+                .collect(),
+        };
+        if incremental && !cancelled.load(Ordering::Relaxed) {
+            let checked = CheckedBuffer {
+                generation,
+                text: buffer.to_string(),
+                typos: typos.clone(),
+            };
+            state
+                .checked
+                .lock()
+                .unwrap()
+                .insert(uri.clone(), Arc::new(checked));
+        }
+
+        // filtered after the typos are kept for the next check, so they don't depend on which words
+        // are ignored
+        let session_ignored = state.session_ignored.read().unwrap().clone();
+        let typos = typos
+            .into_iter()
+            .filter(|typo| !session_ignored.contains(&typo.typo.to_lowercase()))
+            .filter(|typo| {
+                !path
+                    .as_deref()
+                    .zip(instance.as_deref())
+                    .is_some_and(|(path, instance)| instance.is_ignored_in_file(path, &typo.typo))
+            });
+
+        crate::codetypo::report(buffer, typos, &ignore, self.encoding)
+            .take_while(|_| !cancelled.load(Ordering::Relaxed))
+            .filter(|(_, line_num, _)| {
+                changed_lines
+                    .as_ref()
+                    .is_none_or(|lines| lines.contains(*line_num))
+            })
+            .map(|(typo, line_num, line_pos)| {
+                let range = Range::new(
+                    Position::new(line_num as u32, line_pos as u32),
+                    Position::new(
+                        line_num as u32,
+                        (line_pos + self.encoding.code_units(&typo.typo)) as u32,
+                    ),
+                );
+                (range, typo.into_owned())
+            })
+            .collect()
+    }
+}
+
+/// A file checked by [`Backend::check_files`], without typos if it couldn't be read.
+struct CheckedFile {
+    uri: Url,
+    typos: Option<Vec<(Range, codetypo::Typo<'static>)>>,
+    /// Why the file, or some of its lines, weren't checked.
+    skipped: Option<Diagnostic>,
+}
+
+/// Files at least this large are memory mapped when they're checked without being open.
+const MMAP_THRESHOLD: u64 = 1024 * 1024;
+
+/// The text of a file that isn't open, mapped rather than read when it's large, so scanning big
+/// files doesn't copy them onto the heap.
+///
+/// Files are decoded like [`crate::codetypo::decode`] does, so UTF-16 files are read, not mapped.
+enum FileText {
+    Read(String),
+    Mapped(memmap2::Mmap),
+}
+
+impl FileText {
+    /// Reads or maps the file at `path`, returning `None` if it can't be read or decoded.
+    fn open(path: &std::path::Path) -> Option<FileText> {
+        let mut file = std::fs::File::open(path).ok()?;
+        if file.metadata().ok()?.len() < MMAP_THRESHOLD {
+            let mut bytes = Vec::new();
+            std::io::Read::read_to_end(&mut file, &mut bytes).ok()?;
+            return match String::from_utf8(bytes) {
+                Ok(text) => Some(FileText::Read(text)),
+                Err(e) => crate::codetypo::decode(e.as_bytes())
+                    .map(|text| FileText::Read(text.into_owned())),
+            };
+        }
+        // SAFETY: the map is only read, but a file modified while it's checked may be seen
+        // partially modified, which like other tools that map files, eg: ripgrep, is accepted for
+        // the memory saved on large files
+        let map = match unsafe { memmap2::Mmap::map(&file) } {
+            Ok(map) => map,
+            Err(e) => {
+                tracing::debug!("Cannot map {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        match crate::codetypo::decode(&map)? {
+            Cow::Borrowed(_) => Some(FileText::Mapped(map)),
+            Cow::Owned(text) => Some(FileText::Read(text)),
+        }
+    }
+
+    /// Returns the text, without a UTF-8 byte order mark, or `None` if it isn't valid UTF-8.
+    fn as_str(&self) -> Option<&str> {
+        let text = match self {
+            FileText::Read(text) => text,
+            FileText::Mapped(map) => std::str::from_utf8(map).ok()?,
+        };
+        Some(text.strip_prefix('\u{feff}').unwrap_or(text))
+    }
+}
+
+/// Receives the files checked by [`Backend::check_files`], in the order they were given although
+/// they're checked in parallel, so results are deterministic.
+struct CheckedFiles {
+    receiver: tokio::sync::mpsc::UnboundedReceiver<(usize, CheckedFile)>,
+    /// Files checked before the ones preceding them.
+    pending: BTreeMap<usize, CheckedFile>,
+    next: usize,
+}
+
+impl CheckedFiles {
+    /// Returns the next file once it's checked, or `None` when all files have been received.
+    async fn next(&mut self) -> Option<CheckedFile> {
+        loop {
+            if let Some(file) = self.pending.remove(&self.next) {
+                self.next += 1;
+                return Some(file);
+            }
+            let (i, file) = self.receiver.recv().await?;
+            self.pending.insert(i, file);
+        }
+    }
 }
 
 /// Diagnostic data attached to LSP diagnostics, including correction suggestions.
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 struct DiagnosticData<'c> {
+    typo: Cow<'c, str>,
     corrections: Vec<Cow<'c, str>>,
 }
 
+/// Code action data describing its edit, which is computed by `codeAction/resolve` for clients
+/// that support resolving edits, rather than sent with every code action.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum ResolveData {
+    /// Replaces the typo at `range` with `correction`.
+    Replace {
+        uri: Url,
+        range: Range,
+        correction: String,
+    },
+    /// Replaces every occurrence of `typo` in the document with `correction`.
+    ReplaceAll {
+        uri: Url,
+        typo: String,
+        correction: String,
+    },
+    /// Replaces every typo in the document with its first correction.
+    FixAll { uri: Url },
+}
+
+impl ResolveData {
+    /// Returns the edit, given the typos of the document.
+    fn edit(&self, document_typos: &[(Range, codetypo::Typo<'static>)]) -> WorkspaceEdit {
+        let (uri, edits) = match self {
+            ResolveData::Replace {
+                uri,
+                range,
+                correction,
+            } => (
+                uri,
+                vec![TextEdit {
+                    range: *range,
+                    new_text: correction.clone(),
+                }],
+            ),
+            ResolveData::ReplaceAll {
+                uri,
+                typo,
+                correction,
+            } => (
+                uri,
+                document_typos
+                    .iter()
+                    .filter(|(_, other)| other.typo == *typo)
+                    .map(|(range, _)| TextEdit {
+                        range: *range,
+                        new_text: correction.clone(),
+                    })
+                    .collect(),
+            ),
+            ResolveData::FixAll { uri } => (uri, fix_all(document_typos)),
+        };
+        WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..WorkspaceEdit::default()
+        }
+    }
+}
+
+/// `$/codetypo/status` notification sent to clients that opt in, eg: to render a status bar item.
+enum CodetypoStatus {}
+
+impl notification::Notification for CodetypoStatus {
+    type Params = CodetypoStatusParams;
+    const METHOD: &'static str = "$/codetypo/status";
+}
+
+/// Params of a [`CodetypoStatus`] notification.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CodetypoStatusParams {
+    /// Whether documents are being checked or the config is being reloaded.
+    busy: bool,
+    /// Whether the config is being reloaded.
+    reloading: bool,
+    /// Total number of typos in the open documents.
+    typos: usize,
+    /// Number of typos in each open document.
+    documents: HashMap<Url, usize>,
+}
+
+/// `$/codetypo/serverState` notification sent to clients that opt in when the server goes between
+/// idle, checking, and reloading the config, or the number of queued documents changes, eg: to
+/// show a spinner.
+enum CodetypoServerState {}
+
+impl notification::Notification for CodetypoServerState {
+    type Params = CodetypoServerStateParams;
+    const METHOD: &'static str = "$/codetypo/serverState";
+}
+
+/// Params of a [`CodetypoServerState`] notification.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CodetypoServerStateParams {
+    state: ServerState,
+    /// Number of open documents waiting to be checked or being checked.
+    queued: usize,
+}
+
+/// Work done progress of a long running operation, reported to the client when it supports it.
+///
+/// See https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workDoneProgress
+struct Progress<'c> {
+    client: &'c Client,
+    token: Option<ProgressToken>,
+    percentage: Option<u32>,
+}
+
+impl Progress<'_> {
+    /// Sends a `$/progress` notification, unless progress isn't being reported.
+    async fn notify(&self, progress: WorkDoneProgress) {
+        if let Some(token) = &self.token {
+            self.client
+                .send_notification::<notification::Progress>(ProgressParams {
+                    token: token.clone(),
+                    value: ProgressParamsValue::WorkDone(progress),
+                })
+                .await;
+        }
+    }
+
+    /// Reports that `done` of `total` items are done, when the percentage changes.
+    async fn report(&mut self, done: usize, total: usize) {
+        let percentage = (done * 100 / total.max(1)) as u32;
+        if self.percentage == Some(percentage) {
+            return;
+        }
+        self.percentage = Some(percentage);
+        self.notify(WorkDoneProgress::Report(WorkDoneProgressReport {
+            message: Some(format!("{}/{}", done, total)),
+            percentage: Some(percentage),
+            ..WorkDoneProgressReport::default()
+        }))
+        .await;
+    }
+
+    /// Reports that the operation has finished.
+    async fn end(self) {
+        self.notify(WorkDoneProgress::End(WorkDoneProgressEnd::default()))
+            .await;
+    }
+}
+
+/// Params of the `codetypo/effectiveConfig` request.
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EffectiveConfigParams {
+    text_document: TextDocumentIdentifier,
+}
+
+/// Result of the `codetypo/effectiveConfig` request, describing how a file is checked.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EffectiveConfig {
+    /// The config files that were loaded, in order of precedence from lowest to highest.
+    config_files: Vec<PathBuf>,
+    /// The word lists that were loaded.
+    word_lists: Vec<PathBuf>,
+    /// Whether the file matches `extend-exclude` and so isn't checked.
+    excluded: bool,
+    /// The file type the file matched, eg: `rust`.
+    file_type: Option<String>,
+    /// The `extend-ignore-re` patterns that apply to the file.
+    ignore_regexes: Vec<String>,
+    /// The config resulting from merging the config files.
+    config: codetypo_cli::config::Config,
+}
+
+/// Number of misspellings in the result of the `codetypo/summary` request, unless it sets `top`.
+const DEFAULT_SUMMARY_TOP: usize = 10;
+
+/// Params of the `codetypo/summary` request.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct SummaryParams {
+    /// Number of the most frequent misspellings to return, see [`DEFAULT_SUMMARY_TOP`].
+    top: Option<usize>,
+}
+
+/// Result of the `codetypo/summary` request, counting the typos in the workspace.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Summary {
+    /// Number of typos in all files.
+    total: usize,
+    /// The files with typos, sorted by URI.
+    files: Vec<FileSummary>,
+    /// The most frequent misspellings, most frequent first.
+    top_typos: Vec<TypoSummary>,
+}
+
+/// Number of typos in a file of the `codetypo/summary` result.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileSummary {
+    uri: Url,
+    typos: usize,
+}
+
+/// A misspelling of the `codetypo/summary` result, and how often it occurs.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TypoSummary {
+    typo: String,
+    count: usize,
+    /// Number of files it occurs in.
+    files: usize,
+}
+
+/// Result of the `codetypo/ping` request, describing the health of the server.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Ping {
+    version: &'static str,
+    uptime_secs: u64,
+    workspace_folders: usize,
+    /// Number of workspace folders that have a config instance routed to them.
+    routed_workspace_folders: usize,
+    /// The config files and word lists that were loaded.
+    config_files: Vec<PathBuf>,
+    open_documents: usize,
+    /// Memory used by the server, on platforms where it can be read.
+    memory: Option<MemoryStats>,
+}
+
+/// Memory used by the server process, in bytes.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MemoryStats {
+    resident: u64,
+    peak_resident: u64,
+}
+
+impl MemoryStats {
+    /// Reads the memory stats of the server process from `/proc/self/status`, which only exists
+    /// on Linux.
+    fn read() -> Option<MemoryStats> {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        // eg: `VmRSS:     1234 kB`
+        let field = |name: &str| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+                .and_then(|value| value.trim().strip_suffix("kB"))
+                .and_then(|kb| kb.trim().parse::<u64>().ok())
+                .map(|kb| kb * 1024)
+        };
+        Some(MemoryStats {
+            resident: field("VmRSS")?,
+            peak_resident: field("VmHWM")?,
+        })
+    }
+}
+
+/// Code action kind for fixing all typos in a document, eg: via `editor.codeActionsOnSave`.
+const SOURCE_FIX_ALL_CODETYPO: CodeActionKind = CodeActionKind::new("source.fixAll.codetypo");
+
+/// Semantic token type of typos, so editors can style them independently of diagnostics.
+const TYPO_TOKEN_TYPE: SemanticTokenType = SemanticTokenType::new("typo");
+
+/// Explains why words are flagged and how to allow them, linked from each diagnostic.
+const TYPO_HREF: &str = "https://github.com/khulnasoft/codetypo?tab=readme-ov-file#false-positives";
+
+/// Semantic token modifier of typos that are disallowed rather than having corrections.
+const DISALLOWED_TOKEN_MODIFIER: SemanticTokenModifier = SemanticTokenModifier::new("disallowed");
+
+/// Command that adds a word to the workspace folder's config file.
+const ADD_TO_PROJECT_DICTIONARY: &str = "codetypo.addToProjectDictionary";
+
+/// Command that makes the workspace folder's config file always correct a word to one correction.
+const ADD_CORRECTION: &str = "codetypo.addCorrection";
+
+/// Command that accepts a word in the current file only, via the workspace folder's file config.
+const IGNORE_WORD_IN_FILE: &str = "codetypo.ignoreWordInFile";
+
+/// Command that adds a word to the user dictionary shared by all workspaces.
+const ADD_TO_USER_DICTIONARY: &str = "codetypo.addToUserDictionary";
+
+/// Command that ignores a word until the server exits, without writing to any config file.
+const IGNORE_WORD_SESSION: &str = "codetypo.ignoreWordSession";
+
+/// Command that fixes typos with a single correction in every file in the workspace.
+const FIX_ALL: &str = "codetypo.fixAll";
+
+/// Command that replaces every occurrence of a typo with a correction in every file in the
+/// workspace.
+const REPLACE_IN_WORKSPACE: &str = "codetypo.replaceInWorkspace";
+
+/// Command that fixes every typo in an open document, eg: from its code lens.
+const FIX_FILE: &str = "codetypo.fixFile";
+
+/// Command that checks every file in the workspace and publishes their diagnostics.
+const SCAN_WORKSPACE: &str = "codetypo.scanWorkspace";
+
+/// Command that checks the names of every file in the workspace and their directories, and
+/// publishes their diagnostics.
+const CHECK_FILE_NAMES: &str = "codetypo.checkFileNames";
+
+/// Command that pauses checking and clears the diagnostics of every document, or resumes checking
+/// when it's paused.
+const TOGGLE: &str = "codetypo.toggle";
+
+/// Command that returns words similar to a typo, for typos with no or few corrections.
+const MORE_SUGGESTIONS: &str = "codetypo.moreSuggestions";
+
+/// Command that asks the client to apply a workspace edit, which code actions with edits are sent
+/// as to clients that only accept commands.
+const APPLY_WORKSPACE_EDIT: &str = "codetypo.applyWorkspaceEdit";
+
+/// Name of the capability in the `disabledCapabilities` setting that turns off watching config
+/// files.
+const WATCHED_FILES: &str = "watchedFiles";
+
+/// A capability that's registered dynamically when the client supports it, so it can be turned off
+/// by the `disabledCapabilities` setting without restarting the server.
+pub(crate) struct DynamicCapability {
+    /// Name of the capability in the `disabledCapabilities` setting.
+    name: &'static str,
+    method: &'static str,
+}
+
+impl DynamicCapability {
+    /// Returns the registration of the capability, with the same options as it has when it's
+    /// registered statically by `initialize`.
+    fn registration(&self) -> Registration {
+        let options = match self.method {
+            "textDocument/codeAction" => json!({
+                "codeActionKinds": [CodeActionKind::QUICKFIX, SOURCE_FIX_ALL_CODETYPO],
+                "resolveProvider": true,
+            }),
+            "textDocument/diagnostic" => json!({
+                "identifier": "codetypo",
+                "interFileDependencies": false,
+                "workspaceDiagnostics": true,
+            }),
+            _ => json!({}),
+        };
+        Registration {
+            id: format!("codetypo-{}", self.method),
+            method: self.method.to_string(),
+            register_options: Some(options),
+        }
+    }
+}
+
+/// The capabilities that are registered dynamically when the client supports it.
+pub(crate) const DYNAMIC_CAPABILITIES: [DynamicCapability; 4] = [
+    DynamicCapability {
+        name: "codeActions",
+        method: "textDocument/codeAction",
+    },
+    DynamicCapability {
+        name: "formatting",
+        method: "textDocument/formatting",
+    },
+    DynamicCapability {
+        name: "formatting",
+        method: "textDocument/rangeFormatting",
+    },
+    DynamicCapability {
+        name: "pullDiagnostics",
+        method: "textDocument/diagnostic",
+    },
+];
+
+/// `$/progress` notification used to stream partial results back to the client.
+enum PartialResultProgress {}
+
+impl notification::Notification for PartialResultProgress {
+    type Params = PartialResultProgressParams;
+    const METHOD: &'static str = "$/progress";
+}
+
+/// Params of a [`PartialResultProgress`] notification carrying a partial workspace diagnostic result.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct PartialResultProgressParams {
+    token: ProgressToken,
+    value: WorkspaceDiagnosticReportPartialResult,
+}
+
 #[tower_lsp::async_trait]
 /// Implements the LSP server for Codetypo.
 #[tower_lsp::async_trait]
-impl LanguageServer for Backend<'static, 'static> {
+impl LanguageServer for Backend<'static> {
     /// Handles LSP initialize request.
     async fn initialize(&self, params: InitializeParams) -> jsonrpc::Result<InitializeResult> {
-        tracing::debug!("initialize: {}", to_string(&params).unwrap_or_default());
+        self.state.settings.write().unwrap().trace = params.trace.unwrap_or_default();
+        self.trace_request("initialize", &params).await;
 
         if let Some(TextDocumentClientCapabilities {
             publish_diagnostics:
@@ -46,70 +734,251 @@ impl LanguageServer for Backend<'static, 'static> {
         {
             tracing::debug!("Client supports diagnostics data")
         } else {
-            tracing::warn!(
-                "Client does not support diagnostics data. Code actions will not be available"
+            tracing::debug!(
+                "Client does not support diagnostics data, so code actions look it up in the published diagnostics"
             )
         }
 
-        let mut state = self.state.lock().unwrap();
+        {
+            let mut settings = self.state.settings.write().unwrap();
 
-        if let Some(ops) = params.initialization_options {
-            if let Some(values) = ops.as_object() {
-                if let Some(value) = values.get("diagnosticSeverity").cloned() {
-                    match value.as_str().unwrap_or("").to_lowercase().as_str() {
-                        "error" => {
-                            state.severity = Some(DiagnosticSeverity::ERROR);
-                        }
-                        "warning" => {
-                            state.severity = Some(DiagnosticSeverity::WARNING);
-                        }
-                        "information" | "info" => {
-                            state.severity = Some(DiagnosticSeverity::INFORMATION);
-                        }
-                        "hint" => {
-                            state.severity = Some(DiagnosticSeverity::HINT);
-                        }
-                        _ => {
-                            tracing::warn!("Unknown diagnostic severity: {}", value);
-                        }
-                    }
-                }
-                if let Some(value) = values.get("config").cloned() {
-                    if let Some(value) = value.as_str() {
-                        let expanded_path = PathBuf::from(shellexpand::tilde(value).to_string());
-                        state.config = Some(expanded_path);
-                    }
-                }
+            if let Some(ops) = params.initialization_options {
+                settings.apply_settings(&ops);
             }
+
+            settings.configuration_support = params
+                .capabilities
+                .workspace
+                .as_ref()
+                .and_then(|workspace| workspace.configuration)
+                .unwrap_or(false);
+            settings.watched_files_support = params
+                .capabilities
+                .workspace
+                .as_ref()
+                .and_then(|workspace| workspace.did_change_watched_files)
+                .and_then(|watched_files| watched_files.dynamic_registration)
+                .unwrap_or(false);
+            let text_document = params.capabilities.text_document.as_ref();
+            settings.dynamic_registration_support = [
+                (
+                    "textDocument/codeAction",
+                    text_document
+                        .and_then(|text_document| text_document.code_action.as_ref())
+                        .and_then(|code_action| code_action.dynamic_registration),
+                ),
+                (
+                    "textDocument/formatting",
+                    text_document
+                        .and_then(|text_document| text_document.formatting.as_ref())
+                        .and_then(|formatting| formatting.dynamic_registration),
+                ),
+                (
+                    "textDocument/rangeFormatting",
+                    text_document
+                        .and_then(|text_document| text_document.range_formatting.as_ref())
+                        .and_then(|range_formatting| range_formatting.dynamic_registration),
+                ),
+                (
+                    "textDocument/diagnostic",
+                    text_document
+                        .and_then(|text_document| text_document.diagnostic.as_ref())
+                        .and_then(|diagnostic| diagnostic.dynamic_registration),
+                ),
+            ]
+            .into_iter()
+            .filter(|(_, supported)| supported.unwrap_or(false))
+            .map(|(method, _)| method)
+            .collect();
+            // the first encoding the client prefers that's supported, or else UTF-16, the default
+            settings.position_encoding = params
+                .capabilities
+                .general
+                .as_ref()
+                .and_then(|general| general.position_encodings.as_ref())
+                .into_iter()
+                .flatten()
+                .find_map(|kind| match kind.as_str() {
+                    "utf-8" => Some(PositionEncoding::Utf8),
+                    "utf-16" => Some(PositionEncoding::Utf16),
+                    "utf-32" => Some(PositionEncoding::Utf32),
+                    _ => None,
+                })
+                .unwrap_or_default();
+            settings.resolve_edit_support = params
+                .capabilities
+                .text_document
+                .as_ref()
+                .and_then(|text_document| text_document.code_action.as_ref())
+                .and_then(|code_action| code_action.resolve_support.as_ref())
+                .is_some_and(|resolve_support| {
+                    resolve_support
+                        .properties
+                        .iter()
+                        .any(|property| property == "edit")
+                });
+            // clients that don't declare code action capabilities at all are assumed to accept
+            // literals, like most clients written since they were added, and so are clients that
+            // resolve them
+            settings.code_action_commands_only = params
+                .capabilities
+                .text_document
+                .as_ref()
+                .and_then(|text_document| text_document.code_action.as_ref())
+                .is_some_and(|code_action| {
+                    code_action.code_action_literal_support.is_none()
+                        && code_action.resolve_support.is_none()
+                });
+            settings.related_information_support = params
+                .capabilities
+                .text_document
+                .as_ref()
+                .and_then(|text_document| text_document.publish_diagnostics.as_ref())
+                .and_then(|publish_diagnostics| publish_diagnostics.related_information)
+                .unwrap_or(false);
+            settings.work_done_progress_support = params
+                .capabilities
+                .window
+                .as_ref()
+                .and_then(|window| window.work_done_progress)
+                .unwrap_or(false);
+            settings.status_notification_support = params
+                .capabilities
+                .experimental
+                .as_ref()
+                .and_then(|experimental| experimental.get("codetypoStatusNotification"))
+                .and_then(serde_json::Value::as_bool)
+                .unwrap_or(false);
+            settings.server_state_notification_support = params
+                .capabilities
+                .experimental
+                .as_ref()
+                .and_then(|experimental| experimental.get("codetypoServerStateNotification"))
+                .and_then(serde_json::Value::as_bool)
+                .unwrap_or(false);
         }
 
-        if let Err(e) = state.set_workspace_folders(params.workspace_folders.unwrap_or_default()) {
+        if let Err(e) = self
+            .state
+            .set_workspace_folders(params.workspace_folders.unwrap_or_default())
+        {
             tracing::warn!("Falling back to default config: {}", e);
         }
 
+        let config_file_operations = FileOperationRegistrationOptions {
+            filters: self
+                .config_file_names()
+                .into_iter()
+                .map(|name| FileOperationFilter {
+                    scheme: Some("file".to_string()),
+                    pattern: FileOperationPattern {
+                        glob: format!("**/{}", name),
+                        matches: Some(FileOperationPatternKind::File),
+                        options: None,
+                    },
+                })
+                .collect(),
+        };
+
+        // capabilities the client registers dynamically aren't registered statically
+        let dynamic = |method: &str| {
+            self.state
+                .settings
+                .read()
+                .unwrap()
+                .dynamic_registration_support
+                .contains(method)
+        };
+
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
-                // only support UTF-16 positions for now, which is the default when unspecified
-                position_encoding: Some(PositionEncodingKind::UTF16),
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    // TODO: should we support incremental?
-                    TextDocumentSyncKind::FULL,
+                position_encoding: Some(
+                    match self.state.settings.read().unwrap().position_encoding {
+                        PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+                        PositionEncoding::Utf16 => PositionEncodingKind::UTF16,
+                        PositionEncoding::Utf32 => PositionEncodingKind::UTF32,
+                    },
+                ),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        will_save_wait_until: Some(true),
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                        ..TextDocumentSyncOptions::default()
+                    },
                 )),
-                code_action_provider: Some(CodeActionProviderCapability::Options(
-                    CodeActionOptions {
-                        code_action_kinds: Some(vec![CodeActionKind::QUICKFIX]),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                completion_provider: Some(CompletionOptions::default()),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                document_formatting_provider: (!dynamic("textDocument/formatting"))
+                    .then_some(OneOf::Left(true)),
+                document_range_formatting_provider: (!dynamic("textDocument/rangeFormatting"))
+                    .then_some(OneOf::Left(true)),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            legend: SemanticTokensLegend {
+                                token_types: vec![TYPO_TOKEN_TYPE],
+                                token_modifiers: vec![DISALLOWED_TOKEN_MODIFIER],
+                            },
+                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                            ..SemanticTokensOptions::default()
+                        },
+                    ),
+                ),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
+                code_action_provider: (!dynamic("textDocument/codeAction")).then_some(
+                    CodeActionProviderCapability::Options(CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            SOURCE_FIX_ALL_CODETYPO,
+                        ]),
                         work_done_progress_options: WorkDoneProgressOptions {
                             work_done_progress: Some(false),
                         },
-                        resolve_provider: None,
-                    },
-                )),
+                        resolve_provider: Some(true),
+                    }),
+                ),
+                diagnostic_provider: (!dynamic("textDocument/diagnostic")).then_some(
+                    DiagnosticServerCapabilities::Options(DiagnosticOptions {
+                        identifier: Some("codetypo".to_string()),
+                        inter_file_dependencies: false,
+                        workspace_diagnostics: true,
+                        ..DiagnosticOptions::default()
+                    }),
+                ),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        ADD_TO_PROJECT_DICTIONARY.to_string(),
+                        ADD_CORRECTION.to_string(),
+                        IGNORE_WORD_IN_FILE.to_string(),
+                        ADD_TO_USER_DICTIONARY.to_string(),
+                        IGNORE_WORD_SESSION.to_string(),
+                        FIX_ALL.to_string(),
+                        REPLACE_IN_WORKSPACE.to_string(),
+                        FIX_FILE.to_string(),
+                        SCAN_WORKSPACE.to_string(),
+                        CHECK_FILE_NAMES.to_string(),
+                        TOGGLE.to_string(),
+                        MORE_SUGGESTIONS.to_string(),
+                        APPLY_WORKSPACE_EDIT.to_string(),
+                    ],
+                    ..ExecuteCommandOptions::default()
+                }),
                 workspace: Some(WorkspaceServerCapabilities {
                     workspace_folders: Some(WorkspaceFoldersServerCapabilities {
                         supported: Some(true),
                         change_notifications: Some(OneOf::Left(true)),
                     }),
-                    ..Default::default()
+                    file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                        did_create: Some(config_file_operations.clone()),
+                        did_delete: Some(config_file_operations),
+                        ..Default::default()
+                    }),
                 }),
                 ..Default::default()
             },
@@ -125,41 +994,174 @@ impl LanguageServer for Backend<'static, 'static> {
         self.client
             .log_message(MessageType::INFO, "server initialized!")
             .await;
+
+        // for clients that don't pass settings via initializationOptions
+        if self.state.settings.read().unwrap().configuration_support {
+            self.pull_configuration().await;
+        }
+
+        if self.state.settings.read().unwrap().watched_files_support {
+            self.watch_config_files().await;
+        }
+        self.register_capabilities().await;
     }
 
     /// Handles opening of a text document.
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        tracing::debug!("did_open: {:?}", to_string(&params).unwrap_or_default());
+        self.trace_request("textDocument/didOpen", &params).await;
+        // the document may be on a drive mounted since the router was updated
+        #[cfg(windows)]
+        if let Err(e) = self.state.refresh_drive_routes() {
+            tracing::warn!("Cannot refresh drive routes: {}", e);
+        }
+        let policy_uri = self.state.policy_uri(&params.text_document.uri);
+        if let Err(e) = self.state.add_share_route(&policy_uri) {
+            tracing::warn!("Cannot add route for {}: {}", policy_uri, e);
+        }
+        self.state.documents.lock().unwrap().insert(
+            params.text_document.uri.clone(),
+            params.text_document.clone(),
+        );
         self.report_diagnostics(params.text_document).await;
     }
 
     /// Handles changes to a text document.
-    async fn did_change(&self, mut params: DidChangeTextDocumentParams) {
-        tracing::debug!("did_change: {:?}", to_string(&params).unwrap_or_default());
-        self.report_diagnostics(TextDocumentItem {
-            language_id: "FOOBAR".to_string(),
-            uri: params.text_document.uri,
-            text: std::mem::take(&mut params.content_changes[0].text),
-            version: params.text_document.version,
-        })
-        .await;
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        self.trace_request("textDocument/didChange", &params).await;
+        let (document, delay, check_on_save_only) = {
+            let (delay, check_on_save_only) = {
+                let settings = self.state.settings.read().unwrap();
+                (settings.diagnostic_delay(), settings.check_on_save_only)
+            };
+            let mut documents = self.state.documents.lock().unwrap();
+            let document = documents
+                .entry(params.text_document.uri.clone())
+                .or_insert_with(|| TextDocumentItem {
+                    uri: params.text_document.uri,
+                    language_id: String::default(),
+                    version: params.text_document.version,
+                    text: String::default(),
+                });
+            if params.text_document.version < document.version {
+                tracing::warn!(
+                    "Ignoring out of order change to {} version {} (have version {})",
+                    document.uri,
+                    params.text_document.version,
+                    document.version
+                );
+                return;
+            }
+            // changes are applied in order, each to the result of the previous one
+            let encoding = self.state.settings.read().unwrap().position_encoding;
+            for change in params.content_changes {
+                crate::state::apply_content_change(&mut document.text, change, encoding);
+            }
+            document.version = params.text_document.version;
+            let document = document.clone();
+            // the in-flight check of the previous version is now stale
+            self.state.cancel_check(&document.uri);
+            (document, delay, check_on_save_only)
+        };
+
+        // the document is checked when it's saved instead
+        if check_on_save_only {
+            return;
+        }
+
+        // debounce bursts of changes, so only the latest version is checked
+        if !delay.is_zero() {
+            self.state.queue_check(&document.uri, document.version);
+            self.send_server_state().await;
+            tokio::time::sleep(delay).await;
+            self.state.dequeue_check(&document.uri, document.version);
+            let latest = self
+                .state
+                .documents
+                .lock()
+                .unwrap()
+                .get(&document.uri)
+                .map(|latest| latest.version);
+            if latest != Some(document.version) {
+                self.send_server_state().await;
+                return;
+            }
+        }
+        self.report_diagnostics(document).await;
+        // the check may have been skipped, eg: when the diagnostics were reused
+        self.send_server_state().await;
+    }
+
+    /// Fixes typos with a single correction before a document is saved, when enabled.
+    async fn will_save_wait_until(
+        &self,
+        params: WillSaveTextDocumentParams,
+    ) -> jsonrpc::Result<Option<Vec<TextEdit>>> {
+        self.trace_request("textDocument/willSaveWaitUntil", &params)
+            .await;
+
+        if !self.state.settings.read().unwrap().auto_fix_on_save {
+            return Ok(None);
+        }
+
+        let typos = self.document_typos(&params.text_document.uri).await;
+        Ok(Some(unambiguous_fixes(typos)))
     }
 
-    /// Handles saving of a text document.
+    /// Handles saving of a text document, checking it when only checking on save.
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
-        tracing::debug!("did_save: {:?}", to_string(&params).unwrap_or_default());
-        // noop to avoid unimplemented warning log line
+        self.trace_request("textDocument/didSave", &params).await;
+        // the file may have been committed, changing the lines changed since the diff base
+        let policy_uri = self.state.policy_uri(&params.text_document.uri);
+        if let Some(path) = self.state.file_path(&policy_uri) {
+            self.state.base_texts.invalidate(&canonicalize(&path));
+        }
+        if !self.state.settings.read().unwrap().check_on_save_only {
+            return;
+        }
+        // the text was already synced via did_change
+        let document = self
+            .state
+            .documents
+            .lock()
+            .unwrap()
+            .get(&params.text_document.uri)
+            .cloned();
+        if let Some(document) = document {
+            self.report_diagnostics(document).await;
+        }
     }
 
     /// Handles closing of a text document.
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
-        tracing::debug!("did_close: {:?}", to_string(&params).unwrap_or_default());
+        self.trace_request("textDocument/didClose", &params).await;
+        self.state
+            .documents
+            .lock()
+            .unwrap()
+            .remove(&params.text_document.uri);
+        self.state.cancel_check(&params.text_document.uri);
+        self.state
+            .published
+            .lock()
+            .unwrap()
+            .remove(&params.text_document.uri);
+        self.state
+            .checked
+            .lock()
+            .unwrap()
+            .remove(&params.text_document.uri);
+        self.state
+            .typo_counts
+            .lock()
+            .unwrap()
+            .remove(&params.text_document.uri);
         // clear diagnostics to avoid a stale diagnostics flash on open
         // if the file has codetypo fixed outside of vscode
         // see https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_publishDiagnostics
         self.client
             .publish_diagnostics(params.text_document.uri, Vec::new(), None)
             .await;
+        self.send_status().await;
     }
 
     /// Provides code actions (quick fixes) for diagnostics.
@@ -167,73 +1169,940 @@ impl LanguageServer for Backend<'static, 'static> {
         &self,
         params: CodeActionParams,
     ) -> jsonrpc::Result<Option<CodeActionResponse>> {
-        tracing::debug!("code_action: {:?}", to_string(&params).unwrap_or_default());
+        self.trace_request("textDocument/codeAction", &params).await;
+        // for clients that can't unregister it
+        if self.capability_disabled("codeActions") {
+            return Ok(None);
+        }
+
+        let path = self.state.file_path(&params.text_document.uri);
+        let workspace_folder = path
+            .as_ref()
+            .and_then(|path| self.state.workspace_folder(path));
+        let file_name = path
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy());
+
+        let only = params.context.only.as_deref();
+        let document_typos = self.document_typos(&params.text_document.uri).await;
+        // searching for similar words is slow, so only done when the user asks for code actions
+        let invoked = params.context.trigger_kind == Some(CodeActionTriggerKind::INVOKED);
+        // edits are only computed when an action is chosen, for clients that can resolve them
+        let (resolve_edits, commands_only) = {
+            let settings = self.state.settings.read().unwrap();
+            (
+                settings.resolve_edit_support && !settings.code_action_commands_only,
+                settings.code_action_commands_only,
+            )
+        };
+        let with_edit = |action: CodeAction, data: ResolveData| {
+            if resolve_edits {
+                CodeAction {
+                    data: Some(json!(data)),
+                    ..action
+                }
+            } else {
+                CodeAction {
+                    edit: Some(data.edit(&document_typos)),
+                    ..action
+                }
+            }
+        };
+        // the open document's comment syntax and text, for suppression comments
+        let document = self
+            .state
+            .documents
+            .lock()
+            .unwrap()
+            .get(&params.text_document.uri)
+            .and_then(|document| {
+                let comment = Syntax::from_language_id(&document.language_id)?
+                    .comment("codetypo:disable-next-line")?;
+                Some((comment, document.text.clone()))
+            });
 
-        let actions = params
+        // for clients that drop the data of diagnostics, it's looked up by range in the diagnostics
+        // published for the document
+        let published = self
+            .state
+            .published
+            .lock()
+            .unwrap()
+            .get(&params.text_document.uri)
+            .map(|published| published.diagnostics.clone())
+            .unwrap_or_default();
+
+        let mut actions = params
             .context
             .diagnostics
             .iter()
+            .filter(|_| is_requested(only, &CodeActionKind::QUICKFIX, true))
             .filter(|diag| diag.source == Some("codetypo".to_string()))
-            .flat_map(|diag| match &diag.data {
+            .flat_map(|diag| match diag_data(diag, &published) {
                 Some(data) => {
-                    if let Ok(DiagnosticData { corrections }) =
+                    if let Ok(DiagnosticData { typo, corrections }) =
                         serde_json::from_value::<DiagnosticData>(data.clone())
                     {
-                        corrections
+                        // ranges of every occurrence of the same typo in the document
+                        let occurrences = document_typos
+                            .iter()
+                            .filter(|(_, other)| other.typo == typo)
+                            .map(|(range, _)| *range)
+                            .collect::<Vec<_>>();
+
+                        let fix_all_occurrences = corrections
+                            .iter()
+                            .filter(|_| occurrences.len() > 1)
+                            .map(|c| {
+                                CodeActionOrCommand::CodeAction(with_edit(
+                                    CodeAction {
+                                        title: format!("Fix all `{}` → `{}` in this file", typo, c),
+                                        kind: Some(CodeActionKind::QUICKFIX),
+                                        diagnostics: Some(vec![diag.clone()]),
+                                        ..CodeAction::default()
+                                    },
+                                    ResolveData::ReplaceAll {
+                                        uri: params.text_document.uri.clone(),
+                                        typo: typo.to_string(),
+                                        correction: c.to_string(),
+                                    },
+                                ))
+                            })
+                            .collect::<Vec<_>>();
+
+                        let replace_in_workspace = corrections
                             .iter()
+                            .filter(|_| workspace_folder.is_some())
                             .map(|c| {
+                                let title = format!(
+                                    "Replace `{}` with `{}` everywhere in the workspace",
+                                    typo, c
+                                );
                                 CodeActionOrCommand::CodeAction(CodeAction {
-                                    title: c.to_string(),
+                                    title: title.clone(),
                                     kind: Some(CodeActionKind::QUICKFIX),
                                     diagnostics: Some(vec![diag.clone()]),
-                                    edit: Some(WorkspaceEdit {
-                                        changes: Some(HashMap::from([(
-                                            params.text_document.uri.clone(),
-                                            vec![TextEdit {
-                                                range: diag.range,
-                                                new_text: c.to_string(),
-                                            }],
-                                        )])),
-                                        ..WorkspaceEdit::default()
+                                    command: Some(Command {
+                                        title,
+                                        command: REPLACE_IN_WORKSPACE.to_string(),
+                                        arguments: Some(vec![json!(typo), json!(c)]),
                                     }),
-                                    is_preferred: if corrections.len() == 1 {
-                                        Some(true)
-                                    } else {
-                                        None
-                                    },
                                     ..CodeAction::default()
                                 })
                             })
-                            .collect()
-                    } else {
-                        tracing::error!(
-                            "Deserialization failed: received {:?} as diagnostic data",
-                            data
-                        );
-                        vec![]
-                    }
-                }
-                None => {
-                    tracing::warn!("Client doesn't support diagnostic data");
-                    vec![]
-                }
-            })
-            .collect::<Vec<_>>();
-
-        Ok(Some(actions))
-    }
+                            .collect::<Vec<_>>();
 
-    /// Handles workspace folder changes.
-    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
-        tracing::debug!(
-            "did_change_workspace_folders: {:?}",
-            to_string(&params).unwrap_or_default()
-        );
+                        // with a single correction, fixes are already applied without prompting
+                        let add_correction = corrections
+                            .iter()
+                            .filter(|_| workspace_folder.is_some() && corrections.len() > 1)
+                            .map(|c| {
+                                let title = format!("Always correct `{}` to `{}`", typo, c);
+                                CodeActionOrCommand::CodeAction(CodeAction {
+                                    title: title.clone(),
+                                    kind: Some(CodeActionKind::QUICKFIX),
+                                    diagnostics: Some(vec![diag.clone()]),
+                                    command: Some(Command {
+                                        title,
+                                        command: ADD_CORRECTION.to_string(),
+                                        arguments: Some(vec![
+                                            json!(params.text_document.uri),
+                                            json!(typo),
+                                            json!(c),
+                                        ]),
+                                    }),
+                                    ..CodeAction::default()
+                                })
+                            })
+                            .collect::<Vec<_>>();
+
+                        let add_to_project_dictionary = workspace_folder.as_ref().map(|_| {
+                            let title = format!("Add `{}` to project dictionary", typo);
+                            CodeActionOrCommand::CodeAction(CodeAction {
+                                title: title.clone(),
+                                kind: Some(CodeActionKind::QUICKFIX),
+                                diagnostics: Some(vec![diag.clone()]),
+                                command: Some(Command {
+                                    title,
+                                    command: ADD_TO_PROJECT_DICTIONARY.to_string(),
+                                    arguments: Some(vec![
+                                        json!(params.text_document.uri),
+                                        json!(typo),
+                                    ]),
+                                }),
+                                ..CodeAction::default()
+                            })
+                        });
+
+                        let ignore_word_in_file = workspace_folder
+                            .as_ref()
+                            .and(file_name.as_ref())
+                            .map(|file_name| {
+                                let title = format!("Ignore `{}` in `{}`", typo, file_name);
+                                CodeActionOrCommand::CodeAction(CodeAction {
+                                    title: title.clone(),
+                                    kind: Some(CodeActionKind::QUICKFIX),
+                                    diagnostics: Some(vec![diag.clone()]),
+                                    command: Some(Command {
+                                        title,
+                                        command: IGNORE_WORD_IN_FILE.to_string(),
+                                        arguments: Some(vec![
+                                            json!(params.text_document.uri),
+                                            json!(typo),
+                                        ]),
+                                    }),
+                                    ..CodeAction::default()
+                                })
+                            });
+
+                        // inserts the comment on a line of its own, so it doesn't end up in a
+                        // string or change what the line does
+                        let suppress_line = document.as_ref().map(|(comment, text)| {
+                            let line = diag.range.start.line;
+                            let indent = crate::codetypo::lines(text)
+                                .nth(line as usize)
+                                .map(|text| {
+                                    let code = text.trim_start();
+                                    &text[..text.len() - code.len()]
+                                })
+                                .unwrap_or_default();
+                            CodeActionOrCommand::CodeAction(CodeAction {
+                                title: "Suppress codetypo for this line".to_string(),
+                                kind: Some(CodeActionKind::QUICKFIX),
+                                diagnostics: Some(vec![diag.clone()]),
+                                edit: Some(WorkspaceEdit {
+                                    changes: Some(HashMap::from([(
+                                        params.text_document.uri.clone(),
+                                        vec![TextEdit {
+                                            range: Range::new(
+                                                Position::new(line, 0),
+                                                Position::new(line, 0),
+                                            ),
+                                            new_text: format!("{}{}\n", indent, comment),
+                                        }],
+                                    )])),
+                                    ..WorkspaceEdit::default()
+                                }),
+                                ..CodeAction::default()
+                            })
+                        });
+
+                        let more_suggestions = if invoked && corrections.len() < 2 {
+                            self.more_suggestions(
+                                &params.text_document.uri,
+                                &typo,
+                                &corrections,
+                                &document_typos,
+                            )
+                        } else {
+                            Vec::new()
+                        };
+                        let more_suggestions = more_suggestions.into_iter().map(|word| {
+                            CodeActionOrCommand::CodeAction(with_edit(
+                                CodeAction {
+                                    title: format!("Did you mean `{}`?", word),
+                                    kind: Some(CodeActionKind::QUICKFIX),
+                                    diagnostics: Some(vec![diag.clone()]),
+                                    ..CodeAction::default()
+                                },
+                                ResolveData::Replace {
+                                    uri: params.text_document.uri.clone(),
+                                    range: diag.range,
+                                    correction: word,
+                                },
+                            ))
+                        });
+
+                        let title = format!("Add `{}` to user dictionary", typo);
+                        let add_to_user_dictionary = CodeActionOrCommand::CodeAction(CodeAction {
+                            title: title.clone(),
+                            kind: Some(CodeActionKind::QUICKFIX),
+                            diagnostics: Some(vec![diag.clone()]),
+                            command: Some(Command {
+                                title,
+                                command: ADD_TO_USER_DICTIONARY.to_string(),
+                                arguments: Some(vec![json!(typo)]),
+                            }),
+                            ..CodeAction::default()
+                        });
+
+                        let title = format!("Ignore `{}` for this session", typo);
+                        let ignore_word_session = CodeActionOrCommand::CodeAction(CodeAction {
+                            title: title.clone(),
+                            kind: Some(CodeActionKind::QUICKFIX),
+                            diagnostics: Some(vec![diag.clone()]),
+                            command: Some(Command {
+                                title,
+                                command: IGNORE_WORD_SESSION.to_string(),
+                                arguments: Some(vec![json!(typo)]),
+                            }),
+                            ..CodeAction::default()
+                        });
+
+                        // corrections are sorted by frequency, so the first is the most likely
+                        let preferred = crate::frequency::is_most_likely(&corrections);
+                        corrections
+                            .iter()
+                            .enumerate()
+                            .map(|(i, c)| {
+                                CodeActionOrCommand::CodeAction(with_edit(
+                                    CodeAction {
+                                        title: c.to_string(),
+                                        kind: Some(CodeActionKind::QUICKFIX),
+                                        diagnostics: Some(vec![diag.clone()]),
+                                        is_preferred: if preferred && i == 0 {
+                                            Some(true)
+                                        } else {
+                                            None
+                                        },
+                                        ..CodeAction::default()
+                                    },
+                                    ResolveData::Replace {
+                                        uri: params.text_document.uri.clone(),
+                                        range: diag.range,
+                                        correction: c.to_string(),
+                                    },
+                                ))
+                            })
+                            .chain(more_suggestions)
+                            .chain(fix_all_occurrences)
+                            .chain(replace_in_workspace)
+                            .chain(add_correction)
+                            .chain(add_to_project_dictionary)
+                            .chain(ignore_word_in_file)
+                            .chain([add_to_user_dictionary, ignore_word_session])
+                            .chain(suppress_line)
+                            .collect()
+                    } else {
+                        tracing::error!(
+                            "Deserialization failed: received {:?} as diagnostic data",
+                            data
+                        );
+                        vec![]
+                    }
+                }
+                None => {
+                    tracing::warn!(
+                        "No data for diagnostic at {:?}, which the client dropped and isn't published",
+                        diag.range
+                    );
+                    vec![]
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // source actions are only returned when explicitly requested
+        if is_requested(only, &SOURCE_FIX_ALL_CODETYPO, false)
+            && !fix_all(&document_typos).is_empty()
+        {
+            actions.push(CodeActionOrCommand::CodeAction(with_edit(
+                CodeAction {
+                    title: "Fix all typos".to_string(),
+                    kind: Some(SOURCE_FIX_ALL_CODETYPO),
+                    ..CodeAction::default()
+                },
+                ResolveData::FixAll {
+                    uri: params.text_document.uri.clone(),
+                },
+            )));
+        }
+
+        if commands_only {
+            actions = actions.into_iter().filter_map(command_action).collect();
+        }
+
+        Ok(Some(actions))
+    }
+
+    /// Computes the edit of a code action returned without one, for clients that resolve edits.
+    async fn code_action_resolve(&self, params: CodeAction) -> jsonrpc::Result<CodeAction> {
+        self.trace_request("codeAction/resolve", &params).await;
+
+        let Some(data) = params.data.clone() else {
+            return Ok(params);
+        };
+        let data = serde_json::from_value::<ResolveData>(data)
+            .map_err(|e| jsonrpc::Error::invalid_params(e.to_string()))?;
+        let uri = match &data {
+            ResolveData::Replace { uri, .. }
+            | ResolveData::ReplaceAll { uri, .. }
+            | ResolveData::FixAll { uri } => uri,
+        };
+        // replacing a single typo doesn't depend on the other typos
+        let document_typos = match data {
+            ResolveData::Replace { .. } => Vec::new(),
+            _ => self.document_typos(uri).await,
+        };
+        Ok(CodeAction {
+            edit: Some(data.edit(&document_typos)),
+            data: None,
+            ..params
+        })
+    }
+
+    /// Shows the corrections for the typo under the cursor, and where they come from.
+    async fn hover(&self, params: HoverParams) -> jsonrpc::Result<Option<Hover>> {
+        self.trace_request("textDocument/hover", &params).await;
 
-        let mut state = self.state.lock().unwrap();
-        if let Err(e) = state.update_workspace_folders(params.event.added, params.event.removed) {
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+        let Some((range, typo)) = self.typo_at(&uri, position).await else {
+            return Ok(None);
+        };
+
+        let rule = self
+            .state
+            .file_path(&uri)
+            .and_then(|path| self.word_rules(&path).find(&typo.typo));
+        let source = match rule {
+            Some(rule) => format!("`[{}]` in `{}`", rule.table, rule.path.display()),
+            None => "built-in dictionary".to_string(),
+        };
+
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("{}\n\nSource: {}", message(&typo, 0), source),
+            }),
+            range: Some(range),
+        }))
+    }
+
+    /// Offers the corrections of the typo under the cursor as completions, so they can be
+    /// accepted while typing.
+    async fn completion(
+        &self,
+        params: CompletionParams,
+    ) -> jsonrpc::Result<Option<CompletionResponse>> {
+        self.trace_request("textDocument/completion", &params).await;
+
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        let Some((range, typo)) = self.typo_at(&uri, position).await else {
+            return Ok(None);
+        };
+        let codetypo::Status::Corrections(corrections) = typo.corrections else {
+            return Ok(None);
+        };
+
+        let items = corrections
+            .iter()
+            .enumerate()
+            .map(|(i, correction)| CompletionItem {
+                label: correction.to_string(),
+                kind: Some(CompletionItemKind::TEXT),
+                detail: Some(format!("Correction of `{}`", typo.typo)),
+                // keep the dictionary's order, and match against what was typed
+                sort_text: Some(format!("{:04}", i)),
+                filter_text: Some(typo.typo.to_string()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: correction.to_string(),
+                })),
+                ..CompletionItem::default()
+            })
+            .collect();
+
+        Ok(Some(CompletionResponse::Array(items)))
+    }
+
+    /// Highlights every occurrence of the typo under the cursor.
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> jsonrpc::Result<Option<Vec<DocumentHighlight>>> {
+        self.trace_request("textDocument/documentHighlight", &params)
+            .await;
+
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+        let typos = self.document_typos(&uri).await;
+        let Some((_, typo)) = typos
+            .iter()
+            .find(|(range, _)| range.start <= position && position <= range.end)
+        else {
+            return Ok(None);
+        };
+
+        let highlights = typos
+            .iter()
+            .filter(|(_, other)| other.typo == typo.typo)
+            .map(|(range, _)| DocumentHighlight {
+                range: *range,
+                kind: Some(DocumentHighlightKind::TEXT),
+            })
+            .collect();
+
+        Ok(Some(highlights))
+    }
+
+    /// Shows the first correction inline after each typo, when enabled.
+    async fn inlay_hint(&self, params: InlayHintParams) -> jsonrpc::Result<Option<Vec<InlayHint>>> {
+        self.trace_request("textDocument/inlayHint", &params).await;
+
+        if !self.state.settings.read().unwrap().inlay_hints {
+            return Ok(None);
+        }
+
+        let hints = self
+            .document_typos(&params.text_document.uri)
+            .await
+            .into_iter()
+            .filter(|(range, _)| params.range.start <= range.start && range.end <= params.range.end)
+            .filter_map(|(range, typo)| match typo.corrections {
+                codetypo::Status::Corrections(corrections) => Some(InlayHint {
+                    position: range.end,
+                    label: InlayHintLabel::String(format!("⟶ {}", corrections.first()?)),
+                    kind: None,
+                    text_edits: None,
+                    tooltip: None,
+                    padding_left: Some(true),
+                    padding_right: None,
+                    data: None,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Ok(Some(hints))
+    }
+
+    /// Shows the number of typos at the top of a document, with a command to fix them all.
+    async fn code_lens(&self, params: CodeLensParams) -> jsonrpc::Result<Option<Vec<CodeLens>>> {
+        self.trace_request("textDocument/codeLens", &params).await;
+
+        let count = self.document_typos(&params.text_document.uri).await.len();
+        if count == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(vec![CodeLens {
+            range: Range::default(),
+            command: Some(Command {
+                title: format!(
+                    "{} {} — Fix all",
+                    count,
+                    if count == 1 { "typo" } else { "typos" }
+                ),
+                command: FIX_FILE.to_string(),
+                arguments: Some(vec![json!(params.text_document.uri)]),
+            }),
+            data: None,
+        }]))
+    }
+
+    /// Fixes every typo with a single correction in a document.
+    async fn formatting(
+        &self,
+        params: DocumentFormattingParams,
+    ) -> jsonrpc::Result<Option<Vec<TextEdit>>> {
+        self.trace_request("textDocument/formatting", &params).await;
+        if self.capability_disabled("formatting") {
+            return Ok(None);
+        }
+
+        let typos = self.document_typos(&params.text_document.uri).await;
+        Ok(Some(unambiguous_fixes(typos)))
+    }
+
+    /// Fixes every typo with a single correction within a range of a document.
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> jsonrpc::Result<Option<Vec<TextEdit>>> {
+        self.trace_request("textDocument/rangeFormatting", &params)
+            .await;
+        if self.capability_disabled("formatting") {
+            return Ok(None);
+        }
+
+        let typos = self
+            .document_typos(&params.text_document.uri)
+            .await
+            .into_iter()
+            .filter(|(range, _)| params.range.start <= range.start && range.end <= params.range.end)
+            .collect();
+        Ok(Some(unambiguous_fixes(typos)))
+    }
+
+    /// Marks typos with the [`TYPO_TOKEN_TYPE`] semantic token type.
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> jsonrpc::Result<Option<SemanticTokensResult>> {
+        self.trace_request("textDocument/semanticTokens/full", &params)
+            .await;
+
+        let mut typos = self.document_typos(&params.text_document.uri).await;
+        // tokens are encoded relative to the previous one
+        typos.sort_by_key(|(range, _)| (range.start.line, range.start.character));
+
+        let mut previous = Position::default();
+        let data = typos
+            .into_iter()
+            .map(|(range, typo)| {
+                let delta_line = range.start.line - previous.line;
+                let delta_start = if delta_line == 0 {
+                    range.start.character - previous.character
+                } else {
+                    range.start.character
+                };
+                previous = range.start;
+                SemanticToken {
+                    delta_line,
+                    delta_start,
+                    length: range.end.character - range.start.character,
+                    token_type: 0,
+                    token_modifiers_bitset: match typo.corrections {
+                        codetypo::Status::Invalid => 1,
+                        _ => 0,
+                    },
+                }
+            })
+            .collect();
+
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: None,
+            data,
+        })))
+    }
+
+    /// Handles document diagnostic pull requests.
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> jsonrpc::Result<DocumentDiagnosticReportResult> {
+        self.trace_request("textDocument/diagnostic", &params).await;
+        // open documents are already reported via publishDiagnostics, so return an
+        // empty report to avoid the client showing every typo twice
+        Ok(DocumentDiagnosticReportResult::Report(
+            DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport::default()),
+        ))
+    }
+
+    /// Handles workspace diagnostic pull requests, checking every file in the workspace folders.
+    ///
+    /// When the client provides a partial result token, each file's report is streamed back
+    /// via `$/progress` and the final response is empty.
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> jsonrpc::Result<WorkspaceDiagnosticReportResult> {
+        self.trace_request("workspace/diagnostic", &params).await;
+        if self.capability_disabled("pullDiagnostics") {
+            return Ok(WorkspaceDiagnosticReportResult::Report(
+                WorkspaceDiagnosticReport::default(),
+            ));
+        }
+
+        let token = params.partial_result_params.partial_result_token;
+        let mut items = Vec::new();
+        let mut progress = self
+            .begin_progress(
+                "Checking workspace",
+                params.work_done_progress_params.work_done_token,
+            )
+            .await;
+
+        // open documents are reported via publishDiagnostics
+        let files = self.closed_workspace_files();
+        let total = files.len();
+        let mut checked = self.check_files(files);
+        let mut i = 0;
+        while let Some(file) = checked.next().await {
+            progress.report(i, total).await;
+            i += 1;
+            // skip files that can't be read or aren't valid UTF-8
+            let Some(typos) = file.typos else {
+                continue;
+            };
+
+            let report =
+                WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id: None,
+                        items: self.document_diagnostics(typos, &file.uri, file.skipped),
+                    },
+                    uri: file.uri,
+                    version: None,
+                });
+
+            match &token {
+                Some(token) => {
+                    self.client
+                        .send_notification::<PartialResultProgress>(PartialResultProgressParams {
+                            token: token.clone(),
+                            value: WorkspaceDiagnosticReportPartialResult {
+                                items: vec![report],
+                            },
+                        })
+                        .await
+                }
+                None => items.push(report),
+            }
+        }
+        progress.end().await;
+
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
+    }
+
+    /// Executes a command, eg: one attached to a code action.
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> jsonrpc::Result<Option<serde_json::Value>> {
+        self.trace_request("workspace/executeCommand", &params)
+            .await;
+
+        match params.command.as_str() {
+            ADD_TO_PROJECT_DICTIONARY => {
+                let (uri, word) = parse_arguments::<(Url, String)>(params.arguments)?;
+                let Some(folder) = self
+                    .state
+                    .file_path(&uri)
+                    .and_then(|path| self.state.workspace_folder(&path))
+                else {
+                    return Err(jsonrpc::Error::invalid_params(format!(
+                        "{} is not in a workspace folder",
+                        uri
+                    )));
+                };
+                let config = crate::config::project_config(&folder);
+                if let Err(e) = crate::config::add_word(&config, &word) {
+                    return Err(internal_error(format!(
+                        "Cannot add {} to {}: {}",
+                        word,
+                        config.display(),
+                        e
+                    )));
+                }
+                self.reload_config().await;
+            }
+            ADD_CORRECTION => {
+                let (uri, word, correction) =
+                    parse_arguments::<(Url, String, String)>(params.arguments)?;
+                let Some(folder) = self
+                    .state
+                    .file_path(&uri)
+                    .and_then(|path| self.state.workspace_folder(&path))
+                else {
+                    return Err(jsonrpc::Error::invalid_params(format!(
+                        "{} is not in a workspace folder",
+                        uri
+                    )));
+                };
+                let config = crate::config::project_config(&folder);
+                if let Err(e) = crate::config::add_correction(&config, &word, &correction) {
+                    return Err(internal_error(format!(
+                        "Cannot add {} → {} to {}: {}",
+                        word,
+                        correction,
+                        config.display(),
+                        e
+                    )));
+                }
+                self.reload_config().await;
+            }
+            IGNORE_WORD_IN_FILE => {
+                let (uri, word) = parse_arguments::<(Url, String)>(params.arguments)?;
+                // the file config is keyed by the path the file is checked with, eg: of a notebook
+                // rather than its cell, relative to the workspace folder
+                let path = self.state.file_path(&self.state.policy_uri(&uri));
+                let Some((folder, file)) = path.as_ref().and_then(|path| {
+                    let folder = self.state.workspace_folder(path)?;
+                    let file = canonicalize(path)
+                        .strip_prefix(canonicalize(&folder))
+                        .ok()?
+                        .to_path_buf();
+                    Some((folder, file))
+                }) else {
+                    return Err(jsonrpc::Error::invalid_params(format!(
+                        "{} is not in a workspace folder",
+                        uri
+                    )));
+                };
+                let config = folder.join(crate::config::FILE_CONFIG);
+                if let Err(e) = crate::config::ignore_word_in_file(&config, &file, &word) {
+                    return Err(internal_error(format!(
+                        "Cannot ignore {} in {} using {}: {}",
+                        word,
+                        file.display(),
+                        config.display(),
+                        e
+                    )));
+                }
+                self.reload_config().await;
+            }
+            ADD_TO_USER_DICTIONARY => {
+                let (word,) = parse_arguments::<(String,)>(params.arguments)?;
+                let user_dictionary = self.state.settings.read().unwrap().user_dictionary();
+                if let Err(e) = crate::config::add_word(&user_dictionary, &word) {
+                    return Err(internal_error(format!(
+                        "Cannot add {} to {}: {}",
+                        word,
+                        user_dictionary.display(),
+                        e
+                    )));
+                }
+                self.reload_config().await;
+            }
+            IGNORE_WORD_SESSION => {
+                let (word,) = parse_arguments::<(String,)>(params.arguments)?;
+                tracing::info!("Ignoring {} for this session", word);
+                self.state
+                    .session_ignored
+                    .write()
+                    .unwrap()
+                    .insert(word.to_lowercase());
+                self.refresh_diagnostics().await;
+            }
+            FIX_ALL => {
+                let changes = self.workspace_edits(unambiguous_fixes).await;
+                self.apply_changes(changes).await?;
+            }
+            REPLACE_IN_WORKSPACE => {
+                let (typo, correction) = parse_arguments::<(String, String)>(params.arguments)?;
+                let changes = self
+                    .workspace_edits(|typos| {
+                        typos
+                            .into_iter()
+                            .filter(|(_, other)| other.typo == typo)
+                            .map(|(range, _)| TextEdit {
+                                range,
+                                new_text: correction.clone(),
+                            })
+                            .collect()
+                    })
+                    .await;
+                self.apply_changes(changes).await?;
+            }
+            SCAN_WORKSPACE => {
+                self.scan_workspace(params.work_done_progress_params.work_done_token)
+                    .await
+            }
+            CHECK_FILE_NAMES => {
+                self.check_file_names(params.work_done_progress_params.work_done_token)
+                    .await
+            }
+            TOGGLE => {
+                let paused = {
+                    let mut settings = self.state.settings.write().unwrap();
+                    settings.paused = !settings.paused;
+                    settings.paused
+                };
+                tracing::info!("{} checking", if paused { "Paused" } else { "Resumed" });
+                self.refresh_diagnostics().await;
+                return Ok(Some(json!({ "enabled": !paused })));
+            }
+            MORE_SUGGESTIONS => {
+                let (uri, typo) = parse_arguments::<(Url, String)>(params.arguments)?;
+                let document_typos = self.document_typos(&uri).await;
+                let corrections = document_typos
+                    .iter()
+                    .find(|(_, other)| other.typo == typo)
+                    .map(|(_, other)| match &other.corrections {
+                        codetypo::Status::Corrections(corrections) => corrections.to_vec(),
+                        _ => Vec::new(),
+                    })
+                    .unwrap_or_default();
+                let suggestions = self.more_suggestions(&uri, &typo, &corrections, &document_typos);
+                return Ok(Some(json!(suggestions)));
+            }
+            APPLY_WORKSPACE_EDIT => {
+                let (edit,) = parse_arguments::<(WorkspaceEdit,)>(params.arguments)?;
+                self.apply_edit(edit).await?;
+            }
+            FIX_FILE => {
+                let (uri,) = parse_arguments::<(Url,)>(params.arguments)?;
+                let edits = self.fix_all_edits(&uri).await;
+                if !edits.is_empty() {
+                    self.apply_changes(HashMap::from([(uri, edits)])).await?;
+                }
+            }
+            _ => {
+                return Err(jsonrpc::Error::invalid_params(format!(
+                    "Unknown command {}",
+                    params.command
+                )));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Applies settings changed at runtime, then re-checks open documents.
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        self.trace_request("workspace/didChangeConfiguration", &params)
+            .await;
+
+        // clients that support pulling settings may send none and expect them to be pulled
+        if params.settings.is_null() && self.state.settings.read().unwrap().configuration_support {
+            self.pull_configuration().await;
+            return;
+        }
+
+        // clients either send the `codetypo` section or the settings without a section
+        let settings = match params.settings.get("codetypo") {
+            Some(settings) => settings,
+            None => &params.settings,
+        };
+        self.state
+            .settings
+            .write()
+            .unwrap()
+            .apply_settings(settings);
+        self.reload_config().await;
+    }
+
+    /// Reloads the config when a watched config file is created, changed, or deleted.
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        self.trace_request("workspace/didChangeWatchedFiles", &params)
+            .await;
+
+        self.reload_config().await;
+    }
+
+    /// Reloads the config when a config file is created, eg: by the user in the editor.
+    async fn did_create_files(&self, params: CreateFilesParams) {
+        self.trace_request("workspace/didCreateFiles", &params)
+            .await;
+
+        if self.is_config_file(params.files.iter().map(|file| file.uri.as_str())) {
+            self.reload_config().await;
+        }
+    }
+
+    /// Reloads the config when a config file is deleted.
+    async fn did_delete_files(&self, params: DeleteFilesParams) {
+        self.trace_request("workspace/didDeleteFiles", &params)
+            .await;
+
+        if self.is_config_file(params.files.iter().map(|file| file.uri.as_str())) {
+            self.reload_config().await;
+        }
+    }
+
+    /// Handles workspace folder changes.
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        self.trace_request("workspace/didChangeWorkspaceFolders", &params)
+            .await;
+
+        let progress = self
+            .begin_progress("Updating workspace folders", None)
+            .await;
+        if let Err(e) = self
+            .state
+            .update_workspace_folders(params.event.added, params.event.removed)
+        {
             tracing::warn!("Cannot update workspace folders {}", e);
         }
+        progress.end().await;
+        if self.state.settings.read().unwrap().watched_files_support {
+            self.watch_config_files().await;
+        }
     }
 
     /// Handles LSP shutdown request.
@@ -242,13 +2111,448 @@ impl LanguageServer for Backend<'static, 'static> {
     }
 }
 
-impl<'s> Backend<'s, '_> {
+impl Backend<'static> {
     /// Constructs a new `Backend` with the given LSP client.
     pub fn new(client: Client) -> Self {
         Self {
             client,
-            state: Mutex::new(BackendState::default()),
+            state: Arc::new(BackendState::default()),
             default_policy: policy::Policy::default(),
+            started: std::time::Instant::now(),
+        }
+    }
+
+    /// Constructs the LSP service, including the custom `codetypo/*` methods.
+    pub fn service() -> (LspService<Self>, ClientSocket) {
+        LspService::build(Self::new)
+            .custom_method("codetypo/effectiveConfig", Self::effective_config)
+            .custom_method("codetypo/ping", Self::ping)
+            .custom_method("codetypo/summary", Self::summary)
+            .custom_method("$/setTrace", Self::set_trace)
+            .finish()
+    }
+
+    /// Handles the `codetypo/effectiveConfig` request, which returns the config used to check a
+    /// file, eg: to debug why a word isn't ignored.
+    async fn effective_config(
+        &self,
+        params: EffectiveConfigParams,
+    ) -> jsonrpc::Result<EffectiveConfig> {
+        tracing::debug!("effective_config: {:?}", params);
+
+        let uri = self.state.policy_uri(&params.text_document.uri);
+        let path = self.state.file_path(&uri).map(|path| canonicalize(&path));
+        let instance = path
+            .as_deref()
+            .and_then(|path| self.state.workspace.read().unwrap().instance_at(path));
+        let (Some(path), Some(instance)) = (path, instance) else {
+            // the default policy applies
+            return Ok(EffectiveConfig {
+                config_files: Vec::new(),
+                word_lists: Vec::new(),
+                excluded: false,
+                file_type: None,
+                ignore_regexes: Vec::new(),
+                config: codetypo_cli::config::Config::default(),
+            });
+        };
+
+        let path = crate::state::instance_path(&instance, &path);
+        let policy = instance.engine().policy(&path);
+        Ok(EffectiveConfig {
+            config_files: instance.config_files.clone(),
+            word_lists: instance.word_lists.clone(),
+            excluded: instance.ignores.matched(&path, false).is_ignore(),
+            file_type: policy.file_type.map(str::to_string),
+            ignore_regexes: policy
+                .ignore
+                .iter()
+                .map(|re| re.as_str().to_string())
+                .collect(),
+            config: instance.config.clone(),
+        })
+    }
+
+    /// Handles the `$/setTrace` notification, which changes how much is sent to the client via
+    /// `$/logTrace`, eg: when tracing is turned on in the editor.
+    async fn set_trace(&self, params: SetTraceParams) {
+        tracing::debug!("set_trace: {:?}", params);
+        self.state.settings.write().unwrap().trace = params.value;
+    }
+
+    /// Logs the handling of a request or notification, and sends a `$/logTrace` notification
+    /// about it when the client turned on tracing, with its params when tracing is verbose.
+    async fn trace_request(&self, method: &str, params: &impl serde::Serialize) {
+        let params = to_string(params).unwrap_or_default();
+        tracing::debug!("{}: {}", method, params);
+        let trace = self.state.settings.read().unwrap().trace;
+        if trace == TraceValue::Off {
+            return;
+        }
+        self.client
+            .send_notification::<notification::LogTrace>(LogTraceParams {
+                message: format!("Handling {}", method),
+                verbose: (trace == TraceValue::Verbose).then_some(params),
+            })
+            .await;
+    }
+
+    /// Handles the `codetypo/summary` request, which checks every file in the workspace and
+    /// returns the number of typos per file, in total, and the most frequent misspellings, eg: for
+    /// an extension to show a report.
+    ///
+    /// Open documents are counted in their unsaved text.
+    async fn summary(&self, params: SummaryParams) -> jsonrpc::Result<Summary> {
+        tracing::debug!("summary: {:?}", params);
+
+        let mut progress = self.begin_progress("Summarizing typos", None).await;
+        let files = self.workspace_files_with_text();
+        let total = files.len();
+        let mut checked = self.check_files(files);
+        let mut summary = Summary {
+            total: 0,
+            files: Vec::new(),
+            top_typos: Vec::new(),
+        };
+        let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+        let mut i = 0;
+        while let Some(file) = checked.next().await {
+            progress.report(i, total).await;
+            i += 1;
+            let typos = file.typos.unwrap_or_default();
+            if typos.is_empty() {
+                continue;
+            }
+            summary.total += typos.len();
+            summary.files.push(FileSummary {
+                uri: file.uri,
+                typos: typos.len(),
+            });
+            let mut seen = HashSet::new();
+            for (_, typo) in typos {
+                let (count, files) = counts.entry(typo.typo.to_string()).or_default();
+                *count += 1;
+                if seen.insert(typo.typo) {
+                    *files += 1;
+                }
+            }
+        }
+        progress.end().await;
+
+        summary.files.sort_by(|a, b| a.uri.cmp(&b.uri));
+        let mut top_typos: Vec<_> = counts
+            .into_iter()
+            .map(|(typo, (count, files))| TypoSummary { typo, count, files })
+            .collect();
+        top_typos.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.typo.cmp(&b.typo)));
+        top_typos.truncate(params.top.unwrap_or(DEFAULT_SUMMARY_TOP));
+        summary.top_typos = top_typos;
+        Ok(summary)
+    }
+
+    /// Handles the `codetypo/ping` request, which returns the health of the server, eg: for an
+    /// extension to diagnose a server that stopped responding to other requests.
+    async fn ping(&self) -> jsonrpc::Result<Ping> {
+        tracing::debug!("ping");
+
+        let (workspace_folders, routed_workspace_folders, config_files) = {
+            let workspace = self.state.workspace.read().unwrap();
+            let routed = workspace
+                .folders
+                .iter()
+                .filter(|folder| workspace.instance(&folder.uri).is_some())
+                .count();
+            (
+                workspace.folders.len(),
+                routed,
+                workspace.referenced_files.clone(),
+            )
+        };
+        Ok(Ping {
+            version: env!("CARGO_PKG_VERSION"),
+            uptime_secs: self.started.elapsed().as_secs(),
+            workspace_folders,
+            routed_workspace_folders,
+            config_files,
+            open_documents: self.state.documents.lock().unwrap().len(),
+            memory: MemoryStats::read(),
+        })
+    }
+
+    /// Requests the `codetypo` settings section from the client, then re-checks open documents.
+    async fn pull_configuration(&self) {
+        let items = vec![ConfigurationItem {
+            scope_uri: None,
+            section: Some("codetypo".to_string()),
+        }];
+        match self.client.configuration(items).await {
+            Ok(values) => {
+                if let Some(settings) = values.first() {
+                    self.state
+                        .settings
+                        .write()
+                        .unwrap()
+                        .apply_settings(settings);
+                }
+                self.reload_config().await;
+            }
+            Err(e) => tracing::warn!("Cannot get workspace configuration: {}", e),
+        }
+    }
+
+    /// Returns the names of the config files looked for in the workspace, including those in the
+    /// `configFileNames` setting.
+    fn config_file_names(&self) -> Vec<String> {
+        let settings = self.state.settings.read().unwrap();
+        codetypo_cli::config::SUPPORTED_FILE_NAMES
+            .iter()
+            .map(|name| name.to_string())
+            .chain(settings.config_file_names.iter().cloned())
+            .collect()
+    }
+
+    /// Whether any of the created or deleted file `uris` is a config file, so the config must
+    /// be reloaded.
+    fn is_config_file<'u>(&self, mut uris: impl Iterator<Item = &'u str>) -> bool {
+        let names = self.config_file_names();
+        uris.any(|uri| {
+            Url::parse(uri)
+                .ok()
+                .and_then(|uri| uri.to_file_path().ok())
+                .and_then(|path| {
+                    path.file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                })
+                .is_some_and(|name| names.contains(&name))
+        })
+    }
+
+    /// Asks the client to notify the server of changes to config files, so they take effect
+    /// without restarting the server.
+    ///
+    /// Besides the config files in the workspace, this watches the files the config was read
+    /// from outside of it, eg: in a parent of the workspace folder. The files are watched again
+    /// whenever they change, eg: after the config is reloaded.
+    async fn watch_config_files(&self) {
+        let names = self.config_file_names();
+        let paths = {
+            let settings = self.state.settings.read().unwrap();
+            [settings.config.clone(), Some(settings.user_dictionary())]
+        };
+        let referenced_files = {
+            let workspace = self.state.workspace.read().unwrap();
+            // config files in the workspace are already watched by name
+            workspace
+                .referenced_files
+                .iter()
+                .filter(|path| {
+                    workspace.workspace_folder(path).is_none()
+                        || !path
+                            .file_name()
+                            .is_some_and(|name| names.contains(&name.to_string_lossy().to_string()))
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let mut patterns = names
+            .iter()
+            .map(|name| format!("**/{}", name))
+            .collect::<Vec<_>>();
+        for path in paths.into_iter().flatten().chain(referenced_files) {
+            let pattern = path.to_string_lossy().to_string();
+            if !patterns.contains(&pattern) {
+                patterns.push(pattern);
+            }
+        }
+
+        // nothing is watched when watching is turned off
+        if self.capability_disabled(WATCHED_FILES) {
+            patterns.clear();
+        }
+
+        let previous = {
+            let mut watched = self.state.watched_files.lock().unwrap();
+            if *watched == patterns {
+                return;
+            }
+            std::mem::replace(&mut *watched, patterns.clone())
+        };
+        if !previous.is_empty() {
+            let unregistration = Unregistration {
+                id: "codetypo-config-files".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+            };
+            if let Err(e) = self
+                .client
+                .unregister_capability(vec![unregistration])
+                .await
+            {
+                tracing::warn!("Cannot stop watching config files: {}", e);
+            }
+        }
+        if patterns.is_empty() {
+            return;
+        }
+
+        let watchers = patterns
+            .into_iter()
+            .map(|pattern| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(pattern),
+                kind: None,
+            })
+            .collect();
+
+        let registration = Registration {
+            id: "codetypo-config-files".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: Some(json!(DidChangeWatchedFilesRegistrationOptions { watchers })),
+        };
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            tracing::warn!("Cannot watch config files: {}", e);
+        }
+    }
+
+    /// Rebuilds the router so config file changes take effect, then re-checks open documents.
+    pub(crate) async fn reload_config(&self) {
+        let progress = self.begin_progress("Reloading config", None).await;
+        self.state.reloading.store(true, Ordering::Relaxed);
+        self.send_status().await;
+        if let Err(e) = self.state.update_router() {
+            tracing::warn!("Cannot reload config: {}", e);
+        }
+        self.state.reloading.store(false, Ordering::Relaxed);
+        self.send_status().await;
+        progress.end().await;
+        if self.state.settings.read().unwrap().watched_files_support {
+            self.watch_config_files().await;
+        }
+        self.register_capabilities().await;
+        self.refresh_diagnostics().await;
+    }
+
+    /// Whether the `disabledCapabilities` setting turns off a capability.
+    fn capability_disabled(&self, name: &str) -> bool {
+        self.state
+            .settings
+            .read()
+            .unwrap()
+            .disabled_capabilities
+            .contains(name)
+    }
+
+    /// Registers the [`DYNAMIC_CAPABILITIES`] the client supports registering dynamically, unless
+    /// they're turned off, and unregisters those that were turned off since they were registered.
+    async fn register_capabilities(&self) {
+        let (registrations, unregistrations) = {
+            let settings = self.state.settings.read().unwrap();
+            let mut registered = self.state.registered_capabilities.lock().unwrap();
+            let mut registrations = Vec::new();
+            let mut unregistrations = Vec::new();
+            for capability in DYNAMIC_CAPABILITIES.iter().filter(|capability| {
+                settings
+                    .dynamic_registration_support
+                    .contains(capability.method)
+            }) {
+                if !settings.disabled_capabilities.contains(capability.name) {
+                    if registered.insert(capability.method) {
+                        registrations.push(capability.registration());
+                    }
+                } else if registered.remove(capability.method) {
+                    unregistrations.push(Unregistration {
+                        id: capability.registration().id,
+                        method: capability.method.to_string(),
+                    });
+                }
+            }
+            (registrations, unregistrations)
+        };
+
+        if !unregistrations.is_empty() {
+            if let Err(e) = self.client.unregister_capability(unregistrations).await {
+                tracing::warn!("Cannot unregister capabilities: {}", e);
+            }
+        }
+        if !registrations.is_empty() {
+            if let Err(e) = self.client.register_capability(registrations).await {
+                tracing::warn!("Cannot register capabilities: {}", e);
+            }
+        }
+    }
+
+    /// Sends a [`CodetypoServerState`] notification when the state or the number of queued
+    /// documents changed since the last one, if the client opted in to them.
+    async fn send_server_state(&self) {
+        if !self
+            .state
+            .settings
+            .read()
+            .unwrap()
+            .server_state_notification_support
+        {
+            return;
+        }
+        let (state, queued) = self.state.server_state();
+        {
+            let mut sent = self.state.sent_server_state.lock().unwrap();
+            if *sent == Some((state, queued)) {
+                return;
+            }
+            *sent = Some((state, queued));
+        }
+        self.client
+            .send_notification::<CodetypoServerState>(CodetypoServerStateParams { state, queued })
+            .await;
+    }
+
+    /// Sends a [`CodetypoStatus`] notification, if the client opted in to them, and a
+    /// [`CodetypoServerState`] notification if the server state changed.
+    async fn send_status(&self) {
+        self.send_server_state().await;
+        if !self
+            .state
+            .settings
+            .read()
+            .unwrap()
+            .status_notification_support
+        {
+            return;
+        }
+        let reloading = self.state.reloading.load(Ordering::Relaxed);
+        let documents = self.state.typo_counts.lock().unwrap().clone();
+        let params = CodetypoStatusParams {
+            busy: reloading || self.state.checking(),
+            reloading,
+            typos: documents.values().sum(),
+            documents,
+        };
+        self.client
+            .send_notification::<CodetypoStatus>(params)
+            .await;
+    }
+
+    /// Re-checks and reports diagnostics for all open documents.
+    pub(crate) async fn refresh_diagnostics(&self) {
+        // the config or settings changed, so the published diagnostics may be stale
+        self.state.published.lock().unwrap().clear();
+        if self.state.settings.read().unwrap().paused {
+            // clear the diagnostics of scanned files, those of open documents are cleared below
+            let scanned = std::mem::take(&mut *self.state.scanned.lock().unwrap());
+            for uri in scanned {
+                if !self.state.documents.lock().unwrap().contains_key(&uri) {
+                    self.client.publish_diagnostics(uri, Vec::new(), None).await;
+                }
+            }
+        }
+        let documents = self
+            .state
+            .documents
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        for document in documents {
+            self.report_diagnostics(document).await;
         }
     }
 
@@ -258,10 +2562,67 @@ impl<'s> Backend<'s, '_> {
     ///
     /// * `params`: The text document to report diagnostics for.
     pub async fn report_diagnostics(&self, params: TextDocumentItem) {
-        let diagnostics = self.check_text(&params.text, &params.uri);
+        let hash = {
+            let mut hasher = DefaultHasher::new();
+            // the language decides how the text is checked, eg: with `syntaxAware`
+            (&params.language_id, &params.text).hash(&mut hasher);
+            hasher.finish()
+        };
+        let published = self
+            .state
+            .published
+            .lock()
+            .unwrap()
+            .get(&params.uri)
+            .filter(|published| published.version == params.version && published.hash == hash)
+            .map(|published| published.diagnostics.clone());
+        if let Some(diagnostics) = published {
+            tracing::debug!(
+                "report_diagnostics: Reusing diagnostics of {} version {}",
+                params.uri,
+                params.version
+            );
+            self.client
+                .publish_diagnostics(params.uri, diagnostics, Some(params.version))
+                .await;
+            return;
+        }
+
+        let cancelled = self.state.start_check(&params.uri);
+        self.send_status().await;
+        let typos = self
+            .check_typos_with(&params.text, &params.uri, cancelled.clone())
+            .await;
+        // don't publish stale diagnostics when a newer version has superseded this one
+        if !self.state.finish_check(&params.uri, &cancelled) {
+            tracing::debug!(
+                "report_diagnostics: Cancelled check of {} version {}",
+                params.uri,
+                params.version
+            );
+            self.send_status().await;
+            return;
+        }
+        self.state
+            .typo_counts
+            .lock()
+            .unwrap()
+            .insert(params.uri.clone(), typos.len());
+        let skipped = CheckRequest::new(&self.state, &params.uri)
+            .and_then(|request| request.skipped(&params.text));
+        let diagnostics = self.document_diagnostics(typos, &params.uri, skipped);
+        self.state.published.lock().unwrap().insert(
+            params.uri.clone(),
+            PublishedDiagnostics {
+                version: params.version,
+                hash,
+                diagnostics: diagnostics.clone(),
+            },
+        );
         self.client
             .publish_diagnostics(params.uri, diagnostics, Some(params.version))
             .await;
+        self.send_status().await;
     }
 
     /// Checks the given text for typos and returns diagnostics.
@@ -270,37 +2631,164 @@ impl<'s> Backend<'s, '_> {
     ///
     /// * `buffer`: The text to check for typos.
     /// * `uri`: The URI of the text document.
-    pub fn check_text(&self, buffer: &str, uri: &Url) -> Vec<Diagnostic> {
-        let state = self.state.lock().unwrap();
+    pub async fn check_text(&self, buffer: &str, uri: &Url) -> Vec<Diagnostic> {
+        let typos = self.check_typos(buffer, uri).await;
+        let skipped =
+            CheckRequest::new(&self.state, uri).and_then(|request| request.skipped(buffer));
+        self.document_diagnostics(typos, uri, skipped)
+    }
+
+    /// Converts the typos of a document into diagnostics, adding those of its path when enabled,
+    /// and the one explaining what was `skipped`, if any.
+    fn document_diagnostics(
+        &self,
+        typos: Vec<(Range, codetypo::Typo<'static>)>,
+        uri: &Url,
+        skipped: Option<Diagnostic>,
+    ) -> Vec<Diagnostic> {
+        let mut diagnostics = self.diagnostics(typos, uri);
+        if self.state.settings.read().unwrap().check_file_names {
+            diagnostics.extend(self.file_name_diagnostics(uri));
+        }
+        diagnostics.extend(skipped);
+        diagnostics
+    }
+
+    /// Checks the name of the file at `uri` and of its directories in the workspace folder,
+    /// returning diagnostics at the top of the file.
+    ///
+    /// The diagnostics have no data, as they can't be fixed by editing the file.
+    fn file_name_diagnostics(&self, uri: &Url) -> Vec<Diagnostic> {
+        if self.state.settings.read().unwrap().paused {
+            return Vec::new();
+        }
+        let Some(relative_path) = self.state.file_path(uri).and_then(|path| {
+            let folder = self.state.workspace_folder(&path)?;
+            let relative_path = path.strip_prefix(folder).ok()?;
+            Some(
+                relative_path
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            )
+        }) else {
+            return Vec::new();
+        };
 
-        let Some((tokenizer, dict, ignore)) = self.workspace_policy(uri, &state) else {
+        let filters = self.state.settings.read().unwrap().filters();
+        let policy_uri = self.state.policy_uri(uri);
+        let path = self
+            .state
+            .file_path(&policy_uri)
+            .map(|path| canonicalize(&path));
+        let instance = path
+            .as_deref()
+            .and_then(|path| self.state.workspace.read().unwrap().instance_at(path));
+        let default_policy = (
+            self.default_policy.tokenizer,
+            self.default_policy.dict,
+            Cow::Borrowed(self.default_policy.ignore),
+        );
+        let Some((tokenizer, dict, ignore)) =
+            Self::workspace_policy(path.as_deref(), instance.as_deref(), None, default_policy)
+        else {
             // skip file because it matches extend-exclude
-            return Vec::default();
+            return Vec::new();
         };
+        let typos = crate::codetypo::check_str(
+            &relative_path,
+            std::iter::once(0..relative_path.len()).collect(),
+            tokenizer,
+            dict,
+            &ignore,
+            filters,
+        )
+        .map(|(typo, _, _)| (Range::default(), typo.into_owned()))
+        .collect();
 
-        crate::codetypo::check_str(buffer, tokenizer, dict, ignore)
-            .map(|(typo, line_num, line_pos)| {
+        self.diagnostics(typos, uri)
+            .into_iter()
+            .map(|diagnostic| Diagnostic {
+                message: format!("{} in the path `{}`", diagnostic.message, relative_path),
+                data: None,
+                ..diagnostic
+            })
+            .collect()
+    }
+
+    /// Converts typos into diagnostics, using the configured severities.
+    ///
+    /// Typos defined by a config file are related to the config entry, so users can jump to it,
+    /// when the client supports related information.
+    fn diagnostics(
+        &self,
+        typos: Vec<(Range, codetypo::Typo<'static>)>,
+        uri: &Url,
+    ) -> Vec<Diagnostic> {
+        let related_information_support = self
+            .state
+            .settings
+            .read()
+            .unwrap()
+            .related_information_support;
+        let rules = self
+            .state
+            .file_path(uri)
+            .filter(|_| related_information_support && !typos.is_empty())
+            .map(|path| self.word_rules(&path));
+        let code_description = Url::parse(TYPO_HREF)
+            .ok()
+            .map(|href| CodeDescription { href });
+        let language_severity = self
+            .state
+            .language_settings(uri)
+            .and_then(|language| language.severity);
+        let (severity, disallowed_severity, max_corrections) = {
+            let settings = self.state.settings.read().unwrap();
+            let severity = language_severity.or(settings.severity);
+            (
+                severity,
+                settings.disallowed_severity.or(severity),
+                settings.max_corrections_in_message,
+            )
+        };
+
+        typos
+            .into_iter()
+            .map(|(range, typo)| {
                 Diagnostic {
-                    range: Range::new(
-                        Position::new(line_num as u32, line_pos as u32),
-                        Position::new(line_num as u32, (line_pos + typo.typo.len()) as u32),
-                    ),
-                    severity: state.severity,
-                    source: Some("codetypo".to_string()),
-                    message: match &typo.corrections {
-                        codetypo::Status::Invalid => format!("`{}` is disallowed", typo.typo),
-                        codetypo::Status::Corrections(corrections) => format!(
-                            "`{}` should be {}",
-                            typo.typo,
-                            itertools::join(corrections.iter().map(|s| format!("`{}`", s)), ", ")
-                        ),
-                        codetypo::Status::Valid => panic!("unexpected codetypo::Status::Valid"),
+                    range,
+                    severity: match typo.corrections {
+                        codetypo::Status::Invalid => disallowed_severity,
+                        _ => severity,
                     },
-                    // store corrections for retrieval during code_action
+                    // the misspelled word, so clients can filter diagnostics by it
+                    code: Some(NumberOrString::String(typo.typo.to_string())),
+                    code_description: code_description.clone(),
+                    related_information: rules
+                        .as_ref()
+                        .and_then(|rules| rules.find(&typo.typo))
+                        .and_then(|rule| {
+                            Some(vec![DiagnosticRelatedInformation {
+                                location: Location::new(
+                                    self.state.file_uri(&rule.path)?,
+                                    rule.range,
+                                ),
+                                message: format!(
+                                    "`{}` is defined in `[{}]`",
+                                    typo.typo, rule.table
+                                ),
+                            }])
+                        }),
+                    source: Some("codetypo".to_string()),
+                    message: message(&typo, max_corrections),
+                    // store all the corrections, even those left out of the message, for retrieval during code_action
                     data: match typo.corrections {
-                        codetypo::Status::Corrections(corrections) => {
-                            Some(json!(DiagnosticData { corrections }))
-                        }
+                        codetypo::Status::Corrections(corrections) => Some(json!(DiagnosticData {
+                            typo: typo.typo,
+                            corrections
+                        })),
                         _ => None,
                     },
                     ..Diagnostic::default()
@@ -309,69 +2797,666 @@ impl<'s> Backend<'s, '_> {
             .collect()
     }
 
-    /// Determines the workspace policy (tokenizer, dictionary, ignore rules) for a given URI.
+    /// Checks the given text for typos and returns each typo with its range.
+    ///
+    /// Checking runs on a blocking thread, so large buffers don't stall the other handlers.
     ///
     /// # Parameters
     ///
-    /// * `uri`: The URI to determine the workspace policy for.
-    /// * `state`: The current state of the backend.
-    pub(crate) fn workspace_policy<'a>(
-        &'a self,
+    /// * `buffer`: The text to check for typos.
+    /// * `uri`: The URI of the text document.
+    pub(crate) async fn check_typos(
+        &self,
+        buffer: &str,
+        uri: &Url,
+    ) -> Vec<(Range, codetypo::Typo<'static>)> {
+        self.check_typos_with(buffer, uri, Arc::default()).await
+    }
+
+    /// Checks the given text for typos like [`Backend::check_typos`], stopping early once
+    /// `cancelled` is set.
+    async fn check_typos_with(
+        &self,
+        buffer: &str,
         uri: &Url,
-        state: &'a std::sync::MutexGuard<'a, BackendState<'s>>,
-    ) -> Option<(
-        &'a codetypo::tokens::Tokenizer,
-        &'a dyn codetypo::Dictionary,
-        &'a [regex::Regex],
-    )> {
-        let (tokenizer, dict, ignore) = match uri.to_file_path() {
-            Err(_) => {
-                // eg: uris like untitled:* or term://*
-                tracing::debug!(
-                    "workspace_policy: Using default policy because cannot convert uri {} to file path",
-                    uri
+        cancelled: Arc<AtomicBool>,
+    ) -> Vec<(Range, codetypo::Typo<'static>)> {
+        let Some(request) = CheckRequest::new(&self.state, uri) else {
+            return Vec::default();
+        };
+        let state = self.state.clone();
+        let buffer = buffer.to_string();
+
+        let task = tokio::task::spawn_blocking(move || request.run(&state, &buffer, &cancelled));
+
+        task.await.unwrap_or_else(|e| {
+            tracing::error!("check_typos: {}", e);
+            Vec::default()
+        })
+    }
+
+    /// Checks files in parallel on a pool of threads, limited by the `concurrency` setting, so
+    /// scanning a large workspace isn't bound to a single core.
+    ///
+    /// Each file is checked with its open document's text, if any, or else read from disk, and is
+    /// received in order from the returned [`CheckedFiles`]. Large files are memory mapped and
+    /// checked in place.
+    fn check_files(&self, files: Vec<(Url, Option<String>)>) -> CheckedFiles {
+        let concurrency = self.state.settings.read().unwrap().concurrency;
+        let requests = files
+            .into_iter()
+            .map(|(uri, text)| (CheckRequest::new(&self.state, &uri), uri, text))
+            .collect::<Vec<_>>();
+        let state = self.state.clone();
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        tokio::task::spawn_blocking(move || {
+            let pool = match state.check_pool(concurrency) {
+                Ok(pool) => pool,
+                Err(e) => {
+                    tracing::error!("Cannot create thread pool: {}", e);
+                    return;
+                }
+            };
+            let cancelled = AtomicBool::new(false);
+            pool.install(|| {
+                // stops once the receiver is dropped, eg: when the request is cancelled, as the
+                // results are no longer needed
+                let _ = requests.into_par_iter().enumerate().try_for_each(
+                    |(i, (request, uri, text))| {
+                        if sender.is_closed() {
+                            cancelled.store(true, Ordering::Relaxed);
+                            return Err(());
+                        }
+                        let text = match text {
+                            Some(text) => Some(FileText::Read(text)),
+                            None => state.file_path(&uri).and_then(|path| FileText::open(&path)),
+                        };
+                        // skip files that can't be read or decoded
+                        let text = text.as_ref().and_then(FileText::as_str);
+                        let typos = text.map(|text| {
+                            request.as_ref().map_or_else(Vec::new, |request| {
+                                request.run(&state, text, &cancelled)
+                            })
+                        });
+                        let skipped = request
+                            .as_ref()
+                            .zip(text)
+                            .and_then(|(request, text)| request.skipped(text));
+                        let file = CheckedFile {
+                            uri,
+                            typos,
+                            skipped,
+                        };
+                        sender
+                            .send((i, file))
+                            .map_err(|_| cancelled.store(true, Ordering::Relaxed))
+                    },
                 );
-                (
-                    self.default_policy.tokenizer,
-                    self.default_policy.dict,
-                    self.default_policy.ignore,
-                )
+            });
+        });
+
+        CheckedFiles {
+            receiver,
+            pending: BTreeMap::new(),
+            next: 0,
+        }
+    }
+
+    /// Returns the parsed config files that apply to `path`, which are parsed once per directory
+    /// until the config is reloaded.
+    fn word_rules(&self, path: &Path) -> Arc<WordRules> {
+        let dir = path.parent().unwrap_or(path);
+        let generation = self.state.generation.load(Ordering::Relaxed);
+        {
+            let (cached, rules) = &*self.state.word_rules.lock().unwrap();
+            if let Some(rules) = rules.get(dir).filter(|_| *cached == generation) {
+                return rules.clone();
             }
-            Ok(path) => {
-                let uri_path = url_path_sanitised(uri);
+        }
 
-                // find relevant tokenizer, and dict for the workspace folder
-                let (tokenizer, dict, ignore) = match state.router.at(&uri_path) {
-                    Err(_) => {
-                        // ie: file:///
-                        tracing::debug!(
-                            "workspace_policy: Using default policy because no route found for {}",
-                            uri_path
-                        );
-                        (
-                            self.default_policy.tokenizer,
-                            self.default_policy.dict,
-                            self.default_policy.ignore,
-                        )
-                    }
-                    Ok(Match { value, params: _ }) => {
-                        tracing::debug!("workspace_policy: path {}", &path.display());
-                        // skip file if matches extend-exclude
-                        if value.ignores.matched(&path, false).is_ignore() {
-                            tracing::debug!(
-                                "workspace_policy: Ignoring {} because it matches extend-exclude.",
-                                uri
-                            );
-                            return None;
-                        }
-                        let policy = value.engine.policy(&path);
-                        (policy.tokenizer, policy.dict, policy.ignore)
+        let encoding = self.state.settings.read().unwrap().position_encoding;
+        let loaded = Arc::new(WordRules::load(&self.config_files(path), encoding));
+        let (cached, rules) = &mut *self.state.word_rules.lock().unwrap();
+        if *cached != generation {
+            *cached = generation;
+            rules.clear();
+        }
+        rules.insert(dir.to_path_buf(), loaded.clone());
+        loaded
+    }
+
+    /// Returns the config files that apply to `path`, in order of precedence.
+    fn config_files(&self, path: &std::path::Path) -> Vec<PathBuf> {
+        let (config_names, mut configs) = {
+            let settings = self.state.settings.read().unwrap();
+            (settings.config_file_names.clone(), settings.configs())
+        };
+        configs.reverse();
+        configs.extend(config_names.iter().rev().filter_map(|name| {
+            path.ancestors()
+                .skip(1)
+                .map(|dir| dir.join(name))
+                .find(|config_path| config_path.is_file())
+        }));
+        configs.extend(crate::config::project_configs(path));
+        configs
+    }
+
+    /// Checks an open document for typos, returning an empty list if the document isn't open.
+    async fn document_typos(&self, uri: &Url) -> Vec<(Range, codetypo::Typo<'static>)> {
+        let Some(text) = self
+            .state
+            .documents
+            .lock()
+            .unwrap()
+            .get(uri)
+            .map(|document| document.text.clone())
+        else {
+            return Vec::default();
+        };
+
+        self.check_typos(&text, uri).await
+    }
+
+    /// Returns words similar to `typo` other than its `corrections`, from the dictionary and the
+    /// words accepted by the config of the document at `uri`, and the words of the document that
+    /// aren't typos.
+    fn more_suggestions(
+        &self,
+        uri: &Url,
+        typo: &str,
+        corrections: &[impl AsRef<str>],
+        document_typos: &[(Range, codetypo::Typo<'static>)],
+    ) -> Vec<String> {
+        let policy_uri = self.state.policy_uri(uri);
+        let path = self
+            .state
+            .file_path(&policy_uri)
+            .map(|path| canonicalize(&path));
+        let instance = path
+            .as_deref()
+            .and_then(|path| self.state.workspace.read().unwrap().instance_at(path));
+        // the dictionary of an excluded file is still the best source of words
+        let dict = Self::workspace_policy(
+            path.as_deref(),
+            instance.as_deref(),
+            None,
+            (
+                self.default_policy.tokenizer,
+                self.default_policy.dict,
+                Cow::Borrowed(self.default_policy.ignore),
+            ),
+        )
+        .map_or(self.default_policy.dict, |(_, dict, _)| dict);
+        let dictionary_words = crate::suggest::dictionary_words(typo, dict);
+
+        let text = self
+            .state
+            .documents
+            .lock()
+            .unwrap()
+            .get(uri)
+            .map(|document| document.text.clone())
+            .unwrap_or_default();
+        let typos = document_typos
+            .iter()
+            .map(|(_, typo)| typo.typo.as_ref())
+            .collect::<HashSet<_>>();
+        let document_words = text
+            .split(|c: char| !c.is_alphabetic())
+            .filter(|word| !word.is_empty() && !typos.contains(word));
+
+        let vocabulary = instance
+            .iter()
+            .flat_map(|instance| instance.vocabulary.iter().map(String::as_str))
+            .chain(dictionary_words.iter().map(String::as_str))
+            .chain(document_words);
+        crate::suggest::suggestions(typo, vocabulary, corrections)
+    }
+
+    /// Returns the typo at `position` in an open document, if any.
+    async fn typo_at(
+        &self,
+        uri: &Url,
+        position: Position,
+    ) -> Option<(Range, codetypo::Typo<'static>)> {
+        self.document_typos(uri)
+            .await
+            .into_iter()
+            .find(|(range, _)| range.start <= position && position <= range.end)
+    }
+
+    /// Returns edits that replace every typo in an open document with its first correction.
+    async fn fix_all_edits(&self, uri: &Url) -> Vec<TextEdit> {
+        fix_all(&self.document_typos(uri).await)
+    }
+
+    /// Checks every file in the workspace folders that isn't open, and publishes diagnostics for
+    /// the files with typos, so they're shown alongside the open documents.
+    ///
+    /// Diagnostics published by the previous scan are cleared for files that no longer have typos.
+    async fn scan_workspace(&self, token: Option<ProgressToken>) {
+        let mut scanned = HashSet::new();
+        let mut progress = self.begin_progress("Scanning workspace", token).await;
+
+        // open documents are reported via report_diagnostics
+        let files = self.closed_workspace_files();
+        let total = files.len();
+        let mut checked = self.check_files(files);
+        let mut i = 0;
+        while let Some(file) = checked.next().await {
+            progress.report(i, total).await;
+            i += 1;
+            // skip files that can't be read or aren't valid UTF-8
+            let Some(typos) = file.typos else {
+                continue;
+            };
+
+            let diagnostics = self.document_diagnostics(typos, &file.uri, file.skipped);
+            if !diagnostics.is_empty() {
+                scanned.insert(file.uri.clone());
+                self.client
+                    .publish_diagnostics(file.uri, diagnostics, None)
+                    .await;
+            }
+        }
+
+        let previous = std::mem::replace(&mut *self.state.scanned.lock().unwrap(), scanned.clone());
+        for uri in previous.difference(&scanned) {
+            // don't clear the diagnostics of documents opened since
+            if self.state.documents.lock().unwrap().contains_key(uri) {
+                continue;
+            }
+            self.client
+                .publish_diagnostics(uri.clone(), Vec::new(), None)
+                .await;
+        }
+        progress.end().await;
+    }
+
+    /// Checks the names of every file in the workspace folders that isn't open, and of their
+    /// directories, and publishes diagnostics at the top of the files with typos in their path.
+    ///
+    /// Files reported by the last workspace scan keep their diagnostics, and like the scan, files
+    /// whose path no longer has typos are cleared.
+    async fn check_file_names(&self, token: Option<ProgressToken>) {
+        let mut scanned = HashSet::new();
+        let mut progress = self.begin_progress("Checking file names", token).await;
+
+        let previous = self.state.scanned.lock().unwrap().clone();
+        let paths = self.workspace_files();
+        let files = self.state.file_uris(&paths);
+        let total = files.len();
+        for (i, (path, uri)) in files.into_iter().enumerate() {
+            progress.report(i, total).await;
+            // open documents are reported via report_diagnostics
+            if self.state.documents.lock().unwrap().contains_key(&uri) {
+                continue;
+            }
+
+            let names = self.file_name_diagnostics(&uri);
+            if names.is_empty() && !previous.contains(&uri) {
+                continue;
+            }
+            // keep the diagnostics of the file's text published by the last scan
+            let text = FileText::open(path);
+            let mut diagnostics = match text.as_ref().and_then(FileText::as_str) {
+                Some(text) if previous.contains(&uri) => {
+                    let typos = self.check_typos(text, &uri).await;
+                    self.diagnostics(typos, &uri)
+                }
+                _ => Vec::new(),
+            };
+            diagnostics.extend(names);
+            if !diagnostics.is_empty() {
+                scanned.insert(uri.clone());
+            }
+            self.client
+                .publish_diagnostics(uri, diagnostics, None)
+                .await;
+        }
+
+        *self.state.scanned.lock().unwrap() = scanned;
+        progress.end().await;
+    }
+
+    /// Begins reporting the progress of a long running operation, using `token` when the client
+    /// provided one, or else creating one when the client supports it.
+    async fn begin_progress(&self, title: &str, token: Option<ProgressToken>) -> Progress<'_> {
+        let supported = self
+            .state
+            .settings
+            .read()
+            .unwrap()
+            .work_done_progress_support;
+        let token = match token {
+            Some(token) => Some(token),
+            None if supported => {
+                let token =
+                    ProgressToken::String(format!("codetypo/{}", self.state.next_progress_id()));
+                match self
+                    .client
+                    .send_request::<request::WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                        token: token.clone(),
+                    })
+                    .await
+                {
+                    Ok(()) => Some(token),
+                    Err(e) => {
+                        tracing::warn!("Cannot create work done progress: {}", e);
+                        None
                     }
-                };
+                }
+            }
+            None => None,
+        };
+
+        let progress = Progress {
+            client: &self.client,
+            token,
+            percentage: None,
+        };
+        progress
+            .notify(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_string(),
+                cancellable: Some(false),
+                ..WorkDoneProgressBegin::default()
+            }))
+            .await;
+        progress
+    }
+
+    /// Asks the client to apply `changes`, unless there are none.
+    async fn apply_changes(&self, changes: HashMap<Url, Vec<TextEdit>>) -> jsonrpc::Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        self.apply_edit(WorkspaceEdit {
+            changes: Some(changes),
+            ..WorkspaceEdit::default()
+        })
+        .await
+    }
+
+    /// Asks the client to apply `edit`, failing if it doesn't.
+    async fn apply_edit(&self, edit: WorkspaceEdit) -> jsonrpc::Result<()> {
+        match self.client.apply_edit(edit).await {
+            Ok(response) if response.applied => Ok(()),
+            Ok(response) => Err(internal_error(format!(
+                "Workspace edit was not applied: {}",
+                response.failure_reason.unwrap_or_default()
+            ))),
+            Err(e) => Err(internal_error(format!(
+                "Cannot apply workspace edit: {}",
+                e
+            ))),
+        }
+    }
+
+    /// Returns the edits `edits` makes of the typos of each file in the workspace, eg: fixing
+    /// every typo with a single correction. Files excluded by their config aren't edited.
+    ///
+    /// Open documents are edited using their unsaved text.
+    async fn workspace_edits(
+        &self,
+        edits: impl Fn(Vec<(Range, codetypo::Typo<'static>)>) -> Vec<TextEdit>,
+    ) -> HashMap<Url, Vec<TextEdit>> {
+        let mut changes = HashMap::new();
+
+        // open documents are edited in their current text
+        let files = self.workspace_files_with_text();
+        let mut checked = self.check_files(files);
+        while let Some(file) = checked.next().await {
+            let edits = edits(file.typos.unwrap_or_default());
+
+            if !edits.is_empty() {
+                changes.insert(file.uri, edits);
+            }
+        }
+
+        changes
+    }
+
+    /// Lists the files in the workspace folders, with the text of those that are open, while the
+    /// others are to be read from disk.
+    fn workspace_files_with_text(&self) -> Vec<(Url, Option<String>)> {
+        let paths = self.workspace_files();
+        let files = self.state.file_uris(&paths);
+        let documents = self.state.documents.lock().unwrap();
+        files
+            .into_iter()
+            .map(|(_, uri)| {
+                let open = documents.get(&uri).map(|document| document.text.clone());
+                (uri, open)
+            })
+            .collect()
+    }
+
+    /// Lists the files in the workspace folders that aren't open, to be read from disk.
+    fn closed_workspace_files(&self) -> Vec<(Url, Option<String>)> {
+        let paths = self.workspace_files();
+        let files = self.state.file_uris(&paths);
+        let documents = self.state.documents.lock().unwrap();
+        files
+            .into_iter()
+            .filter(|(_, uri)| !documents.contains_key(uri))
+            .map(|(_, uri)| (uri, None))
+            .collect()
+    }
+
+    /// Lists the files in the workspace folders, skipping hidden files and directories.
+    ///
+    /// Files matching `extend-exclude` are filtered later by [`Backend::workspace_policy`].
+    fn workspace_files(&self) -> Vec<PathBuf> {
+        let roots = {
+            let workspace = self.state.workspace.read().unwrap();
+            workspace
+                .folders
+                .iter()
+                .filter_map(|folder| {
+                    let root = workspace.path_mappings.file_path(&folder.uri)?;
+                    // a path inside the folder routes to the folder's instance
+                    let instance = workspace
+                        .router
+                        .at(&canonicalize(&root).join("codetypo.toml"))
+                        .cloned();
+                    Some((root, instance))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        roots
+            .iter()
+            .flat_map(|(root, instance)| match instance {
+                Some(instance) => instance.walk(root).build(),
+                None => ignore::WalkBuilder::new(root).build(),
+            })
+            .filter_map(|entry| match entry {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    tracing::warn!("workspace_files: {}", e);
+                    None
+                }
+            })
+            .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+            .map(|entry| entry.into_path())
+            .collect()
+    }
 
-                (tokenizer, dict, ignore)
+    /// Determines the workspace policy (tokenizer, dictionary, ignore rules) for a given file.
+    ///
+    /// # Parameters
+    ///
+    /// * `path`: The canonical path of the file on the server, or `None` if it isn't a file.
+    /// * `instance`: The Codetypo instance routed to for the file, if any.
+    /// * `language`: The settings overridden for the document's language, if any.
+    /// * `default_policy`: The policy used for URIs outside of the workspace folders.
+    pub(crate) fn workspace_policy<'a>(
+        path: Option<&Path>,
+        instance: Option<&'a Instance>,
+        language: Option<&LanguageSettings>,
+        default_policy: PolicyRefs<'a>,
+    ) -> Option<PolicyRefs<'a>> {
+        // the language's patterns are ignored as well as the config's
+        let with_language = |(tokenizer, dict, ignore): PolicyRefs<'a>| match language {
+            Some(language) if !language.extend_ignore_re.is_empty() => {
+                let mut ignore = ignore.into_owned();
+                ignore.extend(language.extend_ignore_re.iter().cloned());
+                (tokenizer, dict, Cow::Owned(ignore))
             }
+            _ => (tokenizer, dict, ignore),
         };
-        Some((tokenizer, dict, ignore))
+
+        let (Some(path), Some(instance)) = (path, instance) else {
+            return Some(with_language(default_policy));
+        };
+        // the instance's excludes are relative to the canonical path of its directory
+        let path = &crate::state::instance_path(instance, path);
+
+        tracing::debug!("workspace_policy: path {}", &path.display());
+        // skip file if matches extend-exclude
+        if instance.ignores.matched(path, false).is_ignore() {
+            tracing::debug!(
+                "workspace_policy: Ignoring {} because it matches extend-exclude.",
+                path.display()
+            );
+            return None;
+        }
+        let policy = instance.engine().policy(path);
+        Some(with_language((
+            policy.tokenizer,
+            policy.dict,
+            Cow::Borrowed(policy.ignore),
+        )))
+    }
+}
+
+/// Deserializes the arguments of an `workspace/executeCommand` request.
+fn parse_arguments<T: serde::de::DeserializeOwned>(
+    arguments: Vec<serde_json::Value>,
+) -> jsonrpc::Result<T> {
+    serde_json::from_value(serde_json::Value::Array(arguments))
+        .map_err(|e| jsonrpc::Error::invalid_params(e.to_string()))
+}
+
+/// Logs and returns an internal error with the given message.
+fn internal_error(message: String) -> jsonrpc::Error {
+    tracing::warn!("{}", message);
+    jsonrpc::Error {
+        message: message.into(),
+        ..jsonrpc::Error::internal_error()
+    }
+}
+
+/// Returns edits that replace every typo with its first correction.
+fn fix_all(document_typos: &[(Range, codetypo::Typo<'static>)]) -> Vec<TextEdit> {
+    document_typos
+        .iter()
+        .filter_map(|(range, typo)| match &typo.corrections {
+            codetypo::Status::Corrections(corrections) => Some(TextEdit {
+                range: *range,
+                new_text: corrections.first()?.to_string(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns true if code actions of `kind` were requested, ie: `kind` is in or a sub-kind of `only`.
+///
+/// When `only` is unspecified, returns `default`.
+fn is_requested(only: Option<&[CodeActionKind]>, kind: &CodeActionKind, default: bool) -> bool {
+    match only {
+        None => default,
+        Some(only) => only.iter().any(|requested| {
+            kind.as_str() == requested.as_str()
+                || kind
+                    .as_str()
+                    .strip_prefix(requested.as_str())
+                    .is_some_and(|rest| rest.starts_with('.'))
+        }),
+    }
+}
+
+/// Returns edits that fix the typos with a single correction, leaving ambiguous typos for the
+/// user to fix.
+fn unambiguous_fixes(typos: Vec<(Range, codetypo::Typo<'static>)>) -> Vec<TextEdit> {
+    typos
+        .into_iter()
+        .filter_map(|(range, typo)| match typo.corrections {
+            codetypo::Status::Corrections(corrections) if corrections.len() == 1 => {
+                Some(TextEdit {
+                    range,
+                    new_text: corrections[0].to_string(),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the data of `diag`, or if the client dropped it, the data of the diagnostic published
+/// at the same range.
+fn diag_data<'d>(
+    diag: &'d Diagnostic,
+    published: &'d [Diagnostic],
+) -> Option<&'d serde_json::Value> {
+    diag.data.as_ref().or_else(|| {
+        published
+            .iter()
+            .find(|published| published.range == diag.range && published.source == diag.source)
+            .and_then(|published| published.data.as_ref())
+    })
+}
+
+/// Converts a code action to a command, for clients that only accept commands as code actions.
+/// Actions with an edit apply it with the [`APPLY_WORKSPACE_EDIT`] command, and actions with
+/// neither an edit nor a command are dropped.
+fn command_action(action: CodeActionOrCommand) -> Option<CodeActionOrCommand> {
+    let CodeActionOrCommand::CodeAction(action) = action else {
+        return Some(action);
+    };
+    let command = match (action.edit, action.command) {
+        (Some(edit), _) => Command {
+            title: action.title,
+            command: APPLY_WORKSPACE_EDIT.to_string(),
+            arguments: Some(vec![json!(edit)]),
+        },
+        (None, Some(command)) => Command {
+            title: action.title,
+            ..command
+        },
+        (None, None) => return None,
+    };
+    Some(CodeActionOrCommand::Command(command))
+}
+
+/// Describes a typo and its corrections, eg: "`teh` should be `the`".
+///
+/// Only the first `max_corrections` corrections are listed, unless 0, followed by how many were
+/// left out, eg: "`fo` should be `of`, `to`, … and 3 more".
+pub(crate) fn message(typo: &codetypo::Typo, max_corrections: usize) -> String {
+    match &typo.corrections {
+        codetypo::Status::Invalid => format!("`{}` is disallowed", typo.typo),
+        codetypo::Status::Corrections(corrections) => {
+            let shown = match max_corrections {
+                0 => corrections.len(),
+                max => max.min(corrections.len()),
+            };
+            let mut message = format!(
+                "`{}` should be {}",
+                typo.typo,
+                itertools::join(
+                    corrections[..shown].iter().map(|s| format!("`{}`", s)),
+                    ", "
+                )
+            );
+            if shown < corrections.len() {
+                message.push_str(&format!(", … and {} more", corrections.len() - shown));
+            }
+            message
+        }
+        codetypo::Status::Valid => panic!("unexpected codetypo::Status::Valid"),
     }
 }
@@ -1,18 +1,389 @@
 //! Binary entry point for Codetypo-LSP. Initializes tracing and runs the LSP server.
+//!
+//! The server communicates over stdio, unless `--listen <addr:port>` is passed to serve it over
+//! TCP, `--ws <addr:port>` to serve it over WebSocket, or `--pipe <path>` to serve it over a Unix
+//! domain socket or, on Windows, a named pipe. See `--help` for all options.
 
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use codetypo_lsp::log_file::{RotatingFile, Rotation};
 use codetypo_lsp::lsp;
-use tower_lsp::{LspService, Server};
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+use tokio_tungstenite::tungstenite::Message;
+use tower_lsp::Server;
+
+/// Language server for the codetypo source code spell checker.
+#[derive(Debug, clap::Parser)]
+#[command(version, about)]
+struct Args {
+    /// Log level or filter directives, eg: `debug` or `codetypo_lsp=trace`. Overrides the
+    /// `RUST_LOG` environment variable.
+    #[arg(long, value_name = "LEVEL")]
+    log_level: Option<String>,
+
+    /// Write logs to this file instead of stderr.
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+
+    /// When to rotate the log file, renaming it so a new one is started.
+    #[arg(long, value_enum, default_value_t, requires = "log_file")]
+    log_rotation: Rotation,
+
+    /// Size in bytes the log file is rotated at, with `--log-rotation size`.
+    #[arg(long, value_name = "BYTES", default_value_t = 10 * 1024 * 1024)]
+    log_max_size: u64,
+
+    #[command(flatten)]
+    transport: Transport,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Check files for typos without an LSP client, using the same config resolution as the
+    /// server. Exits with status 2 if typos are found.
+    Check {
+        /// Files or directories to check.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Workspace folder config files are resolved from. Defaults to the current directory.
+        #[arg(long, value_name = "PATH")]
+        workspace_folder: Option<PathBuf>,
+
+        /// Custom config file, like the `config` setting.
+        #[arg(long, value_name = "PATH")]
+        config: Option<PathBuf>,
+
+        /// User dictionary, like the `userDictionary` setting.
+        #[arg(long, value_name = "PATH")]
+        user_dictionary: Option<PathBuf>,
+
+        /// Name of a config file to discover in addition to the supported ones, like the
+        /// `configFileNames` setting. Can be repeated.
+        #[arg(long = "config-file-name", value_name = "NAME")]
+        config_file_names: Vec<String>,
+
+        /// File of accepted words, one per line, like the `wordLists` setting. Can be repeated.
+        #[arg(long = "word-list", value_name = "PATH")]
+        word_lists: Vec<PathBuf>,
+
+        /// Only check comments and string literals in code, like the `syntaxAware` setting.
+        #[arg(long)]
+        syntax_aware: bool,
+
+        /// Don't report typos shorter than this many chars, like the `minWordLength` setting.
+        #[arg(long, value_name = "CHARS", default_value_t = 0)]
+        min_word_length: usize,
+
+        /// Skip identifiers without lowercase letters, like the `ignoreAllCaps` setting.
+        #[arg(long)]
+        ignore_all_caps: bool,
+
+        /// Skip hexadecimal strings, like the `ignoreHex` setting.
+        #[arg(long)]
+        ignore_hex: bool,
+
+        /// Skip long base64-like blobs, like the `ignoreBase64` setting.
+        #[arg(long)]
+        ignore_base64: bool,
+
+        /// Only report typos on lines changed since this git ref, like the `diffBase` setting.
+        #[arg(long, value_name = "REF")]
+        diff_base: Option<String>,
+
+        /// Skip files larger than this many bytes, like the `maxFileSize` setting.
+        #[arg(long, value_name = "BYTES", default_value_t = 0)]
+        max_file_size: usize,
+
+        /// Skip lines longer than this many chars, like the `maxLineLength` setting.
+        #[arg(long, value_name = "CHARS", default_value_t = 0)]
+        max_line_length: usize,
+
+        /// How typos are written, `github` to annotate them on pull requests in GitHub Actions.
+        #[arg(long, value_enum, default_value_t)]
+        format: codetypo_lsp::check::OutputFormat,
+    },
+}
+
+/// How the server communicates with clients, defaulting to stdio.
+#[derive(Debug, clap::Args)]
+#[group(multiple = false)]
+struct Transport {
+    /// Communicate over stdio.
+    #[arg(long)]
+    stdio: bool,
+
+    /// Serve over TCP at this address.
+    #[arg(long, value_name = "ADDR:PORT")]
+    listen: Option<String>,
+
+    /// Serve over WebSocket at this address.
+    #[arg(long, value_name = "ADDR:PORT")]
+    ws: Option<String>,
+
+    /// Serve over a Unix domain socket, or a named pipe on Windows, at this path.
+    #[arg(long, value_name = "PATH")]
+    pipe: Option<PathBuf>,
+
+    /// Process id of the client, which some clients pass. Unused.
+    #[arg(long = "clientProcessId", hide = true)]
+    client_process_id: Option<String>,
+}
 
 #[tokio::main]
-async fn main() {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .with_writer(std::io::stderr)
-        .init();
+async fn main() -> anyhow::Result<(), anyhow::Error> {
+    let args = <Args as clap::Parser>::parse();
+
+    let filter = match &args.log_level {
+        Some(level) => tracing_subscriber::EnvFilter::try_new(level)?,
+        None => tracing_subscriber::EnvFilter::from_default_env(),
+    };
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match &args.log_file {
+        Some(path) => {
+            let file = RotatingFile::open(path, args.log_rotation, args.log_max_size)
+                .map_err(|e| anyhow!("Cannot open log file {}: {}", path.display(), e))?;
+            subscriber
+                .with_writer(std::sync::Mutex::new(file))
+                .with_ansi(false)
+                .init();
+        }
+        None => subscriber.with_writer(std::io::stderr).init(),
+    }
+
+    if let Some(Command::Check {
+        paths,
+        workspace_folder,
+        config,
+        user_dictionary,
+        config_file_names,
+        word_lists,
+        syntax_aware,
+        min_word_length,
+        ignore_all_caps,
+        ignore_hex,
+        ignore_base64,
+        diff_base,
+        max_file_size,
+        max_line_length,
+        format,
+    }) = args.command
+    {
+        let options = codetypo_lsp::check::CheckOptions {
+            workspace_folder,
+            config,
+            user_dictionary,
+            config_file_names,
+            word_lists,
+            syntax_aware,
+            min_word_length,
+            ignore_all_caps,
+            ignore_hex,
+            ignore_base64,
+            diff_base,
+            max_file_size,
+            max_line_length,
+            format,
+        };
+        let count = codetypo_lsp::check::check(&paths, &options, &mut std::io::stdout().lock())?;
+        if count > 0 {
+            std::process::exit(2);
+        }
+        return Ok(());
+    }
+
+    let transport = args.transport;
+    if let Some(addr) = transport.listen {
+        serve_tcp(&addr).await
+    } else if let Some(addr) = transport.ws {
+        serve_ws(&addr).await
+    } else if let Some(path) = transport.pipe {
+        serve_pipe(&path).await
+    } else {
+        let stdin = tokio::io::stdin();
+        let stdout = tokio::io::stdout();
+
+        let (service, socket) = lsp::Backend::service();
+        Server::new(stdin, stdout, socket).serve(service).await;
+        Ok(())
+    }
+}
+
+/// Serves the LSP over TCP, with a separate server for each connection.
+async fn serve_tcp(addr: &str) -> anyhow::Result<(), anyhow::Error> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!("Listening on {}", listener.local_addr()?);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        tracing::info!("Accepted connection from {}", peer);
+        let (read, write) = tokio::io::split(stream);
+        let (service, socket) = lsp::Backend::service();
+        tokio::spawn(Server::new(read, write, socket).serve(service));
+    }
+}
+
+/// Serves the LSP over a Unix domain socket at `path`, with a separate server for each
+/// connection.
+///
+/// The socket file is removed when the server is interrupted or terminated.
+#[cfg(unix)]
+async fn serve_pipe(path: &Path) -> anyhow::Result<(), anyhow::Error> {
+    use std::os::unix::fs::FileTypeExt;
+    use tokio::signal::unix::{signal, SignalKind};
+
+    // remove the socket left behind by a server that didn't exit cleanly, but nothing else
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(anyhow!("{} exists and is not a socket", path.display()));
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = tokio::net::UnixListener::bind(path)?;
+    tracing::info!("Listening on {}", path.display());
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let result = tokio::select! {
+        result = accept_unix(listener) => result,
+        result = tokio::signal::ctrl_c() => result.map_err(Into::into),
+        _ = terminate.recv() => Ok(()),
+    };
+
+    if let Err(e) = std::fs::remove_file(path) {
+        tracing::warn!("Cannot remove {}: {}", path.display(), e);
+    }
+    result
+}
+
+/// Accepts connections on a Unix domain socket, serving each with a separate server.
+#[cfg(unix)]
+async fn accept_unix(listener: tokio::net::UnixListener) -> anyhow::Result<(), anyhow::Error> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tracing::info!("Accepted connection");
+        let (read, write) = tokio::io::split(stream);
+        let (service, socket) = lsp::Backend::service();
+        tokio::spawn(Server::new(read, write, socket).serve(service));
+    }
+}
+
+/// Serves the LSP over a named pipe at `path`, eg: `\\.\pipe\codetypo-lsp`, with a separate
+/// server for each connection.
+///
+/// Named pipes are removed by Windows once all their handles are closed.
+#[cfg(windows)]
+async fn serve_pipe(path: &Path) -> anyhow::Result<(), anyhow::Error> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(path)?;
+    tracing::info!("Listening on {}", path.display());
+
+    loop {
+        server.connect().await?;
+        let connected = server;
+        // create the next instance before serving, so there's always one for clients to connect to
+        server = ServerOptions::new().create(path)?;
+        tracing::info!("Accepted connection");
+        let (read, write) = tokio::io::split(connected);
+        let (service, socket) = lsp::Backend::service();
+        tokio::spawn(Server::new(read, write, socket).serve(service));
+    }
+}
+
+/// Serves the LSP over WebSocket, with a separate server for each connection.
+///
+/// Each WebSocket message carries a single JSON-RPC message, without the `Content-Length` header
+/// used over stdio and TCP.
+async fn serve_ws(addr: &str) -> anyhow::Result<(), anyhow::Error> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!(
+        "Listening for WebSocket connections on {}",
+        listener.local_addr()?
+    );
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        tracing::info!("Accepted connection from {}", peer);
+
+        // the server reads and writes framed messages through a pair of pipes, which are bridged
+        // to the WebSocket
+        let (server_read, bridge_write) = tokio::io::duplex(64 * 1024);
+        let (bridge_read, server_write) = tokio::io::duplex(64 * 1024);
+        let (service, socket) = lsp::Backend::service();
+        tokio::spawn(Server::new(server_read, server_write, socket).serve(service));
+
+        tokio::spawn(async move {
+            if let Err(e) = bridge_ws(stream, bridge_write, bridge_read).await {
+                tracing::warn!("WebSocket connection from {} failed: {}", peer, e);
+            }
+            tracing::info!("Closed connection from {}", peer);
+        });
+    }
+}
+
+/// Accepts a WebSocket connection on `stream`, then forwards its messages to the server via
+/// `to_server` and the server's messages from `from_server`, until either side closes.
+async fn bridge_ws(
+    stream: tokio::net::TcpStream,
+    mut to_server: tokio::io::DuplexStream,
+    from_server: tokio::io::DuplexStream,
+) -> anyhow::Result<(), anyhow::Error> {
+    let (mut ws_sink, mut ws_stream) = tokio_tungstenite::accept_async(stream).await?.split();
+
+    let incoming = async move {
+        while let Some(message) = ws_stream.next().await {
+            let body = match message? {
+                Message::Text(text) => text.as_bytes().to_vec(),
+                Message::Binary(bytes) => bytes.to_vec(),
+                Message::Close(_) => break,
+                // pings are answered by tungstenite
+                _ => continue,
+            };
+            let header = format!("Content-Length: {}\r\n\r\n", body.len());
+            to_server.write_all(header.as_bytes()).await?;
+            to_server.write_all(&body).await?;
+        }
+        anyhow::Ok(())
+    };
 
-    let stdin = tokio::io::stdin();
-    let stdout = tokio::io::stdout();
+    let outgoing = async move {
+        let mut reader = tokio::io::BufReader::new(from_server);
+        loop {
+            let mut content_length = None;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).await? == 0 {
+                    // the server has shut down
+                    return anyhow::Ok(());
+                }
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("Content-Length:") {
+                    content_length = Some(value.trim().parse::<usize>()?);
+                }
+            }
+            let content_length =
+                content_length.ok_or_else(|| anyhow!("Missing Content-Length header"))?;
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).await?;
+            ws_sink
+                .send(Message::text(String::from_utf8(body)?))
+                .await?;
+        }
+    };
 
-    let (service, socket) = LspService::new(lsp::Backend::new);
-    Server::new(stdin, stdout, socket).serve(service).await;
+    tokio::select! {
+        result = incoming => result,
+        result = outgoing => result,
+    }
 }
@@ -1,114 +1,1154 @@
 //! Workspace and routing state management for Codetypo-LSP.
 
 use anyhow::anyhow;
-use matchit::Router;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use tower_lsp::lsp_types::{DiagnosticSeverity, Url, WorkspaceFolder};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticSeverity, Position, TextDocumentContentChangeEvent, TextDocumentItem,
+    TraceValue, Url, WorkspaceFolder,
+};
 
-use crate::codetypo::Instance;
+use crate::codetypo::{CheckedBuffer, ConfigSources, Filters, Instance, PositionEncoding};
+use crate::config::WordRules;
+use crate::git::BaseTexts;
+use crate::syntax::Syntax;
+
+/// How long to wait after the last change to a document before checking it.
+const DEFAULT_DIAGNOSTIC_DELAY: Duration = Duration::from_millis(200);
 
 #[derive(Default)]
-/// State for the Codetypo-LSP backend, including severity, config, workspace folders, and router.
-pub(crate) struct BackendState<'s> {
+/// State for the Codetypo-LSP backend, split by concern so each part is locked independently
+/// and a slow router rebuild doesn't stall requests that only need the open documents.
+pub(crate) struct BackendState {
+    pub settings: RwLock<Settings>,
+    pub workspace: RwLock<Workspace>,
+    pub documents: Mutex<HashMap<Url, TextDocumentItem>>,
+    /// Number of typos found by the latest check of each open document.
+    pub typo_counts: Mutex<HashMap<Url, usize>>,
+    /// Whether the config is being reloaded.
+    pub reloading: AtomicBool,
+    /// Files with typos found by the last workspace scan, whose diagnostics were published.
+    pub scanned: Mutex<HashSet<Url>>,
+    /// Cancellation flags of the in-flight checks of open documents.
+    checks: Mutex<HashMap<Url, Arc<AtomicBool>>>,
+    /// Versions of the open documents waiting for the diagnostic delay before they're checked.
+    queued: Mutex<HashMap<Url, i32>>,
+    /// The state and number of queued documents last sent to the client.
+    pub sent_server_state: Mutex<Option<(ServerState, usize)>>,
+    /// Id of the last work done progress token created by the server.
+    progress_id: AtomicU64,
+    /// Glob patterns of the files the client was last asked to watch.
+    pub watched_files: Mutex<Vec<String>>,
+    /// Methods whose capabilities are registered with the client dynamically.
+    pub registered_capabilities: Mutex<HashSet<&'static str>>,
+    /// Diagnostics last published for each open document.
+    pub published: Mutex<HashMap<Url, PublishedDiagnostics>>,
+    /// Text and typos of the last check of each open document, so the next check only needs to
+    /// check the lines that changed.
+    pub checked: Mutex<HashMap<Url, Arc<CheckedBuffer>>>,
+    /// Words ignored with the `codetypo.ignoreWordSession` command, lowercase, until the server
+    /// exits.
+    pub session_ignored: RwLock<HashSet<String>>,
+    /// Incremented whenever the router is updated, so typos found with a previous config aren't
+    /// reused.
+    pub generation: AtomicU64,
+    /// Config files parsed to find where words are defined, by the directory of the files they
+    /// apply to, and the generation they were parsed in, so they're parsed again once the config
+    /// is reloaded.
+    pub word_rules: Mutex<(u64, HashMap<PathBuf, Arc<WordRules>>)>,
+    /// The text of files at the `diffBase` ref.
+    pub base_texts: BaseTexts,
+    /// The threads files are checked on and their number, built again when the `concurrency`
+    /// setting changes.
+    pub check_pool: Mutex<Option<(usize, Arc<rayon::ThreadPool>)>>,
+}
+
+/// What the server is doing, reported to clients that opt in, eg: to show a spinner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum ServerState {
+    Idle,
+    Checking,
+    Reloading,
+}
+
+/// Diagnostics published for a version of a document, reused when it's checked again unchanged,
+/// eg: on save or when a client resends a change.
+pub(crate) struct PublishedDiagnostics {
+    pub version: i32,
+    /// Hash of the language and text, so a version with other text, eg: when a document is
+    /// reopened, isn't mistaken for the published one.
+    pub hash: u64,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Default)]
+/// Settings from the client, including severity, config, and supported client capabilities.
+pub(crate) struct Settings {
     pub severity: Option<DiagnosticSeverity>,
+    /// Severity of disallowed words, defaults to `severity`.
+    pub disallowed_severity: Option<DiagnosticSeverity>,
+    pub diagnostic_delay: Option<Duration>,
     pub config: Option<PathBuf>,
-    pub workspace_folders: Vec<WorkspaceFolder>,
-    pub router: Router<crate::codetypo::Instance<'s>>,
+    pub user_dictionary: Option<PathBuf>,
+    /// Path whose config applies to untitled documents, relative to the first workspace folder.
+    pub untitled_path: Option<PathBuf>,
+    /// Prefixes of the client's paths and the server's paths they map to.
+    pub path_mappings: PathMappings,
+    /// Names of config files discovered in addition to the supported ones, eg: `spelling.toml`.
+    pub config_file_names: Vec<String>,
+    /// Config from the settings rather than a file, taking precedence over the config files.
+    pub inline_config: Option<codetypo_cli::config::Config>,
+    /// Files of accepted words, one per line, relative to the workspace folder.
+    pub word_lists: Vec<PathBuf>,
+    /// The locale of the dictionary, eg: `en-gb`, unless a config file sets one.
+    pub locale: Option<codetypo_cli::config::Locale>,
+    pub syntax_aware: bool,
+    /// Typos shorter than this many chars aren't reported.
+    pub min_word_length: usize,
+    pub ignore_all_caps: bool,
+    pub ignore_hex: bool,
+    pub ignore_base64: bool,
+    /// Whether to check the names of files and their directories in the workspace folders.
+    pub check_file_names: bool,
+    /// Git ref, eg: `origin/main`, that only lines changed since are checked against.
+    pub diff_base: Option<String>,
+    /// Documents larger than this many bytes aren't checked, unless 0.
+    pub max_file_size: usize,
+    /// Lines longer than this many chars aren't checked, unless 0.
+    pub max_line_length: usize,
+    /// Diagnostic messages show at most this many corrections, unless 0.
+    pub max_corrections_in_message: usize,
+    /// Number of files checked in parallel by workspace scans and fixes, 0 for the number of CPUs.
+    pub concurrency: usize,
+    pub inlay_hints: bool,
+    pub auto_fix_on_save: bool,
+    pub check_on_save_only: bool,
+    pub configuration_support: bool,
+    pub watched_files_support: bool,
+    pub status_notification_support: bool,
+    pub server_state_notification_support: bool,
+    /// How much the client wants to be told about what the server does, via `$/logTrace`.
+    pub trace: TraceValue,
+    pub work_done_progress_support: bool,
+    pub related_information_support: bool,
+    /// How the characters of positions are counted, negotiated with the client.
+    pub position_encoding: PositionEncoding,
+    /// Whether the client resolves the edits of code actions with `codeAction/resolve`.
+    pub resolve_edit_support: bool,
+    /// Whether the client only accepts commands as code actions, because it declares code action
+    /// capabilities without `codeActionLiteralSupport` or `resolveSupport`.
+    pub code_action_commands_only: bool,
+    /// URI schemes of documents that are never checked, eg: `output`.
+    pub excluded_schemes: HashSet<String>,
+    /// Language identifiers of documents that are never checked, eg: `log`.
+    pub disabled_language_ids: HashSet<String>,
+    /// Whether checking is paused, by the `enable` setting or the `codetypo.toggle` command.
+    pub paused: bool,
+    /// Features that are turned off, eg: `formatting`, see [`crate::lsp::DYNAMIC_CAPABILITIES`].
+    pub disabled_capabilities: HashSet<String>,
+    /// Methods whose capabilities the client can register dynamically, eg: `textDocument/formatting`.
+    pub dynamic_registration_support: HashSet<&'static str>,
+    /// Overrides for documents with a language identifier, eg: `markdown`.
+    pub languages: HashMap<String, LanguageSettings>,
+}
+
+/// Settings overridden for documents with a language identifier.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct LanguageSettings {
+    pub severity: Option<DiagnosticSeverity>,
+    /// Patterns ignored in addition to the config's `extend-ignore-re`.
+    pub extend_ignore_re: Vec<regex::Regex>,
+}
+
+#[derive(Default)]
+/// Workspace folders and the router to the Codetypo instance for each of them.
+pub(crate) struct Workspace {
+    pub folders: Vec<WorkspaceFolder>,
+    pub router: Router,
+    /// The `pathMappings` setting the router was last updated with.
+    pub path_mappings: PathMappings,
+    /// The config files and word lists read by the instances, watched so changes to them take
+    /// effect.
+    pub referenced_files: Vec<PathBuf>,
 }
 
-impl BackendState<'_> {
+impl BackendState {
     /// Sets the workspace folders and updates the router.
     pub(crate) fn set_workspace_folders(
-        &mut self,
+        &self,
         workspace_folders: Vec<WorkspaceFolder>,
     ) -> anyhow::Result<(), anyhow::Error> {
-        self.workspace_folders = workspace_folders;
+        self.workspace.write().unwrap().folders = workspace_folders;
         self.update_router()?;
         Ok(())
     }
 
     /// Updates the workspace folders by adding and removing, then updates the router.
+    ///
+    /// Only the added folders' instances are built, so the other folders keep their instances
+    /// rather than re-reading their config.
     pub(crate) fn update_workspace_folders(
-        &mut self,
+        &self,
         added: Vec<WorkspaceFolder>,
         removed: Vec<WorkspaceFolder>,
     ) -> anyhow::Result<(), anyhow::Error> {
-        self.workspace_folders.extend(added);
-        if !removed.is_empty() {
-            self.workspace_folders.retain(|x| !removed.contains(x));
+        let sources = self.settings.read().unwrap().config_sources();
+        let path_mappings = self.workspace.read().unwrap().path_mappings.clone();
+        let mut instances = Vec::new();
+        for folder in added.iter().filter(|x| !removed.contains(x)) {
+            let path = folder_path(folder, &path_mappings)?;
+            let instance = new_instance(&path, &sources, true)?;
+            instances.push((path, instance));
+        }
+
+        let mut workspace = self.workspace.write().unwrap();
+        workspace.folders.extend(added.iter().cloned());
+        workspace.folders.retain(|x| !removed.contains(x));
+        for folder in removed.iter() {
+            let Some(path) = path_mappings.file_path(&folder.uri) else {
+                continue;
+            };
+            // another folder may have the same path, eg: when it's listed twice
+            if workspace
+                .folders
+                .iter()
+                .all(|x| path_mappings.file_path(&x.uri).as_ref() != Some(&path))
+            {
+                workspace.router.remove(&canonicalize(&path));
+            }
+        }
+        for (path, instance) in instances {
+            workspace.router.insert(&path, instance);
+        }
+        workspace.referenced_files = workspace.router.referenced_files();
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        self.base_texts.clear();
+        drop(workspace);
+
+        for folder in added.iter() {
+            self.add_share_route(&folder.uri)?;
         }
-        self.update_router()?;
         Ok(())
     }
 
+    /// Adds catch all routes for the drives mounted since the router was updated, eg: USB drives
+    /// or network mounts, and removes the routes of drives that were unmounted.
+    #[cfg(windows)]
+    pub(crate) fn refresh_drive_routes(&self) -> anyhow::Result<(), anyhow::Error> {
+        let drives: Vec<PathBuf> = crate::windows::get_drives()
+            .iter()
+            .map(|drive| crate::windows::drive_root(drive))
+            .collect();
+        let (mounted, unmounted) = {
+            let workspace = self.workspace.read().unwrap();
+            let mounted: Vec<&PathBuf> = drives
+                .iter()
+                .filter(|drive| !workspace.router.contains(drive))
+                .collect();
+            let unmounted: Vec<PathBuf> = workspace
+                .router
+                .dirs()
+                .filter(|dir| {
+                    crate::windows::is_drive_root(dir)
+                        && !drives.iter().any(|drive| route_key(drive) == **dir)
+                })
+                .cloned()
+                .collect();
+            (mounted, unmounted)
+        };
+        if mounted.is_empty() && unmounted.is_empty() {
+            return Ok(());
+        }
+
+        let sources = self.settings.read().unwrap().config_sources();
+        let mut instances = Vec::new();
+        for drive in mounted {
+            tracing::info!("Drive {} was mounted", drive.display());
+            instances.push((drive, new_instance(drive, &sources, false)?));
+        }
+        let mut workspace = self.workspace.write().unwrap();
+        for drive in unmounted {
+            tracing::info!("Drive {} was unmounted", drive.display());
+            workspace.router.remove(&drive);
+        }
+        for (drive, instance) in instances {
+            workspace.router.insert(drive, instance);
+        }
+        workspace.referenced_files = workspace.router.referenced_files();
+        Ok(())
+    }
+
+    /// Adds a catch all route for the network share of a UNC path, eg: `\\server\share\`, so
+    /// files on the share outside the workspace folders still read the config of their parents.
+    ///
+    /// Drives get their catch all routes when the router is updated, but shares can't be listed.
+    pub(crate) fn add_share_route(&self, uri: &Url) -> anyhow::Result<(), anyhow::Error> {
+        let Some(share) = self
+            .file_path(uri)
+            .and_then(|path| crate::windows::unc_share(&path))
+        else {
+            return Ok(());
+        };
+        if self
+            .workspace
+            .read()
+            .unwrap()
+            .router
+            .contains(&canonicalize(&share))
+        {
+            return Ok(());
+        }
+
+        let sources = self.settings.read().unwrap().config_sources();
+        let instance = new_instance(&share, &sources, false)?;
+        let mut workspace = self.workspace.write().unwrap();
+        workspace.router.insert(&share, instance);
+        workspace.referenced_files = workspace.router.referenced_files();
+        Ok(())
+    }
+
+    /// Returns the path of the innermost workspace folder containing `path`.
+    pub(crate) fn workspace_folder(&self, path: &Path) -> Option<PathBuf> {
+        self.workspace.read().unwrap().workspace_folder(path)
+    }
+
+    /// Converts the URI of a file on the client to its path on the server, see [`PathMappings`].
+    pub(crate) fn file_path(&self, uri: &Url) -> Option<PathBuf> {
+        self.workspace.read().unwrap().path_mappings.file_path(uri)
+    }
+
+    /// Converts the path of a file on the server to its URI on the client, see [`PathMappings`].
+    pub(crate) fn file_uri(&self, path: &Path) -> Option<Url> {
+        self.workspace.read().unwrap().path_mappings.file_uri(path)
+    }
+
+    /// Converts the paths of files on the server to their URIs on the client, which are the URIs
+    /// of the open documents for open files, however the client encoded them, eg:
+    /// `file:///c%3A/a%2Bb.txt` rather than `file:///c:/a+b.txt`.
+    pub(crate) fn file_uris<'a>(&self, paths: &'a [PathBuf]) -> Vec<(&'a PathBuf, Url)> {
+        let documents: Vec<Url> = self.documents.lock().unwrap().keys().cloned().collect();
+        let workspace = self.workspace.read().unwrap();
+        let open: HashMap<PathBuf, Url> = documents
+            .into_iter()
+            .filter_map(|uri| Some((workspace.path_mappings.file_path(&uri)?, uri)))
+            .collect();
+        paths
+            .iter()
+            .filter_map(|path| {
+                let uri = match open.get(path) {
+                    Some(uri) => uri.clone(),
+                    None => workspace.path_mappings.file_uri(path)?,
+                };
+                Some((path, uri))
+            })
+            .collect()
+    }
+
+    /// Returns the URI whose config applies to a document, which is the document's own URI except
+    /// for untitled documents when the `untitledPath` setting is set, and virtual documents of a
+    /// file, whose file's URI is returned.
+    pub(crate) fn policy_uri(&self, uri: &Url) -> Url {
+        if VIRTUAL_SCHEMES.contains(&uri.scheme()) {
+            // the path is the file's, while the query or fragment identify the revision or cell
+            // keeping the host of files on a network share, eg: `git://server/share/file.txt`
+            let host = uri.host_str().unwrap_or_default();
+            if let Ok(file_uri) = Url::parse(&format!("file://{}{}", host, uri.path())) {
+                return file_uri;
+            }
+        }
+        if uri.scheme() == "untitled" {
+            let untitled_path = self.settings.read().unwrap().untitled_path.clone();
+            if let Some(untitled_path) = untitled_path {
+                let workspace = self.workspace.read().unwrap();
+                let folder = workspace
+                    .folders
+                    .first()
+                    .and_then(|folder| workspace.path_mappings.file_path(&folder.uri));
+                // relative paths without a workspace folder have no config to apply
+                let path = match folder {
+                    Some(folder) => folder.join(untitled_path),
+                    None => untitled_path,
+                };
+                if let Some(policy_uri) = workspace.path_mappings.file_uri(&path) {
+                    return policy_uri;
+                }
+            }
+        }
+        uri.clone()
+    }
+
+    /// Returns the syntax used to only check comments and strings, when enabled and the language
+    /// of the document is known.
+    pub(crate) fn syntax(&self, uri: &Url) -> Option<&'static Syntax> {
+        if !self.settings.read().unwrap().syntax_aware {
+            return None;
+        }
+        match self.language_id(uri) {
+            Some(language_id) => Syntax::from_language_id(&language_id),
+            None => Syntax::from_path(&uri.to_file_path().ok()?),
+        }
+    }
+
+    /// Returns the language identifier of an open document.
+    pub(crate) fn language_id(&self, uri: &Url) -> Option<String> {
+        self.documents
+            .lock()
+            .unwrap()
+            .get(uri)
+            .map(|document| document.language_id.clone())
+    }
+
+    /// Returns true if checking is paused, the document has an excluded scheme, or is open with a
+    /// language that checking is disabled for.
+    pub(crate) fn is_disabled(&self, uri: &Url) -> bool {
+        let language_id = self.language_id(uri);
+        let settings = self.settings.read().unwrap();
+        settings.paused
+            || settings.excluded_schemes.contains(uri.scheme())
+            || language_id
+                .is_some_and(|language_id| settings.disabled_language_ids.contains(&language_id))
+    }
+
+    /// Returns the settings overridden for the language of an open document, if any.
+    pub(crate) fn language_settings(&self, uri: &Url) -> Option<LanguageSettings> {
+        let language_id = self.language_id(uri)?;
+        self.settings
+            .read()
+            .unwrap()
+            .languages
+            .get(&language_id)
+            .cloned()
+    }
+
+    /// Returns the pool of `concurrency` threads that files are checked on, building it if the
+    /// number of threads changed.
+    pub(crate) fn check_pool(
+        &self,
+        concurrency: usize,
+    ) -> anyhow::Result<Arc<rayon::ThreadPool>, anyhow::Error> {
+        let mut check_pool = self.check_pool.lock().unwrap();
+        match &*check_pool {
+            Some((threads, pool)) if *threads == concurrency => Ok(pool.clone()),
+            _ => {
+                let pool = Arc::new(
+                    rayon::ThreadPoolBuilder::new()
+                        .num_threads(concurrency)
+                        .build()?,
+                );
+                *check_pool = Some((concurrency, pool.clone()));
+                Ok(pool)
+            }
+        }
+    }
+
+    /// Starts a check of a document, cancelling any in-flight check of the same document.
+    pub(crate) fn start_check(&self, uri: &Url) -> Arc<AtomicBool> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let previous = self
+            .checks
+            .lock()
+            .unwrap()
+            .insert(uri.clone(), cancelled.clone());
+        if let Some(previous) = previous {
+            previous.store(true, Ordering::Relaxed);
+        }
+        cancelled
+    }
+
+    /// Cancels the in-flight check of a document, if any.
+    pub(crate) fn cancel_check(&self, uri: &Url) {
+        if let Some(previous) = self.checks.lock().unwrap().remove(uri) {
+            previous.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns a new id for a work done progress token.
+    pub(crate) fn next_progress_id(&self) -> u64 {
+        self.progress_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Returns true if any document is being checked.
+    pub(crate) fn checking(&self) -> bool {
+        !self.checks.lock().unwrap().is_empty()
+    }
+
+    /// Queues a version of a document to be checked after the diagnostic delay.
+    pub(crate) fn queue_check(&self, uri: &Url, version: i32) {
+        self.queued.lock().unwrap().insert(uri.clone(), version);
+    }
+
+    /// Removes a version of a document from the queue, unless a later version was queued since.
+    pub(crate) fn dequeue_check(&self, uri: &Url, version: i32) {
+        let mut queued = self.queued.lock().unwrap();
+        if queued.get(uri) == Some(&version) {
+            queued.remove(uri);
+        }
+    }
+
+    /// Returns what the server is doing, and the number of documents waiting to be checked or
+    /// being checked.
+    pub(crate) fn server_state(&self) -> (ServerState, usize) {
+        let checks = self.checks.lock().unwrap();
+        let queued = self.queued.lock().unwrap();
+        let count = queued.len()
+            + checks
+                .keys()
+                .filter(|uri| !queued.contains_key(*uri))
+                .count();
+        let state = if self.reloading.load(Ordering::Relaxed) {
+            ServerState::Reloading
+        } else if count > 0 {
+            ServerState::Checking
+        } else {
+            ServerState::Idle
+        };
+        (state, count)
+    }
+
+    /// Finishes a check started by [`BackendState::start_check`], returning false if it was
+    /// cancelled.
+    pub(crate) fn finish_check(&self, uri: &Url, cancelled: &Arc<AtomicBool>) -> bool {
+        let mut checks = self.checks.lock().unwrap();
+        if checks
+            .get(uri)
+            .is_some_and(|current| Arc::ptr_eq(current, cancelled))
+        {
+            checks.remove(uri);
+        }
+        !cancelled.load(Ordering::Relaxed)
+    }
+
     /// Updates the internal router for workspace folders.
-    pub(crate) fn update_router(&mut self) -> anyhow::Result<(), anyhow::Error> {
-        self.router = Router::new();
-        for folder in self.workspace_folders.iter() {
-            let path = folder
-                .uri
-                .to_file_path()
-                .map_err(|_| anyhow!("Cannot convert uri {} to file path", folder.uri))?;
-            let route = format!("{}{}", url_path_sanitised(&folder.uri), "/{*p}");
-            self.router
-                .insert_instance(&route, &path, self.config.as_deref())?;
-        }
-
-        // add low priority catch all route used for files outside the workspace, or
+    ///
+    /// The new router is built without holding any locks, then swapped in.
+    pub(crate) fn update_router(&self) -> anyhow::Result<(), anyhow::Error> {
+        let (sources, path_mappings) = {
+            let settings = self.settings.read().unwrap();
+            (settings.config_sources(), settings.path_mappings.clone())
+        };
+        let folders = self.workspace.read().unwrap().folders.clone();
+
+        let mut router = Router::default();
+        for folder in folders.iter() {
+            let path = folder_path(folder, &path_mappings)?;
+            router.insert(&path, new_instance(&path, &sources, true)?);
+        }
+
+        // and for the network shares of folders and open documents with UNC paths, which aren't
+        // drives
+        let mut uris: Vec<Url> = folders.iter().map(|folder| folder.uri.clone()).collect();
+        let documents: Vec<Url> = self.documents.lock().unwrap().keys().cloned().collect();
+        uris.extend(documents.iter().map(|uri| self.policy_uri(uri)));
+        for share in uris.iter().filter_map(|uri| {
+            let path = path_mappings.file_path(uri)?;
+            crate::windows::unc_share(&path)
+        }) {
+            if !router.contains(&canonicalize(&share)) {
+                router.insert(&share, new_instance(&share, &sources, false)?);
+            }
+        }
+
+        // add low priority catch all routes used for files outside the workspace, or
         // when there is no workspace folder
         #[cfg(windows)]
         for drive in crate::windows::get_drives() {
-            let route = format!("/{}%3A/{{*p}}", &drive);
-            self.router.insert_instance(
-                &route,
-                &PathBuf::from(format!("{}:\\", &drive)),
-                self.config.as_deref(),
-            )?;
+            let path = crate::windows::drive_root(&drive);
+            router.insert(&path, new_instance(&path, &sources, false)?);
         }
 
         #[cfg(not(windows))]
         {
-            let route = "/{*p}";
-            self.router
-                .insert_instance(route, &PathBuf::from("/"), self.config.as_deref())?;
+            let path = PathBuf::from("/");
+            router.insert(&path, new_instance(&path, &sources, false)?);
         }
 
+        let mut workspace = self.workspace.write().unwrap();
+        workspace.referenced_files = router.referenced_files();
+        workspace.router = router;
+        workspace.path_mappings = path_mappings;
+        // typos found with the previous config may not be typos anymore
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        self.base_texts.clear();
         Ok(())
     }
 }
 
-/// Extension trait for inserting Codetypo instances into the router.
-trait RouterExt {
-    /// Inserts a new Codetypo instance into the router for the given route and path.
-    fn insert_instance(
-        &mut self,
-        route: &str,
-        path: &Path,
-        config: Option<&Path>,
-    ) -> anyhow::Result<(), anyhow::Error>;
+/// Schemes of virtual documents whose path is the path of a file, eg: a notebook cell or a git
+/// revision of the file.
+const VIRTUAL_SCHEMES: &[&str] = &["vscode-notebook-cell", "git", "diff"];
+
+/// Parses a severity setting, returning `None` if it's unknown.
+fn parse_severity(value: &Value) -> Option<Option<DiagnosticSeverity>> {
+    match value.as_str().unwrap_or("").to_lowercase().as_str() {
+        "error" => Some(Some(DiagnosticSeverity::ERROR)),
+        "warning" => Some(Some(DiagnosticSeverity::WARNING)),
+        "information" | "info" => Some(Some(DiagnosticSeverity::INFORMATION)),
+        "hint" => Some(Some(DiagnosticSeverity::HINT)),
+        _ if value.is_null() => Some(None),
+        _ => {
+            tracing::warn!("Unknown diagnostic severity: {}", value);
+            None
+        }
+    }
 }
 
-impl RouterExt for Router<Instance<'_>> {
-    // convenience method to insert a new CodetypoCli into the router
-    // implemented as an extension trait to avoid interprocedural conflicts
-    fn insert_instance(
-        &mut self,
-        route: &str,
-        path: &Path,
-        config: Option<&Path>,
-    ) -> anyhow::Result<(), anyhow::Error> {
-        tracing::debug!("Adding route {} for path {}", route, path.display());
-        let instance = Instance::new(path, config)?;
-        self.insert(route, instance)?;
-        Ok(())
+/// Parses the settings overridden for a language, skipping invalid patterns.
+fn parse_language(value: &Value) -> LanguageSettings {
+    let mut language = LanguageSettings::default();
+    if let Some(value) = value.get("diagnosticSeverity") {
+        language.severity = parse_severity(value).flatten();
+    }
+    language.extend_ignore_re = value
+        .get("extendIgnoreRe")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .filter_map(|pattern| match regex::Regex::new(pattern) {
+            Ok(re) => Some(re),
+            Err(e) => {
+                tracing::warn!("Invalid extendIgnoreRe {}: {}", pattern, e);
+                None
+            }
+        })
+        .collect();
+    language
+}
+
+impl Settings {
+    /// Applies settings from `initializationOptions` or `workspace/didChangeConfiguration`.
+    ///
+    /// Settings that are missing are left unchanged, and settings that are `null` are reset.
+    pub(crate) fn apply_settings(&mut self, settings: &Value) {
+        let Some(values) = settings.as_object() else {
+            return;
+        };
+        if let Some(value) = values.get("diagnosticSeverity") {
+            if let Some(severity) = parse_severity(value) {
+                self.severity = severity;
+            }
+        }
+        if let Some(value) = values.get("disallowedSeverity") {
+            if let Some(severity) = parse_severity(value) {
+                self.disallowed_severity = severity;
+            }
+        }
+        if let Some(value) = values.get("diagnosticDelay") {
+            self.diagnostic_delay = value.as_u64().map(Duration::from_millis);
+        }
+        if let Some(value) = values.get("config") {
+            self.config = expand_path(value);
+        }
+        if let Some(value) = values.get("userDictionary") {
+            self.user_dictionary = expand_path(value);
+        }
+        if let Some(value) = values.get("untitledPath") {
+            self.untitled_path = expand_path(value);
+        }
+        if let Some(value) = values.get("pathMappings") {
+            self.path_mappings = PathMappings::parse(value);
+        }
+        if let Some(value) = values.get("inlineConfig") {
+            let inline_config = match value {
+                Value::Null => Ok(None),
+                Value::String(toml) => codetypo_cli::config::Config::from_toml(toml).map(Some),
+                value => serde_json::from_value(value.clone())
+                    .map(Some)
+                    .map_err(Into::into),
+            };
+            match inline_config {
+                Ok(inline_config) => self.inline_config = inline_config,
+                Err(e) => tracing::warn!("Invalid inline config: {}", e),
+            }
+        }
+        if let Some(value) = values.get("configFileNames") {
+            self.config_file_names = value
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|name| name.as_str().map(str::to_string))
+                .collect();
+        }
+        if let Some(value) = values.get("locale") {
+            match value.as_str().filter(|locale| !locale.is_empty()) {
+                None => self.locale = None,
+                Some(locale) => match locale.parse() {
+                    Ok(locale) => self.locale = Some(locale),
+                    Err(e) => tracing::warn!("Invalid locale {}: {}", locale, e),
+                },
+            }
+        }
+        if let Some(value) = values.get("wordLists") {
+            self.word_lists = value
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(expand_path)
+                .collect();
+        }
+        if let Some(value) = values.get("excludedSchemes") {
+            self.excluded_schemes = value
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|scheme| scheme.as_str().map(str::to_string))
+                .collect();
+        }
+        if let Some(value) = values.get("disabledLanguageIds") {
+            self.disabled_language_ids = value
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|language_id| language_id.as_str().map(str::to_string))
+                .collect();
+        }
+        if let Some(value) = values.get("enable") {
+            self.paused = !value.as_bool().unwrap_or(true);
+        }
+        if let Some(value) = values.get("disabledCapabilities") {
+            self.disabled_capabilities = value
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|capability| capability.as_str().map(str::to_string))
+                .collect();
+        }
+        if let Some(value) = values.get("languages") {
+            self.languages = value
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(language_id, value)| (language_id.clone(), parse_language(value)))
+                .collect();
+        }
+        if let Some(value) = values.get("syntaxAware") {
+            self.syntax_aware = value.as_bool().unwrap_or_default();
+        }
+        if let Some(value) = values.get("minWordLength") {
+            self.min_word_length = value.as_u64().unwrap_or_default() as usize;
+        }
+        if let Some(value) = values.get("ignoreAllCaps") {
+            self.ignore_all_caps = value.as_bool().unwrap_or_default();
+        }
+        if let Some(value) = values.get("ignoreHex") {
+            self.ignore_hex = value.as_bool().unwrap_or_default();
+        }
+        if let Some(value) = values.get("ignoreBase64") {
+            self.ignore_base64 = value.as_bool().unwrap_or_default();
+        }
+        if let Some(value) = values.get("checkFileNames") {
+            self.check_file_names = value.as_bool().unwrap_or_default();
+        }
+        if let Some(value) = values.get("diffBase") {
+            self.diff_base = value
+                .as_str()
+                .filter(|base| !base.is_empty())
+                .map(str::to_string);
+        }
+        if let Some(value) = values.get("maxFileSize") {
+            self.max_file_size = value.as_u64().unwrap_or_default() as usize;
+        }
+        if let Some(value) = values.get("maxLineLength") {
+            self.max_line_length = value.as_u64().unwrap_or_default() as usize;
+        }
+        if let Some(value) = values.get("maxCorrectionsInMessage") {
+            self.max_corrections_in_message = value.as_u64().unwrap_or_default() as usize;
+        }
+        if let Some(value) = values.get("concurrency") {
+            self.concurrency = value.as_u64().unwrap_or_default() as usize;
+        }
+        if let Some(value) = values.get("inlayHints") {
+            self.inlay_hints = value.as_bool().unwrap_or_default();
+        }
+        if let Some(value) = values.get("autoFixOnSave") {
+            self.auto_fix_on_save = value.as_bool().unwrap_or_default();
+        }
+        if let Some(value) = values.get("checkOnSaveOnly") {
+            self.check_on_save_only = value.as_bool().unwrap_or_default();
+        }
+    }
+
+    /// Returns how long to wait after the last change to a document before checking it.
+    pub(crate) fn diagnostic_delay(&self) -> Duration {
+        self.diagnostic_delay.unwrap_or(DEFAULT_DIAGNOSTIC_DELAY)
+    }
+
+    /// Returns the path of the user dictionary, which defaults to `~/.config/codetypo/dictionary.toml`.
+    pub(crate) fn user_dictionary(&self) -> PathBuf {
+        self.user_dictionary.clone().unwrap_or_else(|| {
+            PathBuf::from(shellexpand::tilde("~/.config/codetypo/dictionary.toml").to_string())
+        })
+    }
+
+    /// Returns the tokens that aren't checked.
+    pub(crate) fn filters(&self) -> Filters {
+        Filters {
+            min_word_length: self.min_word_length,
+            ignore_all_caps: self.ignore_all_caps,
+            ignore_hex: self.ignore_hex,
+            ignore_base64: self.ignore_base64,
+            max_line_length: self.max_line_length,
+        }
+    }
+
+    /// Returns the config applied on top of the workspace config.
+    pub(crate) fn config_sources(&self) -> ConfigSources {
+        ConfigSources {
+            names: self.config_file_names.clone(),
+            files: self.configs(),
+            inline: self.inline_config.clone(),
+            word_lists: self.word_lists.clone(),
+            locale: self.locale,
+        }
+    }
+
+    /// Returns the config files applied on top of the workspace config, in order of precedence.
+    pub(crate) fn configs(&self) -> Vec<PathBuf> {
+        let mut configs = Vec::new();
+        // the user dictionary doesn't exist until a word is first added
+        let user_dictionary = self.user_dictionary();
+        if user_dictionary.is_file() {
+            configs.push(user_dictionary);
+        }
+        configs.extend(self.config.clone());
+        configs
+    }
+}
+
+impl Workspace {
+    /// Returns the path of the innermost workspace folder containing `path`.
+    pub(crate) fn workspace_folder(&self, path: &Path) -> Option<PathBuf> {
+        self.folders
+            .iter()
+            .filter_map(|folder| self.path_mappings.file_path(&folder.uri))
+            .filter(|folder| path.starts_with(folder))
+            .max_by_key(|folder| folder.components().count())
+    }
+
+    /// Returns the Codetypo instance for a URI, or `None` when the default policy applies.
+    pub(crate) fn instance(&self, uri: &Url) -> Option<Arc<Instance>> {
+        let Some(path) = self.path_mappings.file_path(uri) else {
+            // eg: uris like untitled:* or term://*
+            tracing::debug!(
+                "instance: Using default policy because cannot convert uri {} to file path",
+                uri
+            );
+            return None;
+        };
+        self.instance_at(&canonicalize(&path))
+    }
+
+    /// Returns the Codetypo instance for a canonical path, see [`canonicalize`], or `None` when
+    /// the default policy applies.
+    pub(crate) fn instance_at(&self, path: &Path) -> Option<Arc<Instance>> {
+        let instance = self.router.at(path).cloned();
+        if instance.is_none() {
+            tracing::debug!(
+                "instance: Using default policy because no route found for {}",
+                path.display()
+            );
+        }
+        instance
+    }
+}
+
+/// Routes paths to the Codetypo instance of the deepest directory containing them, so the config
+/// of a workspace folder nested in another takes precedence, whatever the order of the folders.
+#[derive(Default)]
+pub(crate) struct Router {
+    /// Directories, by their [`route_key`], and their instances, deepest first.
+    ///
+    /// Directories are canonicalized once, when they're inserted, so looking up the canonical
+    /// paths of files doesn't touch the filesystem.
+    routes: Vec<(PathBuf, Arc<Instance>)>,
+}
+
+impl Router {
+    /// Inserts the Codetypo instance for the directory at `path`, replacing any previous one.
+    fn insert(&mut self, path: &Path, instance: Arc<Instance>) {
+        tracing::debug!("Adding route for path {}", path.display());
+        let path = &route_key(&canonicalize(path));
+        self.routes.retain(|(dir, _)| dir != path);
+        // after the deeper directories, so lookups find the deepest match first
+        let depth = path.components().count();
+        let index = self
+            .routes
+            .partition_point(|(dir, _)| dir.components().count() >= depth);
+        self.routes.insert(index, (path.to_path_buf(), instance));
+    }
+
+    /// Returns whether there is a route for the directory at the canonical `path`.
+    fn contains(&self, path: &Path) -> bool {
+        let path = route_key(path);
+        self.routes.iter().any(|(dir, _)| *dir == path)
+    }
+
+    /// Returns the directories with a route, by their [`route_key`].
+    #[cfg(windows)]
+    fn dirs(&self) -> impl Iterator<Item = &PathBuf> {
+        self.routes.iter().map(|(dir, _)| dir)
+    }
+
+    /// Removes the route for the directory at the canonical `path`, if any.
+    fn remove(&mut self, path: &Path) {
+        let path = route_key(path);
+        self.routes.retain(|(dir, _)| *dir != path);
+    }
+
+    /// Returns the config files and word lists read by the instances, sorted and deduplicated.
+    fn referenced_files(&self) -> Vec<PathBuf> {
+        let mut referenced_files: Vec<PathBuf> = self
+            .routes
+            .iter()
+            .flat_map(|(_, instance)| instance.config_files.iter().chain(&instance.word_lists))
+            .cloned()
+            .collect();
+        referenced_files.sort();
+        referenced_files.dedup();
+        referenced_files
+    }
+
+    /// Returns the instance of the deepest directory containing the canonical `path`, if any.
+    pub(crate) fn at(&self, path: &Path) -> Option<&Arc<Instance>> {
+        let path = route_key(path);
+        self.routes
+            .iter()
+            .find(|(dir, _)| path.starts_with(dir))
+            .map(|(_, instance)| instance)
+    }
+}
+
+/// Builds a new Codetypo instance for the directory at `path`.
+///
+/// Config files nested in the path are discovered when `nested` is true, eg: for workspace
+/// folders but not the filesystem root.
+fn new_instance(
+    path: &Path,
+    sources: &ConfigSources,
+    nested: bool,
+) -> anyhow::Result<Arc<Instance>, anyhow::Error> {
+    let path = &canonicalize(path);
+    let mut instance = Instance::new(path, sources)?;
+    if nested {
+        instance = instance.with_nested_configs(path);
+    }
+    Ok(Arc::new(instance))
+}
+
+/// Resolves the symlinks in `path`, so files reached through a symlinked workspace folder and
+/// through its target are routed, and matched by excludes, the same.
+///
+/// Paths that don't exist, eg: of untitled documents, are resolved up to their nearest existing
+/// ancestor.
+pub(crate) fn canonicalize(path: &Path) -> PathBuf {
+    for ancestor in path.ancestors() {
+        let Ok(canonical) = ancestor.canonicalize() else {
+            continue;
+        };
+        #[cfg(windows)]
+        let canonical = crate::windows::strip_verbatim(canonical);
+        return match path.strip_prefix(ancestor) {
+            Ok(rest) if !rest.as_os_str().is_empty() => canonical.join(rest),
+            _ => canonical,
+        };
+    }
+    path.to_path_buf()
+}
+
+/// Whether paths differing only in case are the same file, as on the default filesystems of
+/// Windows and macOS.
+const CASE_INSENSITIVE_PATHS: bool = cfg!(any(windows, target_os = "macos"));
+
+/// Returns the key routes are compared by, which is the canonical path, lowercased when paths
+/// are case-insensitive, so URIs cased differently than the workspace folder, eg: a lowercase
+/// drive letter, are still routed to it.
+fn route_key(path: &Path) -> PathBuf {
+    if CASE_INSENSITIVE_PATHS {
+        PathBuf::from(path.to_string_lossy().to_lowercase())
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Returns the canonical `path` of a file to match with the excludes of its instance, cased like
+/// the instance's directory when paths are case-insensitive, since the excludes are relative to it.
+pub(crate) fn instance_path(instance: &Instance, path: &Path) -> PathBuf {
+    let root = instance.ignores.path();
+    if !CASE_INSENSITIVE_PATHS || path.starts_with(root) {
+        return path.to_path_buf();
+    }
+    let mut components = path.components();
+    for root_component in root.components() {
+        match components.next() {
+            Some(component)
+                if component
+                    .as_os_str()
+                    .eq_ignore_ascii_case(root_component.as_os_str()) => {}
+            _ => return path.to_path_buf(),
+        }
+    }
+    root.join(components.as_path())
+}
+
+/// Returns the path of a workspace folder on the server.
+fn folder_path(
+    folder: &WorkspaceFolder,
+    path_mappings: &PathMappings,
+) -> anyhow::Result<PathBuf, anyhow::Error> {
+    path_mappings
+        .file_path(&folder.uri)
+        .ok_or_else(|| anyhow!("Cannot convert uri {} to file path", folder.uri))
+}
+
+/// Prefixes of paths on the client mapped to paths on the server, from the `pathMappings`
+/// setting, eg: `{ "/home/me/project": "/workspaces/project" }` for a dev container.
+///
+/// Client paths are compared with `/` separators and a lowercase drive letter, so
+/// `C:\Users\me` matches `file:///c%3A/Users/me` from a Windows client.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct PathMappings {
+    /// Normalized client prefixes and the server prefixes they map to.
+    mappings: Vec<(String, PathBuf)>,
+}
+
+impl PathMappings {
+    /// Parses the `pathMappings` setting, an object of client prefixes to server prefixes.
+    fn parse(value: &Value) -> Self {
+        let mappings = value
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(client, server)| {
+                let server = expand_path(server)?;
+                Some((normalize_path(client), server))
+            })
+            .collect();
+        Self { mappings }
+    }
+
+    /// Converts the URI of a file on the client to its path on the server, mapping the longest
+    /// matching client prefix.
+    pub(crate) fn file_path(&self, uri: &Url) -> Option<PathBuf> {
+        let path = uri.to_file_path().ok();
+        if self.mappings.is_empty() || uri.scheme() != "file" {
+            return path;
+        }
+        // eg: a posix path from the client of a server on Windows isn't a file path there
+        let client_path = match &path {
+            Some(path) => normalize_path(&path.to_string_lossy()),
+            None => normalize_path(
+                &percent_encoding::percent_decode_str(uri.path()).decode_utf8_lossy(),
+            ),
+        };
+        self.mappings
+            .iter()
+            .filter_map(|(client, server)| {
+                let rest = strip_path_prefix(&client_path, client)?;
+                Some((client.len(), server.join(rest)))
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, path)| path)
+            .or(path)
+    }
+
+    /// Converts the path of a file on the server to its URI on the client, mapping the longest
+    /// matching server prefix.
+    pub(crate) fn file_uri(&self, path: &Path) -> Option<Url> {
+        let Some((_, client, rest)) = self
+            .mappings
+            .iter()
+            .filter_map(|(client, server)| {
+                let rest = path.strip_prefix(server).ok()?;
+                Some((server.components().count(), client, rest))
+            })
+            .max_by_key(|(depth, _, _)| *depth)
+        else {
+            return Url::from_file_path(path).ok();
+        };
+        let rest = normalize_path(&rest.to_string_lossy());
+        // the segments are percent-encoded, including any `%`, `?`, or `#` in file names
+        let mut uri = Url::parse("file:///").ok()?;
+        uri.path_segments_mut()
+            .ok()?
+            .clear()
+            .extend(format!("{}{}", client, rest).split('/').skip(1));
+        Some(uri)
+    }
+}
+
+/// Normalizes a client path for comparison, with `/` separators, a leading `/`, no trailing `/`,
+/// and a lowercase drive letter, eg: `C:\Users\` to `/c:/Users`.
+fn normalize_path(path: &str) -> String {
+    let mut path = path.replace('\\', "/");
+    if !path.starts_with('/') {
+        path.insert(0, '/');
+    }
+    if path.len() >= 3 && path.as_bytes()[2] == b':' && path.as_bytes()[1].is_ascii_alphabetic() {
+        path[1..2].make_ascii_lowercase();
+    }
+    path.trim_end_matches('/').to_string()
+}
+
+/// Strips a normalized prefix from a normalized path at a `/`, returning the rest without a
+/// leading `/`.
+fn strip_path_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = path.strip_prefix(prefix)?;
+    if rest.is_empty() {
+        Some(rest)
+    } else {
+        rest.strip_prefix('/')
+    }
+}
+
+/// Applies a change to the text of a document, replacing either the given range or the whole text.
+///
+/// Positions are counted in code units of `encoding`.
+pub(crate) fn apply_content_change(
+    text: &mut String,
+    change: TextDocumentContentChangeEvent,
+    encoding: PositionEncoding,
+) {
+    match change.range {
+        Some(range) => {
+            let start = offset_at(text, range.start, encoding);
+            let end = offset_at(text, range.end, encoding).max(start);
+            text.replace_range(start..end, &change.text);
+        }
+        None => *text = change.text,
+    }
+}
+
+/// Converts a position with a character offset in code units of `encoding` to a byte offset in
+/// `text`.
+///
+/// Lines end like in LSP, at `\n`, `\r\n`, or a lone `\r`. Positions past the end of a line or
+/// the text are clamped to the end of the line, before its line break, or the text.
+fn offset_at(text: &str, position: Position, encoding: PositionEncoding) -> usize {
+    let Some(line_start) = crate::codetypo::line_starts(text).nth(position.line as usize) else {
+        return text.len();
+    };
+
+    let line = &text[line_start..];
+    let line = &line[..line.find(['\r', '\n']).unwrap_or(line.len())];
+    let mut character = 0;
+    for (i, c) in line.char_indices() {
+        if character >= position.character as usize {
+            return line_start + i;
+        }
+        character += encoding.char_units(c);
     }
+    line_start + line.len()
 }
 
-pub fn url_path_sanitised(url: &Url) -> String {
-    // windows paths (eg: /C:/Users/..) may not be percent-encoded by some clients
-    // and therefore contain colons, see
-    // https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#uri
-    //
-    // and because matchit treats colons as a wildcard we need to strip them
-    url.path().replace(':', "%3A")
+/// Expands a leading `~` in a path setting, treating `null` and empty strings as unset.
+fn expand_path(value: &Value) -> Option<PathBuf> {
+    value
+        .as_str()
+        .filter(|value| !value.is_empty())
+        .map(|value| PathBuf::from(shellexpand::tilde(value).to_string()))
 }
@@ -0,0 +1,120 @@
+//! Suggestions for typos with no or few corrections, found by searching the words of a vocabulary
+//! for those within a small Damerau-Levenshtein distance of the typo.
+
+use std::collections::HashSet;
+
+use codetypo::tokens::{Case, Word};
+
+/// The most suggestions returned for a typo.
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Returns the words of `vocabulary` closest to `typo`, nearest first, excluding `corrections`,
+/// which the typo already has.
+///
+/// Words are compared case insensitively, and suggestions are cased like the typo, eg: `Teh`
+/// suggests `The`.
+pub(crate) fn suggestions<'w>(
+    typo: &str,
+    vocabulary: impl IntoIterator<Item = &'w str>,
+    corrections: &[impl AsRef<str>],
+) -> Vec<String> {
+    let typo_lower = typo.to_lowercase();
+    let typo_chars = typo_lower.chars().collect::<Vec<_>>();
+    // short words are within a distance of 2 of too many unrelated words
+    let max_distance = if typo_chars.len() <= 4 { 1 } else { 2 };
+
+    let mut candidates = vocabulary
+        .into_iter()
+        .map(str::to_lowercase)
+        .filter(|word| {
+            *word != typo_lower
+                && !corrections
+                    .iter()
+                    .any(|correction| correction.as_ref().to_lowercase() == *word)
+        })
+        .filter_map(|word| {
+            let chars = word.chars().collect::<Vec<_>>();
+            if chars.len().abs_diff(typo_chars.len()) > max_distance {
+                return None;
+            }
+            let distance = distance(&typo_chars, &chars);
+            (distance <= max_distance).then_some((distance, word))
+        })
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates.dedup_by(|(_, a), (_, b)| a == b);
+
+    candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, word)| crate::codetypo::match_case(typo, &word))
+        .collect()
+}
+
+/// Returns the words that `dict` corrects the misspellings one edit away from `typo` to, ie: the
+/// dictionary's words near the typo.
+///
+/// The dictionary lists misspellings rather than valid words, so its words are found through the
+/// misspellings similar to the typo, eg: `recive` is one edit from `recieve`, which is corrected
+/// to `receive`.
+pub(crate) fn dictionary_words(typo: &str, dict: &dyn codetypo::Dictionary) -> Vec<String> {
+    let chars = typo.to_lowercase().chars().collect::<Vec<_>>();
+    let edits = (0..=chars.len())
+        .flat_map(|i| {
+            let (before, after) = chars.split_at(i);
+            let deletion = after.split_first().map(|(_, rest)| [before, rest].concat());
+            let transposition = match after {
+                [a, b, rest @ ..] => Some([before, &[*b, *a], rest].concat()),
+                _ => None,
+            };
+            let insertions = ('a'..='z').map(move |c| [before, &[c], after].concat());
+            let substitutions = after
+                .split_first()
+                .into_iter()
+                .flat_map(move |(_, rest)| ('a'..='z').map(move |c| [before, &[c], rest].concat()));
+            deletion
+                .into_iter()
+                .chain(transposition)
+                .chain(insertions)
+                .chain(substitutions)
+        })
+        .map(|edit| edit.into_iter().collect::<String>())
+        .collect::<HashSet<_>>();
+
+    edits
+        .iter()
+        .filter_map(|edit| dict.correct_word(Word::new_unchecked(edit, Case::Lower, 0)))
+        .flat_map(|status| match status {
+            codetypo::Status::Corrections(corrections) => corrections
+                .into_iter()
+                .map(|correction| correction.into_owned())
+                .collect(),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+/// Returns the optimal string alignment distance between two words, ie: the Damerau-Levenshtein
+/// distance where no substring is edited more than once, counting insertions, deletions,
+/// substitutions, and transpositions of adjacent chars.
+fn distance(a: &[char], b: &[char]) -> usize {
+    // rows of the distances between the prefixes of `a` and `b`, from two rows back to the current
+    let mut previous2 = vec![0; b.len() + 1];
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(previous2[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut previous2, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,249 @@
+//! Lightweight syntax awareness, used to only check the comments and string literals of code, and
+//! the prose of git commit messages.
+
+use std::ops::Range;
+use std::path::Path;
+
+/// The comment and string literal delimiters of a language.
+pub(crate) struct Syntax {
+    line_comments: &'static [&'static str],
+    block_comments: &'static [(&'static str, &'static str)],
+    /// String delimiters, longest first so eg: `"""` is matched before `"`.
+    strings: &'static [&'static str],
+}
+
+const C_LIKE: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    strings: &["\""],
+};
+
+const JAVASCRIPT: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    strings: &["`", "\"", "'"],
+};
+
+const PYTHON: Syntax = Syntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    strings: &["\"\"\"", "'''", "\"", "'"],
+};
+
+const HASH: Syntax = Syntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    strings: &["\"", "'"],
+};
+
+const LUA: Syntax = Syntax {
+    line_comments: &["--"],
+    block_comments: &[("--[[", "]]")],
+    strings: &["\"", "'"],
+};
+
+const SQL: Syntax = Syntax {
+    line_comments: &["--"],
+    block_comments: &[("/*", "*/")],
+    strings: &["'"],
+};
+
+const HASKELL: Syntax = Syntax {
+    line_comments: &["--"],
+    block_comments: &[("{-", "-}")],
+    strings: &["\""],
+};
+
+const CSS: Syntax = Syntax {
+    line_comments: &[],
+    block_comments: &[("/*", "*/")],
+    strings: &["\"", "'"],
+};
+
+const SCSS: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    strings: &["\"", "'"],
+};
+
+impl Syntax {
+    /// Returns the syntax for an LSP language identifier, or `None` if the language isn't known
+    /// or is prose (eg: markdown) that should be checked in full.
+    pub(crate) fn from_language_id(language_id: &str) -> Option<&'static Syntax> {
+        // see https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocumentItem
+        match language_id {
+            "c" | "cpp" | "csharp" | "go" | "java" | "kotlin" | "rust" | "scala" | "swift"
+            | "dart" | "objective-c" | "objective-cpp" | "zig" => Some(&C_LIKE),
+            "javascript" | "javascriptreact" | "typescript" | "typescriptreact" | "php" => {
+                Some(&JAVASCRIPT)
+            }
+            "python" => Some(&PYTHON),
+            "shellscript" | "ruby" | "perl" | "r" | "yaml" | "toml" | "makefile" | "dockerfile"
+            | "elixir" | "powershell" => Some(&HASH),
+            "lua" => Some(&LUA),
+            "sql" => Some(&SQL),
+            "haskell" => Some(&HASKELL),
+            "css" => Some(&CSS),
+            "scss" | "less" => Some(&SCSS),
+            _ => None,
+        }
+    }
+
+    /// Returns the syntax for a file that isn't open, and so has no language identifier, based on
+    /// its extension.
+    pub(crate) fn from_path(path: &Path) -> Option<&'static Syntax> {
+        let language_id = match path.extension()?.to_str()? {
+            "c" | "h" => "c",
+            "cc" | "cpp" | "cxx" | "hpp" | "hxx" => "cpp",
+            "cs" => "csharp",
+            "go" => "go",
+            "java" => "java",
+            "kt" | "kts" => "kotlin",
+            "rs" => "rust",
+            "scala" => "scala",
+            "swift" => "swift",
+            "dart" => "dart",
+            "zig" => "zig",
+            "js" | "mjs" | "cjs" | "jsx" => "javascript",
+            "ts" | "mts" | "cts" | "tsx" => "typescript",
+            "php" => "php",
+            "py" | "pyi" => "python",
+            "sh" | "bash" | "zsh" => "shellscript",
+            "rb" => "ruby",
+            "pl" | "pm" => "perl",
+            "r" => "r",
+            "yaml" | "yml" => "yaml",
+            "toml" => "toml",
+            "ex" | "exs" => "elixir",
+            "ps1" => "powershell",
+            "lua" => "lua",
+            "sql" => "sql",
+            "hs" => "haskell",
+            "css" => "css",
+            "scss" => "scss",
+            "less" => "less",
+            _ => return None,
+        };
+        Self::from_language_id(language_id)
+    }
+
+    /// Returns a comment containing `text`, preferring a line comment, eg: `// text`.
+    pub(crate) fn comment(&self, text: &str) -> Option<String> {
+        match (self.line_comments.first(), self.block_comments.first()) {
+            (Some(line_comment), _) => Some(format!("{} {}", line_comment, text)),
+            (None, Some((start, end))) => Some(format!("{} {} {}", start, text, end)),
+            (None, None) => None,
+        }
+    }
+
+    /// Returns the byte ranges of the comments and string literals in `text`.
+    pub(crate) fn extract(&self, text: &str) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        let mut i = 0;
+
+        while i < text.len() {
+            let rest = &text[i..];
+
+            let end = if let Some((start, end)) = self
+                .block_comments
+                .iter()
+                .find(|(start, _)| rest.starts_with(start))
+            {
+                rest[start.len()..]
+                    .find(end)
+                    .map(|j| i + start.len() + j + end.len())
+                    .unwrap_or(text.len())
+            } else if self
+                .line_comments
+                .iter()
+                .any(|comment| rest.starts_with(comment))
+            {
+                rest.find('\n').map(|j| i + j).unwrap_or(text.len())
+            } else if let Some(quote) = self.strings.iter().find(|quote| rest.starts_with(*quote)) {
+                i + quote.len() + string_len(&rest[quote.len()..], quote)
+            } else {
+                // advance by a whole character to stay on a char boundary
+                i += rest.chars().next().map_or(1, char::len_utf8);
+                continue;
+            };
+
+            ranges.push(i..end);
+            i = end;
+        }
+
+        ranges
+    }
+}
+
+/// Returns whether a document is a git commit message, from its language identifier, eg:
+/// `git-commit` in VS Code and `gitcommit` in Neovim, or its file name.
+pub(crate) fn is_commit_message(language_id: Option<&str>, file_name: Option<&str>) -> bool {
+    matches!(language_id, Some("git-commit" | "gitcommit")) || file_name == Some("COMMIT_EDITMSG")
+}
+
+/// Returns the byte ranges of the prose of a git commit message, skipping comment lines, the diff
+/// added by `git commit --verbose`, and trailers, eg: `Signed-off-by: ...`.
+pub(crate) fn commit_message(text: &str) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let line = line.trim_end_matches(['\n', '\r']);
+        // everything after the scissors line, or the diff if it has none, is for reference only
+        if line.starts_with("# ") && line.contains(" >8 ") || line.starts_with("diff --git ") {
+            break;
+        }
+        if !line.starts_with('#') {
+            lines.push((start..start + line.len(), line));
+        }
+    }
+
+    let is_blank = |(_, line): &(Range<usize>, &str)| line.trim().is_empty();
+    while lines.last().is_some_and(is_blank) {
+        lines.pop();
+    }
+    // trailers are the last paragraph, when it isn't the subject and only has `Key: value` lines
+    if let Some(i) = lines.iter().rposition(is_blank) {
+        let is_subject = !lines[..i].iter().any(|line| !is_blank(line));
+        let mut paragraph = lines[i + 1..].iter();
+        if !is_subject
+            && paragraph.next().is_some_and(|(_, line)| is_trailer(line))
+            && paragraph.all(|(_, line)| is_trailer(line) || line.starts_with(char::is_whitespace))
+        {
+            lines.truncate(i);
+        }
+    }
+
+    lines
+        .into_iter()
+        .map(|(range, _)| range)
+        .filter(|range| !range.is_empty())
+        .collect()
+}
+
+/// Returns whether a line of a commit message is a trailer, eg: `Signed-off-by: Name <email>`.
+fn is_trailer(line: &str) -> bool {
+    line.split_once(": ").is_some_and(|(key, _)| {
+        !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+/// Returns the length of a string literal's content and closing quote, skipping escaped quotes.
+///
+/// Strings delimited by a single `"` or `'` end at the end of the line if they're unterminated.
+fn string_len(text: &str, quote: &str) -> usize {
+    let single_line = quote == "\"" || quote == "'";
+    let mut chars = text.char_indices();
+
+    while let Some((j, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if text[j..].starts_with(quote) {
+            return j + quote.len();
+        } else if c == '\n' && single_line {
+            return j;
+        }
+    }
+    text.len()
+}
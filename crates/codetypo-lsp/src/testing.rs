@@ -0,0 +1,195 @@
+//! An in-process harness for integration tests of the server, eg: by editor extensions.
+//!
+//! [`TestServer`] serves the [`Backend`] over in-memory pipes, so tests exchange the same
+//! JSON-RPC messages with it as an editor would, without spawning a process:
+//!
+//! ```no_run
+//! # async fn test() {
+//! use codetypo_lsp::testing::TestServer;
+//! use tower_lsp::lsp_types::Url;
+//!
+//! let mut server = TestServer::new();
+//! server.initialize(serde_json::json!({ "capabilities": {} })).await;
+//! let uri = Url::parse("file:///tmp/README.md").unwrap();
+//! server.did_open(&uri, "markdown", "teh").await;
+//! server.assert_typos(&uri, &["teh"]).await;
+//! # }
+//! ```
+//!
+//! It must be used within a tokio runtime, eg: in a `#[tokio::test]`.
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, PublishDiagnosticsParams, Url};
+use tower_lsp::Server;
+
+use crate::lsp::Backend;
+
+/// A server running in the background, and the client side of the pipes it's served over.
+pub struct TestServer {
+    req_client: tokio::io::DuplexStream,
+    resp_client: tokio::io::DuplexStream,
+    buf: Vec<u8>,
+}
+
+impl Default for TestServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestServer {
+    /// Starts a server as a concurrent task.
+    pub fn new() -> Self {
+        let (req_client, req_server) = tokio::io::duplex(1024);
+        let (resp_server, resp_client) = tokio::io::duplex(1024);
+
+        let (service, socket) = Backend::service();
+
+        // start server as concurrent task
+        tokio::spawn(Server::new(req_server, resp_server, socket).serve(service));
+
+        Self {
+            req_client,
+            resp_client,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Sends a message and returns the next message from the server.
+    pub async fn request(&mut self, msg: &str) -> Value {
+        self.send(msg).await;
+        self.recv().await
+    }
+
+    /// Sends a request and returns its response, along with the `count` other messages the server
+    /// sends while handling it, which can arrive before or after the response.
+    pub async fn request_with_messages(&mut self, msg: &str, count: usize) -> (Value, Vec<Value>) {
+        self.send(msg).await;
+
+        let mut response = None;
+        let mut messages = Vec::new();
+        while response.is_none() || messages.len() < count {
+            let msg = self.recv().await;
+            if msg.get("result").is_some() || msg.get("error").is_some() {
+                response = Some(msg);
+            } else {
+                messages.push(msg);
+            }
+        }
+
+        (response.unwrap(), messages)
+    }
+
+    /// Sends a message without waiting for the server to respond.
+    pub async fn send(&mut self, msg: &str) {
+        tracing::debug!("{}", msg);
+        let msg = format!("Content-Length: {}\r\n\r\n{}", msg.len(), msg);
+
+        self.req_client.write_all(msg.as_bytes()).await.unwrap();
+    }
+
+    /// Returns the next message from the server.
+    pub async fn recv(&mut self) -> Value {
+        loop {
+            if let Some((s, len)) = body(&self.buf).unwrap() {
+                // convert to json value to normalise key order for comparison
+                let value = serde_json::from_str(s).unwrap();
+                self.buf.drain(..len);
+                return value;
+            }
+
+            let mut chunk = [0; 1024];
+            let n = self.resp_client.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "server closed the connection");
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    /// Sends an `initialize` request with id 1 and `params`, eg: the client capabilities and
+    /// `initializationOptions`, and returns its response.
+    pub async fn initialize(&mut self, params: Value) -> Value {
+        let msg = json!({
+            "jsonrpc": "2.0",
+            "method": "initialize",
+            "params": params,
+            "id": 1
+        });
+        self.request(&msg.to_string()).await
+    }
+
+    /// Opens a document at version 1.
+    pub async fn did_open(&mut self, uri: &Url, language_id: &str, text: &str) {
+        let msg = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": language_id,
+                    "version": 1,
+                    "text": text
+                }
+            }
+        });
+        self.send(&msg.to_string()).await;
+    }
+
+    /// Returns the diagnostics next published for `uri`, skipping other messages, eg: the
+    /// diagnostics of other documents and notifications.
+    pub async fn diagnostics(&mut self, uri: &Url) -> Vec<Diagnostic> {
+        loop {
+            let msg = self.recv().await;
+            if msg.get("method").and_then(Value::as_str) != Some("textDocument/publishDiagnostics")
+            {
+                continue;
+            }
+            let params: PublishDiagnosticsParams =
+                serde_json::from_value(msg["params"].clone()).unwrap();
+            if &params.uri == uri {
+                return params.diagnostics;
+            }
+        }
+    }
+
+    /// Asserts that the diagnostics next published for `uri` are of `typos`, in order.
+    pub async fn assert_typos(&mut self, uri: &Url, typos: &[&str]) {
+        let diagnostics = self.diagnostics(uri).await;
+        let codes = diagnostics
+            .iter()
+            .map(|diagnostic| match &diagnostic.code {
+                Some(NumberOrString::String(code)) => code.as_str(),
+                _ => "",
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(codes, typos, "diagnostics of {}: {:?}", uri, diagnostics);
+    }
+}
+
+/// Returns the body of the first complete message in `src` and the length of the message, or
+/// `None` if more bytes are needed.
+fn body(src: &[u8]) -> anyhow::Result<Option<(&str, usize)>, anyhow::Error> {
+    // parse headers to get headers length
+    let mut dst = [httparse::EMPTY_HEADER; 2];
+
+    let (headers_len, headers) = match httparse::parse_headers(src, &mut dst)? {
+        httparse::Status::Complete(output) => output,
+        httparse::Status::Partial => return Ok(None),
+    };
+
+    let content_length: usize = headers
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case("Content-Length"))
+        .ok_or_else(|| anyhow::anyhow!("missing Content-Length"))
+        .and_then(|h| Ok(std::str::from_utf8(h.value)?.parse()?))?;
+
+    // skip headers and return the rest (ie: the body) as &str
+    let Some(body) = src.get(headers_len..headers_len + content_length) else {
+        return Ok(None);
+    };
+
+    Ok(Some((
+        std::str::from_utf8(body)?,
+        headers_len + content_length,
+    )))
+}
@@ -18,3 +18,58 @@ pub fn get_drives() -> Vec<String> {
     }
     drives
 }
+
+/// Returns the root directory of a drive, eg: `C:\` for `C`.
+#[cfg(windows)]
+pub fn drive_root(drive: &str) -> std::path::PathBuf {
+    std::path::PathBuf::from(format!("{}:\\", drive))
+}
+
+/// Returns whether `path` is the root directory of a drive, eg: `C:\`.
+#[cfg(windows)]
+pub fn is_drive_root(path: &std::path::Path) -> bool {
+    use std::path::{Component, Prefix};
+
+    let mut components = path.components();
+    matches!(
+        (components.next(), components.next(), components.next()),
+        (Some(Component::Prefix(prefix)), Some(Component::RootDir), None)
+            if matches!(prefix.kind(), Prefix::Disk(_) | Prefix::VerbatimDisk(_))
+    )
+}
+
+/// Strips the `\\?\` prefix [`std::fs::canonicalize`] adds on Windows, eg: from
+/// `\\?\C:\project` or `\\?\UNC\server\share`, so canonical paths compare equal to the
+/// paths of URIs.
+#[cfg(windows)]
+pub fn strip_verbatim(path: std::path::PathBuf) -> std::path::PathBuf {
+    let Some(s) = path.to_str() else {
+        return path;
+    };
+    if let Some(rest) = s.strip_prefix(r"\\?\UNC\") {
+        std::path::PathBuf::from(format!(r"\\{}", rest))
+    } else if let Some(rest) = s
+        .strip_prefix(r"\\?\")
+        .filter(|rest| rest.get(1..2) == Some(":"))
+    {
+        std::path::PathBuf::from(rest)
+    } else {
+        path
+    }
+}
+
+/// Returns the root of the network share containing `path`, eg: `\\server\share\`, or `None` if
+/// it isn't a UNC path.
+pub fn unc_share(path: &std::path::Path) -> Option<std::path::PathBuf> {
+    use std::path::{Component, PathBuf, Prefix};
+
+    let Some(Component::Prefix(prefix)) = path.components().next() else {
+        return None;
+    };
+    match prefix.kind() {
+        Prefix::UNC(..) | Prefix::VerbatimUNC(..) => {
+            Some(PathBuf::from(prefix.as_os_str()).join(std::path::MAIN_SEPARATOR_STR))
+        }
+        _ => None,
+    }
+}
@@ -1,13 +1,18 @@
+use codetypo_lsp::testing::TestServer;
 use serde_json::{json, Value};
 use std::{path::PathBuf, str::FromStr};
 use tower_lsp::lsp_types::Url;
-mod common;
-use common::TestServer;
 use {once_cell::sync::Lazy, regex::Regex};
 
 #[test_log::test(tokio::test)]
 async fn test_initialize_e2e() {
     let mut server = TestServer::new();
+    let config_file_operations = json!({
+        "filters": codetypo_cli::config::SUPPORTED_FILE_NAMES
+            .iter()
+            .map(|name| json!({ "scheme": "file", "pattern": { "glob": format!("**/{}", name), "matches": "file" } }))
+            .collect::<Vec<_>>()
+    });
 
     similar_asserts::assert_eq!(
         server.request(&initialize()).await,
@@ -17,13 +22,56 @@ async fn test_initialize_e2e() {
             "result": {
               "capabilities": {
                 "codeActionProvider": {
-                  "codeActionKinds": ["quickfix"],
+                  "codeActionKinds": ["quickfix", "source.fixAll.codetypo"],
+                  "resolveProvider": true,
                   "workDoneProgress": false
                 },
+                "diagnosticProvider": {
+                  "identifier": "codetypo",
+                  "interFileDependencies": false,
+                  "workspaceDiagnostics": true
+                },
+                "hoverProvider": true,
+                "completionProvider": {},
+                "documentHighlightProvider": true,
+                "inlayHintProvider": true,
+                "documentFormattingProvider": true,
+                "documentRangeFormattingProvider": true,
+                "codeLensProvider": { "resolveProvider": false },
+                "semanticTokensProvider": {
+                  "full": true,
+                  "legend": { "tokenModifiers": ["disallowed"], "tokenTypes": ["typo"] }
+                },
+                "executeCommandProvider": {
+                  "commands": [
+                    "codetypo.addToProjectDictionary",
+                    "codetypo.addCorrection",
+                    "codetypo.ignoreWordInFile",
+                    "codetypo.addToUserDictionary",
+                    "codetypo.ignoreWordSession",
+                    "codetypo.fixAll",
+                    "codetypo.replaceInWorkspace",
+                    "codetypo.fixFile",
+                    "codetypo.scanWorkspace",
+                    "codetypo.checkFileNames",
+                    "codetypo.toggle",
+                    "codetypo.moreSuggestions",
+                    "codetypo.applyWorkspaceEdit"
+                  ]
+                },
                 "positionEncoding": "utf-16",
-                "textDocumentSync": 1,
+                "textDocumentSync": {
+                  "change": 2,
+                  "openClose": true,
+                  "save": true,
+                  "willSaveWaitUntil": true
+                },
                 "workspace": {
-                  "workspaceFolders": { "changeNotifications": true, "supported": true }
+                  "workspaceFolders": { "changeNotifications": true, "supported": true },
+                  "fileOperations": {
+                    "didCreate": config_file_operations,
+                    "didDelete": config_file_operations
+                  }
                 }
               },
               "serverInfo": { "name": "codetypo", "version": env!("CARGO_PKG_VERSION") }
@@ -85,7 +133,7 @@ async fn test_code_action() {
         server.request(&did_open).await,
         publish_diagnostics(&[
             diag("`apropriate` should be `appropriate`", 0, 11, 21),
-            diag("`fo` should be `of`, `for`, `do`, `go`, `to`", 1, 0, 2)
+            diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 1, 0, 2)
         ])
     );
 
@@ -107,6 +155,7 @@ async fn test_code_action() {
                     ]
                   }
                 },
+                "isPreferred": true,
                 "kind": "quickfix",
                 "title": "of"
               },
@@ -124,6 +173,26 @@ async fn test_code_action() {
                 },
                 "kind": "quickfix",
                 "title": "for"
+              },
+              {
+                "diagnostics": [ diag("`fo` should be `of`, `for`", 1, 0, 2) ],
+                "command": {
+                  "title": "Add `fo` to user dictionary",
+                  "command": "codetypo.addToUserDictionary",
+                  "arguments": ["fo"]
+                },
+                "kind": "quickfix",
+                "title": "Add `fo` to user dictionary"
+              },
+              {
+                "diagnostics": [ diag("`fo` should be `of`, `for`", 1, 0, 2) ],
+                "command": {
+                  "title": "Ignore `fo` for this session",
+                  "command": "codetypo.ignoreWordSession",
+                  "arguments": ["fo"]
+                },
+                "kind": "quickfix",
+                "title": "Ignore `fo` for this session"
               }
             ],
             "id": 2
@@ -152,6 +221,26 @@ async fn test_code_action() {
                 "isPreferred": true,
                 "kind": "quickfix",
                 "title": "appropriate"
+              },
+              {
+                "diagnostics": [ diag("`apropriate` should be `appropriate`", 0, 11, 21) ],
+                "command": {
+                  "title": "Add `apropriate` to user dictionary",
+                  "command": "codetypo.addToUserDictionary",
+                  "arguments": ["apropriate"]
+                },
+                "kind": "quickfix",
+                "title": "Add `apropriate` to user dictionary"
+              },
+              {
+                "diagnostics": [ diag("`apropriate` should be `appropriate`", 0, 11, 21) ],
+                "command": {
+                  "title": "Ignore `apropriate` for this session",
+                  "command": "codetypo.ignoreWordSession",
+                  "arguments": ["apropriate"]
+                },
+                "kind": "quickfix",
+                "title": "Ignore `apropriate` for this session"
               }
             ],
             "id": 3
@@ -161,160 +250,4373 @@ async fn test_code_action() {
 }
 
 #[test_log::test(tokio::test)]
-async fn test_config_file() {
-    let workspace_folder_uri =
-        Url::from_file_path(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")).unwrap();
-    let diag_txt = workspace_folder_uri.join("tests/diagnostics.txt").unwrap();
-    let changelog_md = workspace_folder_uri.join("tests/CHANGELOG.md").unwrap();
-    let skip_me = workspace_folder_uri.join("tests/skip_me.txt").unwrap();
+async fn test_suppress_line() {
+    let mut did_open: Value =
+        serde_json::from_str(&did_open("fn main() {\n    let teh = 1;\n}\n")).unwrap();
+    did_open["params"]["textDocument"]["languageId"] = json!("rust");
 
-    let did_open_diag_txt = did_open_with("fo codetypo", Some(&diag_txt));
-    let did_open_changelog_md = did_open_with("fo codetypo", Some(&changelog_md));
-    let did_open_skip_me = did_open_with("fo codetypo # skip_me", Some(&skip_me));
+    let code_action = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/codeAction",
+        "params": {
+          "textDocument": {
+            "uri": "file:///C%3A/diagnostics.txt"
+          },
+          "range": range(1, 8, 11),
+          "context": {
+            "diagnostics": [ diag("`teh` should be `the`", 1, 8, 11) ],
+            "only": ["quickfix"],
+            "triggerKind": 1
+          }
+        },
+        "id": 2
+      }
+    )
+    .to_string();
 
     let mut server = TestServer::new();
-    let _ = server
-        .request(&initialize_with(Some(&workspace_folder_uri), None))
-        .await;
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open.to_string()).await;
 
-    // check "fo" is corrected to "of" because of default.extend-words
+    // the comment is inserted above the line with the same indentation
+    let response = server.request(&code_action).await;
+    let actions = response["result"].as_array().unwrap();
     similar_asserts::assert_eq!(
-        server.request(&did_open_diag_txt).await,
-        publish_diagnostics_with(&[diag("`fo` should be `of`", 0, 0, 2)], Some(&diag_txt))
+        actions.last().unwrap(),
+        &json!(
+          {
+            "diagnostics": [ diag("`teh` should be `the`", 1, 8, 11) ],
+            "edit": {
+              "changes": {
+                "file:///C%3A/diagnostics.txt": [
+                  {
+                    "newText": "    // codetypo:disable-next-line\n",
+                    "range": range(1, 0, 0)
+                  }
+                ]
+              }
+            },
+            "kind": "quickfix",
+            "title": "Suppress codetypo for this line"
+          }
+        )
     );
+}
 
-    // check changelog is excluded because of files.extend-exclude
-    similar_asserts::assert_eq!(
-        server.request(&did_open_changelog_md).await,
-        publish_diagnostics_with(&[], Some(&changelog_md)),
-    );
+#[test_log::test(tokio::test)]
+async fn test_fix_all() {
+    let did_open = did_open("this is an apropriate test\nfo codetypo\n");
+
+    let code_action = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/codeAction",
+        "params": {
+          "textDocument": {
+            "uri": "file:///C%3A/diagnostics.txt"
+          },
+          "range": {
+            "start": { "line": 0, "character": 0 },
+            "end": { "line": 0, "character": 0 }
+          },
+          "context": {
+            "diagnostics": [],
+            "only": ["source.fixAll"],
+            "triggerKind": 2
+          }
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open).await;
 
-    // check skip_line is excluded because of default.extend-ignore-re
     similar_asserts::assert_eq!(
-        server.request(&did_open_skip_me).await,
-        publish_diagnostics_with(&[], Some(&skip_me)),
+        server.request(&code_action).await,
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "result": [
+              {
+                "edit": {
+                  "changes": {
+                    "file:///C%3A/diagnostics.txt": [
+                      {
+                        "newText": "appropriate",
+                        "range": {
+                          "end": { "character": 21, "line": 0 },
+                          "start": { "character": 11, "line": 0 }
+                        }
+                      },
+                      {
+                        "newText": "to",
+                        "range": {
+                          "end": { "character": 2, "line": 1 },
+                          "start": { "character": 0, "line": 1 }
+                        }
+                      }
+                    ]
+                  }
+                },
+                "kind": "source.fixAll.codetypo",
+                "title": "Fix all typos"
+              }
+            ],
+            "id": 2
+          }
+        )
     );
 }
 
 #[test_log::test(tokio::test)]
-async fn test_custom_config_file() {
-    let custom_config = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-        .join("tests")
-        .join("custom_codetypo.toml");
-
-    let workspace_folder_uri =
-        Url::from_file_path(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")).unwrap();
-
-    let diag_txt = workspace_folder_uri.join("tests/diagnostics.txt").unwrap();
+async fn test_fix_all_occurrences() {
+    let did_open = did_open("teh cat and teh dog\n");
 
-    let did_open_diag_txt = did_open_with("fo codetypo", Some(&diag_txt));
+    let code_action = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/codeAction",
+        "params": {
+          "textDocument": {
+            "uri": "file:///C%3A/diagnostics.txt"
+          },
+          "range": {
+            "start": { "line": 0, "character": 0 },
+            "end": { "line": 0, "character": 3 }
+          },
+          "context": {
+            "diagnostics": [ diag("`teh` should be `the`", 0, 0, 3) ],
+            "only": ["quickfix"],
+            "triggerKind": 1
+          }
+        },
+        "id": 2
+      }
+    )
+    .to_string();
 
     let mut server = TestServer::new();
-    let _ = server
-        .request(&initialize_with(
-            Some(&workspace_folder_uri),
-            Some(&custom_config),
-        ))
-        .await;
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open).await;
 
-    // check "fo" is corrected to "go" because of default.extend-words
-    // in custom_codetypo.toml which overrides codetypo.toml
+    let response = server.request(&code_action).await;
     similar_asserts::assert_eq!(
-        server.request(&did_open_diag_txt).await,
-        publish_diagnostics_with(&[diag("`fo` should be `go`", 0, 0, 2)], Some(&diag_txt))
+        response["result"][1],
+        json!(
+          {
+            "diagnostics": [ diag("`teh` should be `the`", 0, 0, 3) ],
+            "edit": {
+              "changes": {
+                "file:///C%3A/diagnostics.txt": [
+                  {
+                    "newText": "the",
+                    "range": {
+                      "end": { "character": 3, "line": 0 },
+                      "start": { "character": 0, "line": 0 }
+                    }
+                  },
+                  {
+                    "newText": "the",
+                    "range": {
+                      "end": { "character": 15, "line": 0 },
+                      "start": { "character": 12, "line": 0 }
+                    }
+                  }
+                ]
+              }
+            },
+            "kind": "quickfix",
+            "title": "Fix all `teh` → `the` in this file"
+          }
+        )
     );
 }
 
 #[test_log::test(tokio::test)]
-async fn test_custom_config_no_workspace_folder() {
-    // mimics Neovim opening a file outside the root dir
-    let custom_config = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-        .join("tests")
-        .join("custom_codetypo.toml");
-
-    let workspace_folder_uri =
-        Url::from_file_path(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")).unwrap();
-
-    let diag_txt = workspace_folder_uri.join("tests/diagnostics.txt").unwrap();
+async fn test_effective_config() {
+    let workspace_folder = temp_dir("test_effective_config");
+    std::fs::write(
+        workspace_folder.join("codetypo.toml"),
+        "[files]\nextend-exclude = [\"excluded.txt\"]\n\n[default]\nextend-ignore-re = [\"ignored\"]\n\n[default.extend-words]\nrecieve = \"receive\"\n",
+    )
+    .unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
 
-    let did_open_diag_txt = did_open_with("fo codetypo", Some(&diag_txt));
+    let effective_config = |file_name: &str| {
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "method": "codetypo/effectiveConfig",
+            "params": {
+              "textDocument": {
+                "uri": Url::from_file_path(workspace_folder.join(file_name)).unwrap()
+              }
+            },
+            "id": 2
+          }
+        )
+        .to_string()
+    };
 
     let mut server = TestServer::new();
     let _ = server
-        .request(&initialize_with(None, Some(&custom_config)))
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
         .await;
 
-    // check "fo" is corrected to "go" because of default.extend-words
-    // in custom_codetypo.toml which overrides codetypo.toml
+    let response = server.request(&effective_config("diagnostics.txt")).await;
+    let result = &response["result"];
     similar_asserts::assert_eq!(
-        server.request(&did_open_diag_txt).await,
-        publish_diagnostics_with(&[diag("`fo` should be `go`", 0, 0, 2)], Some(&diag_txt))
+        (
+            &result["configFiles"],
+            &result["excluded"],
+            &result["ignoreRegexes"],
+            &result["config"]["default"]["extend-words"]
+        ),
+        (
+            &json!([workspace_folder.join("codetypo.toml")]),
+            &json!(false),
+            &json!(["ignored"]),
+            &json!({ "recieve": "receive" })
+        )
     );
+
+    let response = server.request(&effective_config("excluded.txt")).await;
+    similar_asserts::assert_eq!(response["result"]["excluded"], json!(true));
 }
 
 #[test_log::test(tokio::test)]
-async fn test_non_file_uri() {
-    // a Neovim toggleterm uri
-    let term = Url::from_str("term://~/code/codetypo-lsp//59317:/bin/zsh;#toggleterm#1").unwrap();
+async fn test_dynamic_registration() {
+    let initialized =
+        json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }).to_string();
+    let did_change_configuration = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/didChangeConfiguration",
+        "params": {
+          "settings": { "codetypo": { "disabledCapabilities": ["codeActions"] } }
+        }
+      }
+    )
+    .to_string();
 
-    let did_open_diag_txt = did_open_with("apropriate", Some(&term));
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["capabilities"]["textDocument"]["codeAction"] =
+        json!({ "dynamicRegistration": true });
 
     let mut server = TestServer::new();
-    let _ = server.request(&initialize_with(None, None)).await;
+    let response = server.request(&initialize.to_string()).await;
+    // registered dynamically instead
+    similar_asserts::assert_eq!(
+        response["result"]["capabilities"]["codeActionProvider"],
+        Value::Null
+    );
+    assert!(response["result"]["capabilities"]["documentFormattingProvider"].is_boolean());
 
+    server.send(&initialized).await;
+    let _ = server.recv().await;
     similar_asserts::assert_eq!(
-        server.request(&did_open_diag_txt).await,
-        publish_diagnostics_with(
-            &[diag("`apropriate` should be `appropriate`", 0, 0, 10)],
-            Some(&term)
-        )
+        server.recv().await,
+        json!({
+          "jsonrpc": "2.0",
+          "method": "client/registerCapability",
+          "params": {
+            "registrations": [{
+              "id": "codetypo-textDocument/codeAction",
+              "method": "textDocument/codeAction",
+              "registerOptions": {
+                "codeActionKinds": ["quickfix", "source.fixAll.codetypo"],
+                "resolveProvider": true
+              }
+            }]
+          },
+          "id": 0
+        })
+    );
+    server
+        .send(&json!({ "jsonrpc": "2.0", "result": null, "id": 0 }).to_string())
+        .await;
+
+    // turning code actions off unregisters them
+    similar_asserts::assert_eq!(
+        server.request(&did_change_configuration).await,
+        json!({
+          "jsonrpc": "2.0",
+          "method": "client/unregisterCapability",
+          "params": {
+            "unregisterations": [{
+              "id": "codetypo-textDocument/codeAction",
+              "method": "textDocument/codeAction"
+            }]
+          },
+          "id": 1
+        })
     );
 }
 
 #[test_log::test(tokio::test)]
-async fn test_empty_file_uri() {
-    // eg: when using nvim telescope
-    let term = Url::from_str("file:///").unwrap();
+async fn test_ping() {
+    let workspace_folder = temp_dir("test_ping");
+    std::fs::write(workspace_folder.join("codetypo.toml"), "").unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
 
-    let did_open_diag_txt = did_open_with("apropriate", Some(&term));
+    let ping = json!({ "jsonrpc": "2.0", "method": "codetypo/ping", "id": 2 }).to_string();
 
     let mut server = TestServer::new();
-    let _ = server.request(&initialize_with(None, None)).await;
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+    let _ = server.request(&did_open("teh")).await;
 
+    let response = server.request(&ping).await;
+    let result = &response["result"];
     similar_asserts::assert_eq!(
-        server.request(&did_open_diag_txt).await,
-        publish_diagnostics_with(
-            &[diag("`apropriate` should be `appropriate`", 0, 0, 10)],
-            Some(&term)
+        (
+            &result["workspaceFolders"],
+            &result["routedWorkspaceFolders"],
+            &result["configFiles"],
+            &result["openDocuments"]
+        ),
+        (
+            &json!(1),
+            &json!(1),
+            &json!([workspace_folder.join("codetypo.toml")]),
+            &json!(1)
         )
     );
+    assert!(result["uptimeSecs"].is_u64());
+    if cfg!(target_os = "linux") {
+        assert!(result["memory"]["resident"].as_u64().unwrap() > 0);
+    }
 }
 
 #[test_log::test(tokio::test)]
-async fn test_position_with_unicode_text() {
+async fn test_summary() {
+    let workspace_folder = temp_dir("test_summary");
+    std::fs::write(workspace_folder.join("a.txt"), "teh teh recieve\n").unwrap();
+    std::fs::write(workspace_folder.join("b.txt"), "teh\n").unwrap();
+    std::fs::write(workspace_folder.join("c.txt"), "the\n").unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+
+    let summary = |top: Value| {
+        json!({ "jsonrpc": "2.0", "method": "codetypo/summary", "params": top, "id": 2 })
+            .to_string()
+    };
+
     let mut server = TestServer::new();
-    let _ = server.request(&initialize()).await;
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+    // open documents are counted in their unsaved text
+    let b_uri = Url::from_file_path(workspace_folder.join("b.txt")).unwrap();
+    let _ = server
+        .request(&did_open_with("teh apropriate", Some(&b_uri)))
+        .await;
 
-    // ¿ and é are two-byte code points in utf-8
-    let unicode_text = did_open("¿Qué hace él?");
     similar_asserts::assert_eq!(
-        server.request(&unicode_text).await,
-        publish_diagnostics(&[diag("`hace` should be `have`", 0, 5, 9)])
+        server.request(&summary(json!({}))).await["result"],
+        json!({
+            "total": 5,
+            "files": [
+                { "uri": Url::from_file_path(workspace_folder.join("a.txt")).unwrap(), "typos": 3 },
+                { "uri": b_uri, "typos": 2 },
+            ],
+            "topTypos": [
+                { "typo": "teh", "count": 3, "files": 2 },
+                { "typo": "apropriate", "count": 1, "files": 1 },
+                { "typo": "recieve", "count": 1, "files": 1 },
+            ]
+        })
     );
-
-    // ẽ has two code points U+0065 U+0303 (latin small letter e, combining tilde)
-    let unicode_text = did_open("ẽ hace");
     similar_asserts::assert_eq!(
-        server.request(&unicode_text).await,
-        publish_diagnostics(&[diag("`hace` should be `have`", 0, 2, 6)])
+        server.request(&summary(json!({ "top": 1 }))).await["result"]["topTypos"],
+        json!([{ "typo": "teh", "count": 3, "files": 2 }])
     );
 }
 
 #[test_log::test(tokio::test)]
-async fn test_ignore_codetypo_in_config_files() {
-    let term = Url::from_str("file:///C%3A/.codetypo.toml").unwrap();
-
-    let did_open = did_open_with("apropriate", Some(&term));
+async fn test_status() {
+    let status = |busy: bool, typos: usize, documents: Value| {
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "method": "$/codetypo/status",
+            "params": {
+              "busy": busy,
+              "reloading": false,
+              "typos": typos,
+              "documents": documents
+            }
+          }
+        )
+    };
+
+    let did_close = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/didClose",
+        "params": {
+          "textDocument": { "uri": "file:///C%3A/diagnostics.txt" }
+        }
+      }
+    )
+    .to_string();
+
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["capabilities"]["experimental"] =
+        json!({ "codetypoStatusNotification": true });
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    server.send(&did_open("teh fo")).await;
+    similar_asserts::assert_eq!(server.recv().await, status(true, 0, json!({})));
+    let _ = server.recv().await;
+    similar_asserts::assert_eq!(
+        server.recv().await,
+        status(false, 2, json!({ "file:///C%3A/diagnostics.txt": 2 }))
+    );
+
+    server.send(&did_close).await;
+    let _ = server.recv().await;
+    similar_asserts::assert_eq!(server.recv().await, status(false, 0, json!({})));
+}
+
+#[test_log::test(tokio::test)]
+async fn test_server_state_notification() {
+    let server_state = |state: &str, queued: usize| {
+        json!({
+          "jsonrpc": "2.0",
+          "method": "$/codetypo/serverState",
+          "params": { "state": state, "queued": queued }
+        })
+    };
+    let did_change = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/didChange",
+        "params": {
+          "textDocument": { "uri": "file:///C%3A/diagnostics.txt", "version": 2 },
+          "contentChanges": [{ "text": "the fo" }]
+        }
+      }
+    )
+    .to_string();
+
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["capabilities"]["experimental"] =
+        json!({ "codetypoServerStateNotification": true });
+    initialize["params"]["initializationOptions"]["diagnosticDelay"] = json!(50);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    server.send(&did_open("teh fo")).await;
+    similar_asserts::assert_eq!(server.recv().await, server_state("checking", 1));
+    let _ = server.recv().await;
+    similar_asserts::assert_eq!(server.recv().await, server_state("idle", 0));
+
+    // a changed document is queued while waiting for the diagnostic delay, and the state isn't
+    // sent again until it changes
+    server.send(&did_change).await;
+    similar_asserts::assert_eq!(server.recv().await, server_state("checking", 1));
+    similar_asserts::assert_eq!(server.recv().await["params"]["version"], json!(2));
+    similar_asserts::assert_eq!(server.recv().await, server_state("idle", 0));
+}
+
+#[test_log::test(tokio::test)]
+async fn test_trace() {
+    let set_trace = |value: &str| {
+        json!({ "jsonrpc": "2.0", "method": "$/setTrace", "params": { "value": value } })
+            .to_string()
+    };
+    let did_close = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/didClose",
+        "params": {
+          "textDocument": { "uri": "file:///C%3A/diagnostics.txt" }
+        }
+      }
+    )
+    .to_string();
+
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["trace"] = json!("messages");
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    server.send(&did_open("teh")).await;
+    similar_asserts::assert_eq!(
+        server.recv().await,
+        json!({
+          "jsonrpc": "2.0",
+          "method": "$/logTrace",
+          "params": { "message": "Handling textDocument/didOpen" }
+        })
+    );
+    similar_asserts::assert_eq!(
+        server.recv().await,
+        publish_diagnostics(&[diag("`teh` should be `the`", 0, 0, 3)])
+    );
+
+    // verbose traces include the params
+    server.send(&set_trace("verbose")).await;
+    server.send(&did_close).await;
+    similar_asserts::assert_eq!(
+        server.recv().await,
+        json!({
+          "jsonrpc": "2.0",
+          "method": "$/logTrace",
+          "params": {
+            "message": "Handling textDocument/didClose",
+            "verbose": r#"{"textDocument":{"uri":"file:///C%3A/diagnostics.txt"}}"#
+          }
+        })
+    );
+    let _ = server.recv().await;
+
+    server.send(&set_trace("off")).await;
+    similar_asserts::assert_eq!(
+        server.request(&did_open("teh")).await,
+        publish_diagnostics(&[diag("`teh` should be `the`", 0, 0, 3)])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_reuse_published_diagnostics() {
+    let did_save = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/didSave",
+        "params": {
+          "textDocument": { "uri": "file:///C%3A/diagnostics.txt" }
+        }
+      }
+    )
+    .to_string();
+
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["capabilities"]["experimental"] =
+        json!({ "codetypoStatusNotification": true });
+    initialize["params"]["initializationOptions"]["checkOnSaveOnly"] = json!(true);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // checking is bracketed by status notifications
+    server.send(&did_open("teh")).await;
+    similar_asserts::assert_eq!(server.recv().await["params"]["busy"], json!(true));
+    let _ = server.recv().await;
+    similar_asserts::assert_eq!(server.recv().await["params"]["busy"], json!(false));
+
+    // saving without changes reuses the diagnostics without checking again, so there's no status
+    similar_asserts::assert_eq!(
+        server.request(&did_save).await,
+        publish_diagnostics(&[diag("`teh` should be `the`", 0, 0, 3)])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_code_action_resolve() {
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["capabilities"]["textDocument"]["codeAction"] =
+        json!({ "resolveSupport": { "properties": ["edit"] } });
+
+    let code_action = json!({
+      "jsonrpc": "2.0",
+      "method": "textDocument/codeAction",
+      "params": {
+        "textDocument": { "uri": "file:///C%3A/diagnostics.txt" },
+        "range": range(0, 0, 3),
+        "context": {
+          "diagnostics": [ diag("`teh` should be `the`", 0, 0, 3) ],
+          "only": ["quickfix"],
+          "triggerKind": 2
+        }
+      },
+      "id": 2
+    })
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+    let _ = server.request(&did_open("teh teh\n")).await;
+
+    // actions are returned without their edits
+    let actions = server.request(&code_action).await["result"].clone();
+    let actions = actions.as_array().unwrap();
+    assert!(actions.iter().all(|action| action.get("edit").is_none()));
+    similar_asserts::assert_eq!(
+        actions[1]["data"],
+        json!({
+          "kind": "replaceAll",
+          "uri": "file:///C%3A/diagnostics.txt",
+          "typo": "teh",
+          "correction": "the"
+        })
+    );
+
+    let resolve = |action: &Value| {
+        json!({ "jsonrpc": "2.0", "method": "codeAction/resolve", "params": action, "id": 3 })
+            .to_string()
+    };
+    let resolved = server.request(&resolve(&actions[0])).await["result"].clone();
+    similar_asserts::assert_eq!(resolved["title"], json!("the"));
+    assert!(resolved.get("data").is_none());
+    similar_asserts::assert_eq!(
+        resolved["edit"],
+        json!({
+          "changes": {
+            "file:///C%3A/diagnostics.txt": [{ "newText": "the", "range": range(0, 0, 3) }]
+          }
+        })
+    );
+
+    // every occurrence is replaced
+    let resolved = server.request(&resolve(&actions[1])).await["result"].clone();
+    similar_asserts::assert_eq!(
+        resolved["edit"],
+        json!({
+          "changes": {
+            "file:///C%3A/diagnostics.txt": [
+              { "newText": "the", "range": range(0, 0, 3) },
+              { "newText": "the", "range": range(0, 4, 7) }
+            ]
+          }
+        })
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_more_suggestions() {
+    let workspace_folder = temp_dir("test_more_suggestions");
+    std::fs::write(
+        workspace_folder.join("words.txt"),
+        "tech\nten\nthe\nunrelated\n",
+    )
+    .unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let uri = Url::from_file_path(workspace_folder.join("a.txt")).unwrap();
+
+    let mut initialize: Value =
+        serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+    initialize["params"]["initializationOptions"]["wordLists"] = json!(["words.txt"]);
+
+    let more_suggestions = json!({
+      "jsonrpc": "2.0",
+      "method": "workspace/executeCommand",
+      "params": {
+        "command": "codetypo.moreSuggestions",
+        "arguments": [uri, "teh"]
+      },
+      "id": 2
+    })
+    .to_string();
+
+    let code_action = |trigger_kind: u32| {
+        json!({
+          "jsonrpc": "2.0",
+          "method": "textDocument/codeAction",
+          "params": {
+            "textDocument": { "uri": uri },
+            "range": range(0, 0, 3),
+            "context": {
+              "diagnostics": [ diag("`teh` should be `the`", 0, 0, 3) ],
+              "only": ["quickfix"],
+              "triggerKind": trigger_kind
+            }
+          },
+          "id": 3
+        })
+        .to_string()
+    };
+    let titles = |response: Value| {
+        response["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|action| action["title"].as_str().unwrap().to_string())
+            .filter(|title| {
+                !title.contains("dictionary")
+                    && !title.starts_with("Ignore")
+                    && !title.ends_with("everywhere in the workspace")
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+    let _ = server
+        .request(&did_open_with("teh tea\n", Some(&uri)))
+        .await;
+
+    // from the word list and the document, nearest first, without the correction
+    similar_asserts::assert_eq!(
+        server.request(&more_suggestions).await,
+        json!({ "jsonrpc": "2.0", "result": ["tea", "tech", "ten"], "id": 2 })
+    );
+
+    similar_asserts::assert_eq!(
+        titles(server.request(&code_action(1)).await),
+        vec![
+            "the",
+            "Did you mean `tea`?",
+            "Did you mean `tech`?",
+            "Did you mean `ten`?"
+        ]
+    );
+
+    // not searched for automatically triggered code actions
+    similar_asserts::assert_eq!(titles(server.request(&code_action(2)).await), vec!["the"]);
+
+    // from the dictionary, via the misspelling `recieve`
+    let more_suggestions = json!({
+      "jsonrpc": "2.0",
+      "method": "workspace/executeCommand",
+      "params": {
+        "command": "codetypo.moreSuggestions",
+        "arguments": [uri, "recive"]
+      },
+      "id": 4
+    })
+    .to_string();
+    similar_asserts::assert_eq!(
+        server.request(&more_suggestions).await,
+        json!({ "jsonrpc": "2.0", "result": ["receive"], "id": 4 })
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_testing_helpers() {
+    let mut server = TestServer::new();
+    let response = server
+        .initialize(json!({ "capabilities": {}, "initializationOptions": { "diagnosticSeverity": "Warning" } }))
+        .await;
+    assert!(response["result"]["capabilities"].is_object());
+
+    let other = Url::parse("file:///C%3A/other.txt").unwrap();
+    let uri = Url::parse("file:///C%3A/diagnostics.txt").unwrap();
+    server.did_open(&other, "plaintext", "fo").await;
+    server.did_open(&uri, "plaintext", "teh\nrecieve").await;
+
+    // the diagnostics of other documents are skipped
+    server.assert_typos(&uri, &["teh", "recieve"]).await;
+}
+
+#[cfg(feature = "api")]
+#[test]
+fn test_instance_drop() {
+    let dir = temp_dir("test_instance_drop");
+    std::fs::write(
+        dir.join("codetypo.toml"),
+        "[default.extend-words]\nteh = \"teh\"\n",
+    )
+    .unwrap();
+    let sources = codetypo_lsp::api::ConfigSources::default();
+
+    // most instances are dropped while the others are still used, which reads freed memory, eg:
+    // under Miri or a sanitizer, if an engine outlives the storage it borrows from
+    let kept = (0..100)
+        .map(|_| codetypo_lsp::api::Instance::new(&dir, &sources).unwrap())
+        .step_by(10)
+        .collect::<Vec<_>>();
+    for instance in &kept {
+        let policy = instance.engine().policy(&dir.join("a.txt"));
+        let typos = codetypo_lsp::api::check_str(
+            "teh fo",
+            std::iter::once(0..6).collect(),
+            policy.tokenizer,
+            policy.dict,
+            policy.ignore,
+            codetypo_lsp::api::Filters::default(),
+        )
+        .map(|(typo, _, _)| typo.typo.to_string())
+        .collect::<Vec<_>>();
+        similar_asserts::assert_eq!(typos, vec!["fo".to_string()]);
+    }
+}
+
+#[cfg(feature = "api")]
+#[test]
+fn test_api() {
+    let workspace_folder = temp_dir("test_api");
+    std::fs::write(
+        workspace_folder.join("codetypo.toml"),
+        "[default.extend-words]\nteh = \"teh\"\n",
+    )
+    .unwrap();
+    std::fs::write(workspace_folder.join("words.txt"), "recieve\n").unwrap();
+
+    let router = codetypo_lsp::api::Router::new(
+        std::slice::from_ref(&workspace_folder),
+        &json!({ "wordLists": ["words.txt"] }),
+    )
+    .unwrap();
+    let path = workspace_folder.join("a.txt");
+    similar_asserts::assert_eq!(
+        router.workspace_folder(&path),
+        Some(workspace_folder.clone())
+    );
+    assert!(router.instance(&path).is_some());
+
+    // checked with the config file and the settings, and suppression comments like the server
+    let typos = router
+        .check(&path, "teh recieve\nfo\nfo // codetypo:disable-line\n")
+        .into_iter()
+        .map(|(typo, line, column)| (typo.typo.to_string(), line, column))
+        .collect::<Vec<_>>();
+    similar_asserts::assert_eq!(typos, vec![("fo".to_string(), 1, 0)]);
+}
+
+#[test]
+fn test_log_file_rotation() {
+    use std::io::Write;
+
+    let dir = temp_dir("test_log_file_rotation");
+    let path = dir.join("codetypo.log");
+    std::fs::write(&path, "previous\n").unwrap();
+
+    let mut file = codetypo_lsp::log_file::RotatingFile::open(
+        &path,
+        codetypo_lsp::log_file::Rotation::Size,
+        16,
+    )
+    .unwrap();
+    // appended to the existing file while it's small enough
+    file.write_all(b"first\n").unwrap();
+    file.write_all(b"second\n").unwrap();
+    file.write_all(b"third\n").unwrap();
+    file.flush().unwrap();
+
+    similar_asserts::assert_eq!(
+        std::fs::read_to_string(dir.join("codetypo.log.1")).unwrap(),
+        "previous\nfirst\n"
+    );
+    similar_asserts::assert_eq!(std::fs::read_to_string(&path).unwrap(), "second\nthird\n");
+}
+
+#[test]
+fn test_check() {
+    let workspace_folder = temp_dir("test_check");
+    std::fs::write(
+        workspace_folder.join("codetypo.toml"),
+        "[files]\nextend-exclude = [\"excluded.txt\"]\n\n[default.extend-words]\nfo = \"fo\"\n",
+    )
+    .unwrap();
+    std::fs::create_dir_all(workspace_folder.join("src")).unwrap();
+    std::fs::write(
+        workspace_folder.join("src").join("a.txt"),
+        "fo\nteh 𝐀 recieve\n",
+    )
+    .unwrap();
+    std::fs::write(workspace_folder.join("excluded.txt"), "teh\n").unwrap();
+
+    let options = codetypo_lsp::check::CheckOptions {
+        workspace_folder: Some(workspace_folder.clone()),
+        user_dictionary: Some(workspace_folder.join("user_dictionary.toml")),
+        ..Default::default()
+    };
+    let outside = temp_dir("test_check_outside").join("outside.txt");
+    std::fs::write(&outside, "fo\n").unwrap();
+    let paths = [
+        workspace_folder.join("src"),
+        workspace_folder.join("excluded.txt"),
+        outside.clone(),
+    ];
+    let mut out = Vec::new();
+    let count = codetypo_lsp::check::check(&paths, &options, &mut out).unwrap();
+
+    // columns are in UTF-16 code units like LSP positions, so `𝐀` counts as 2, and files outside
+    // the workspace folder are checked without its config
+    let a_txt = workspace_folder.join("src").join("a.txt");
+    similar_asserts::assert_eq!(
+        (count, String::from_utf8(out).unwrap()),
+        (
+            3,
+            format!(
+                "{0}:2:1: `teh` should be `the`\n{0}:2:8: `recieve` should be `receive`\n\
+                 {1}:1:1: `fo` should be `to`, `of`, `for`, `do`, `go`\n",
+                a_txt.display(),
+                outside.display()
+            )
+        )
+    );
+
+    let options = codetypo_lsp::check::CheckOptions {
+        format: codetypo_lsp::check::OutputFormat::Github,
+        ..options
+    };
+    let mut out = Vec::new();
+    codetypo_lsp::check::check(&paths, &options, &mut out).unwrap();
+    similar_asserts::assert_eq!(
+        String::from_utf8(out).unwrap(),
+        format!(
+            "::warning file={0},line=2,col=1,title=Typo::`teh` should be `the`\n\
+             ::warning file={0},line=2,col=8,title=Typo::`recieve` should be `receive`\n\
+             ::warning file={1},line=1,col=1,title=Typo::`fo` should be `to`, `of`, `for`, `do`, `go`\n",
+            a_txt.display().to_string().replace(':', "%3A"),
+            outside.display().to_string().replace(':', "%3A")
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_hover() {
+    let workspace_folder = temp_dir("test_hover");
+    std::fs::write(
+        workspace_folder.join("codetypo.toml"),
+        "[default.extend-words]\nrecieve = \"receive\"\n",
+    )
+    .unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let diag_txt = Url::from_file_path(workspace_folder.join("diagnostics.txt")).unwrap();
+
+    let hover = |line: u32, character: u32| {
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "method": "textDocument/hover",
+            "params": {
+              "textDocument": { "uri": diag_txt },
+              "position": { "line": line, "character": character }
+            },
+            "id": 2
+          }
+        )
+        .to_string()
+    };
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+    let _ = server
+        .request(&did_open_with("teh recieve\nfine", Some(&diag_txt)))
+        .await;
+
+    similar_asserts::assert_eq!(
+        server.request(&hover(0, 1)).await,
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "result": {
+              "contents": {
+                "kind": "markdown",
+                "value": "`teh` should be `the`\n\nSource: built-in dictionary"
+              },
+              "range": range(0, 0, 3)
+            },
+            "id": 2
+          }
+        )
+    );
+
+    // corrections from the config show the table that defines them
+    similar_asserts::assert_eq!(
+        server.request(&hover(0, 6)).await["result"]["contents"]["value"],
+        json!(format!(
+            "`recieve` should be `receive`\n\nSource: `[default.extend-words]` in `{}`",
+            workspace_folder.join("codetypo.toml").display()
+        ))
+    );
+
+    // no hover when not on a typo
+    similar_asserts::assert_eq!(
+        server.request(&hover(1, 1)).await,
+        json!({ "jsonrpc": "2.0", "result": null, "id": 2 })
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_completion() {
+    let completion = |character: u32| {
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "method": "textDocument/completion",
+            "params": {
+              "textDocument": { "uri": "file:///C%3A/diagnostics.txt" },
+              "position": { "line": 0, "character": character }
+            },
+            "id": 2
+          }
+        )
+        .to_string()
+    };
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open("a fo")).await;
+
+    similar_asserts::assert_eq!(
+        server.request(&completion(1)).await,
+        json!({ "jsonrpc": "2.0", "result": null, "id": 2 })
+    );
+
+    let response = server.request(&completion(4)).await;
+    similar_asserts::assert_eq!(
+        response["result"][0],
+        json!(
+          {
+            "detail": "Correction of `fo`",
+            "filterText": "fo",
+            "kind": 1,
+            "label": "to",
+            "sortText": "0000",
+            "textEdit": {
+              "newText": "to",
+              "range": {
+                "end": { "character": 4, "line": 0 },
+                "start": { "character": 2, "line": 0 }
+              }
+            }
+          }
+        )
+    );
+    similar_asserts::assert_eq!(
+        response["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        vec!["to", "of", "for", "do", "go"]
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_semantic_tokens() {
+    let semantic_tokens = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/semanticTokens/full",
+        "params": {
+          "textDocument": { "uri": "file:///C%3A/diagnostics.txt" }
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open("a teh fo\n\n  recieve\n")).await;
+
+    // [deltaLine, deltaStart, length, tokenType, tokenModifiers] for each typo
+    similar_asserts::assert_eq!(
+        server.request(&semantic_tokens).await,
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "result": {
+              "data": [
+                0, 2, 3, 0, 0,
+                0, 4, 2, 0, 0,
+                2, 2, 7, 0, 0
+              ]
+            },
+            "id": 2
+          }
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_document_highlight() {
+    let document_highlight = |character: u32| {
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "method": "textDocument/documentHighlight",
+            "params": {
+              "textDocument": { "uri": "file:///C%3A/diagnostics.txt" },
+              "position": { "line": 0, "character": character }
+            },
+            "id": 2
+          }
+        )
+        .to_string()
+    };
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open("teh fo\nsee teh\n")).await;
+
+    similar_asserts::assert_eq!(
+        server.request(&document_highlight(3)).await,
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "result": [
+              {
+                "kind": 1,
+                "range": {
+                  "end": { "character": 3, "line": 0 },
+                  "start": { "character": 0, "line": 0 }
+                }
+              },
+              {
+                "kind": 1,
+                "range": {
+                  "end": { "character": 7, "line": 1 },
+                  "start": { "character": 4, "line": 1 }
+                }
+              }
+            ],
+            "id": 2
+          }
+        )
+    );
+
+    // not on a typo
+    similar_asserts::assert_eq!(
+        server.request(&document_highlight(7)).await,
+        json!({ "jsonrpc": "2.0", "result": null, "id": 2 })
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_inlay_hint() {
+    let inlay_hint = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/inlayHint",
+        "params": {
+          "textDocument": { "uri": "file:///C%3A/diagnostics.txt" },
+          "range": {
+            "start": { "line": 0, "character": 0 },
+            "end": { "line": 1, "character": 0 }
+          }
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open("teh cat\nteh dog\n")).await;
+
+    // disabled by default
+    similar_asserts::assert_eq!(
+        server.request(&inlay_hint).await,
+        json!({ "jsonrpc": "2.0", "result": null, "id": 2 })
+    );
+
+    let mut server = TestServer::new();
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["inlayHints"] = json!(true);
+    let _ = server.request(&initialize.to_string()).await;
+    let _ = server.request(&did_open("teh cat\nteh dog\n")).await;
+
+    // only typos in the requested range are hinted
+    similar_asserts::assert_eq!(
+        server.request(&inlay_hint).await,
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "result": [
+              {
+                "label": "⟶ the",
+                "paddingLeft": true,
+                "position": { "character": 3, "line": 0 }
+              }
+            ],
+            "id": 2
+          }
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_add_to_project_dictionary() {
+    let workspace_folder = temp_dir("test_add_to_project_dictionary");
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let diag_txt = Url::from_file_path(workspace_folder.join("diagnostics.txt")).unwrap();
+
+    let add_to_project_dictionary = json!({
+      "title": "Add `apropriate` to project dictionary",
+      "command": "codetypo.addToProjectDictionary",
+      "arguments": [diag_txt, "apropriate"]
+    });
+
+    let code_action = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/codeAction",
+        "params": {
+          "textDocument": {
+            "uri": diag_txt
+          },
+          "range": range(0, 0, 10),
+          "context": {
+            "diagnostics": [ diag("`apropriate` should be `appropriate`", 0, 0, 10) ],
+            "only": ["quickfix"],
+            "triggerKind": 1
+          }
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let execute_command = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": add_to_project_dictionary,
+        "id": 3
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("apropriate", Some(&diag_txt)))
+            .await,
+        publish_diagnostics_with(
+            &[diag("`apropriate` should be `appropriate`", 0, 0, 10)],
+            Some(&diag_txt)
+        )
+    );
+
+    similar_asserts::assert_eq!(
+        server.request(&code_action).await["result"][2],
+        json!(
+          {
+            "diagnostics": [ diag("`apropriate` should be `appropriate`", 0, 0, 10) ],
+            "command": add_to_project_dictionary,
+            "kind": "quickfix",
+            "title": "Add `apropriate` to project dictionary"
+          }
+        ),
+    );
+
+    // open documents are re-checked with the updated config
+    similar_asserts::assert_eq!(
+        server.request_with_messages(&execute_command, 1).await,
+        (
+            json!({ "jsonrpc": "2.0", "result": null, "id": 3 }),
+            vec![publish_diagnostics_with(&[], Some(&diag_txt))]
+        )
+    );
+
+    similar_asserts::assert_eq!(
+        std::fs::read_to_string(workspace_folder.join("codetypo.toml")).unwrap(),
+        "[default.extend-words]\napropriate = \"apropriate\"\n"
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_add_correction() {
+    let workspace_folder = temp_dir("test_add_correction");
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let diag_txt = Url::from_file_path(workspace_folder.join("diagnostics.txt")).unwrap();
+    let fo = diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 0, 0, 2);
+
+    let add_correction = json!({
+      "title": "Always correct `fo` to `for`",
+      "command": "codetypo.addCorrection",
+      "arguments": [diag_txt, "fo", "for"]
+    });
+
+    let code_action = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/codeAction",
+        "params": {
+          "textDocument": {
+            "uri": diag_txt
+          },
+          "range": range(0, 0, 2),
+          "context": {
+            "diagnostics": [ fo ],
+            "only": ["quickfix"],
+            "triggerKind": 1
+          }
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let execute_command = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": add_correction,
+        "id": 3
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+    let _ = server.request(&did_open_with("fo", Some(&diag_txt))).await;
+
+    let response = server.request(&code_action).await;
+    let actions = response["result"].as_array().unwrap();
+    assert!(actions.contains(&json!(
+      {
+        "diagnostics": [ fo ],
+        "command": add_correction,
+        "kind": "quickfix",
+        "title": "Always correct `fo` to `for`"
+      }
+    )));
+
+    // open documents are re-checked, and only have the chosen correction
+    similar_asserts::assert_eq!(
+        server.request_with_messages(&execute_command, 1).await,
+        (
+            json!({ "jsonrpc": "2.0", "result": null, "id": 3 }),
+            vec![publish_diagnostics_with(
+                &[diag("`fo` should be `for`", 0, 0, 2)],
+                Some(&diag_txt)
+            )]
+        )
+    );
+
+    similar_asserts::assert_eq!(
+        std::fs::read_to_string(workspace_folder.join("codetypo.toml")).unwrap(),
+        "[default.extend-words]\nfo = \"for\"\n"
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_ignore_word_in_file() {
+    let workspace_folder = temp_dir("test_ignore_word_in_file");
+    std::fs::create_dir_all(workspace_folder.join("src")).unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let diag_txt =
+        Url::from_file_path(workspace_folder.join("src").join("diagnostics.txt")).unwrap();
+    // with the same name, in another directory
+    let other_txt = Url::from_file_path(workspace_folder.join("diagnostics.txt")).unwrap();
+
+    let execute_command = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": {
+          "command": "codetypo.ignoreWordInFile",
+          "arguments": [diag_txt, "apropriate"]
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+    let _ = server
+        .request(&did_open_with("apropriate", Some(&diag_txt)))
+        .await;
+    let _ = server
+        .request(&did_open_with("apropriate", Some(&other_txt)))
+        .await;
+
+    // the word is accepted in src/diagnostics.txt but still flagged in the file with the same name
+    let (response, mut messages) = server.request_with_messages(&execute_command, 2).await;
+    messages.sort_by_key(|msg| msg["params"]["uri"].to_string());
+    similar_asserts::assert_eq!(
+        (response, messages),
+        (
+            json!({ "jsonrpc": "2.0", "result": null, "id": 2 }),
+            vec![
+                publish_diagnostics_with(
+                    &[diag("`apropriate` should be `appropriate`", 0, 0, 10)],
+                    Some(&other_txt)
+                ),
+                publish_diagnostics_with(&[], Some(&diag_txt)),
+            ]
+        )
+    );
+
+    // the codetypo config is left alone
+    assert!(!workspace_folder.join("codetypo.toml").exists());
+    similar_asserts::assert_eq!(
+        std::fs::read_to_string(workspace_folder.join("codetypo-lsp.toml")).unwrap(),
+        "[file.\"src/diagnostics.txt\".extend-words]\napropriate = \"apropriate\"\n"
+    );
+
+    // the word is matched case insensitively, like codetypo does
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("Apropriate teh", Some(&diag_txt)))
+            .await,
+        publish_diagnostics_with(&[diag("`teh` should be `the`", 0, 11, 14)], Some(&diag_txt))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_add_to_user_dictionary() {
+    let user_dictionary = temp_dir("test_add_to_user_dictionary")
+        .join("codetypo")
+        .join("dictionary.toml");
+
+    let execute_command = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": {
+          "command": "codetypo.addToUserDictionary",
+          "arguments": ["apropriate"]
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["userDictionary"] = json!(user_dictionary);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    similar_asserts::assert_eq!(
+        server.request(&did_open("apropriate")).await,
+        publish_diagnostics(&[diag("`apropriate` should be `appropriate`", 0, 0, 10)])
+    );
+
+    // the word is accepted once it's in the user dictionary
+    similar_asserts::assert_eq!(
+        server.request_with_messages(&execute_command, 1).await,
+        (
+            json!({ "jsonrpc": "2.0", "result": null, "id": 2 }),
+            vec![publish_diagnostics(&[])]
+        )
+    );
+
+    similar_asserts::assert_eq!(
+        std::fs::read_to_string(&user_dictionary).unwrap(),
+        "[default.extend-words]\napropriate = \"apropriate\"\n"
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_ignore_word_session() {
+    let user_dictionary = temp_dir("test_ignore_word_session").join("dictionary.toml");
+
+    let execute_command = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": {
+          "command": "codetypo.ignoreWordSession",
+          "arguments": ["apropriate"]
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["userDictionary"] = json!(user_dictionary);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    similar_asserts::assert_eq!(
+        server.request(&did_open("Apropriate teh")).await,
+        publish_diagnostics(&[
+            diag("`Apropriate` should be `Appropriate`", 0, 0, 10),
+            diag("`teh` should be `the`", 0, 11, 14)
+        ])
+    );
+
+    // the word is ignored whatever its case, without writing it anywhere
+    similar_asserts::assert_eq!(
+        server.request_with_messages(&execute_command, 1).await,
+        (
+            json!({ "jsonrpc": "2.0", "result": null, "id": 2 }),
+            vec![publish_diagnostics(&[diag(
+                "`teh` should be `the`",
+                0,
+                11,
+                14
+            )])]
+        )
+    );
+    assert!(!user_dictionary.exists());
+}
+
+#[test_log::test(tokio::test)]
+async fn test_fix_all_workspace() {
+    let workspace_folder = temp_dir("test_fix_all_workspace");
+    std::fs::write(workspace_folder.join("diagnostics.txt"), "fo teh\n").unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let diag_txt = Url::from_file_path(workspace_folder.join("diagnostics.txt")).unwrap();
+    let open_txt = Url::from_file_path(workspace_folder.join("open.txt")).unwrap();
+
+    let execute_command = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": {
+          "command": "codetypo.fixAll",
+          "arguments": []
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+    // unsaved changes in open documents are fixed too, even before they're written to disk
+    std::fs::write(workspace_folder.join("open.txt"), "").unwrap();
+    let _ = server
+        .request(&did_open_with("recieve", Some(&open_txt)))
+        .await;
+
+    // only typos with a single correction are fixed
+    server.send(&execute_command).await;
+    let apply_edit = server.recv().await;
+    similar_asserts::assert_eq!(
+        apply_edit["params"],
+        json!(
+          {
+            "edit": {
+              "changes": {
+                diag_txt.as_str(): [
+                  {
+                    "newText": "the",
+                    "range": {
+                      "end": { "character": 6, "line": 0 },
+                      "start": { "character": 3, "line": 0 }
+                    }
+                  }
+                ],
+                open_txt.as_str(): [
+                  {
+                    "newText": "receive",
+                    "range": {
+                      "end": { "character": 7, "line": 0 },
+                      "start": { "character": 0, "line": 0 }
+                    }
+                  }
+                ]
+              }
+            }
+          }
+        )
+    );
+
+    let apply_edit_response = json!(
+      {
+        "jsonrpc": "2.0",
+        "result": { "applied": true },
+        "id": apply_edit["id"]
+      }
+    )
+    .to_string();
+    similar_asserts::assert_eq!(
+        server.request(&apply_edit_response).await,
+        json!({ "jsonrpc": "2.0", "result": null, "id": 2 })
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_code_action_without_data() {
+    let code_action = |uri: &str| {
+        let mut diag = diag("`teh` should be `the`", 0, 0, 3);
+        diag.as_object_mut().unwrap().remove("data");
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "method": "textDocument/codeAction",
+            "params": {
+              "textDocument": { "uri": uri },
+              "range": range(0, 0, 3),
+              "context": {
+                "diagnostics": [diag],
+                "only": ["quickfix"]
+              }
+            },
+            "id": 2
+          }
+        )
+        .to_string()
+    };
+    let titles = |response: Value| {
+        response["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|action| action["title"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open("teh\n")).await;
+
+    // the data is looked up in the published diagnostics
+    similar_asserts::assert_eq!(
+        titles(
+            server
+                .request(&code_action("file:///C%3A/diagnostics.txt"))
+                .await
+        ),
+        [
+            "the",
+            "Add `teh` to user dictionary",
+            "Ignore `teh` for this session"
+        ]
+    );
+
+    // no diagnostics were published for a document that isn't open
+    similar_asserts::assert_eq!(
+        titles(
+            server
+                .request(&code_action("file:///C%3A/closed.txt"))
+                .await
+        ),
+        Vec::<String>::new()
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_code_action_commands() {
+    let code_action = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/codeAction",
+        "params": {
+          "textDocument": {
+            "uri": "file:///C%3A/diagnostics.txt"
+          },
+          "range": range(0, 0, 3),
+          "context": {
+            "diagnostics": [ diag("`teh` should be `the`", 0, 0, 3) ],
+            "only": ["quickfix"]
+          }
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let edit = json!(
+      {
+        "changes": {
+          "file:///C%3A/diagnostics.txt": [
+            {
+              "newText": "the",
+              "range": range(0, 0, 3)
+            }
+          ]
+        }
+      }
+    );
+
+    // a client that declares code action capabilities without literal support
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["capabilities"]["textDocument"]["codeAction"] =
+        json!({ "dynamicRegistration": false });
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+    let _ = server.request(&did_open("teh\n")).await;
+
+    similar_asserts::assert_eq!(
+        server.request(&code_action).await,
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "result": [
+              {
+                "arguments": [edit],
+                "command": "codetypo.applyWorkspaceEdit",
+                "title": "the"
+              },
+              {
+                "arguments": ["teh"],
+                "command": "codetypo.addToUserDictionary",
+                "title": "Add `teh` to user dictionary"
+              },
+              {
+                "arguments": ["teh"],
+                "command": "codetypo.ignoreWordSession",
+                "title": "Ignore `teh` for this session"
+              }
+            ],
+            "id": 2
+          }
+        )
+    );
+
+    let execute_command = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": {
+          "command": "codetypo.applyWorkspaceEdit",
+          "arguments": [edit]
+        },
+        "id": 3
+      }
+    )
+    .to_string();
+    server.send(&execute_command).await;
+    let apply_edit = server.recv().await;
+    similar_asserts::assert_eq!(apply_edit["params"], json!({ "edit": edit }));
+
+    let apply_edit_response = json!(
+      {
+        "jsonrpc": "2.0",
+        "result": { "applied": true },
+        "id": apply_edit["id"]
+      }
+    )
+    .to_string();
+    similar_asserts::assert_eq!(
+        server.request(&apply_edit_response).await,
+        json!({ "jsonrpc": "2.0", "result": null, "id": 3 })
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_replace_in_workspace() {
+    let workspace_folder = temp_dir("test_replace_in_workspace");
+    std::fs::write(
+        workspace_folder.join("codetypo.toml"),
+        "[files]\nextend-exclude = [\"excluded.txt\"]\n",
+    )
+    .unwrap();
+    std::fs::write(workspace_folder.join("a.txt"), "teh recieve\n").unwrap();
+    std::fs::write(workspace_folder.join("b.txt"), "recieve\nrecieve\n").unwrap();
+    std::fs::write(workspace_folder.join("excluded.txt"), "recieve\n").unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let a_uri = Url::from_file_path(workspace_folder.join("a.txt")).unwrap();
+    let b_uri = Url::from_file_path(workspace_folder.join("b.txt")).unwrap();
+
+    let code_action = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/codeAction",
+        "params": {
+          "textDocument": { "uri": a_uri },
+          "range": range(0, 4, 11),
+          "context": {
+            "diagnostics": [ diag("`recieve` should be `receive`", 0, 4, 11) ],
+            "only": ["quickfix"]
+          }
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+    // open documents are replaced in their unsaved text
+    let _ = server
+        .request(&did_open_with("teh teh recieve\n", Some(&a_uri)))
+        .await;
+
+    let response = server.request(&code_action).await;
+    let replace = response["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|action| {
+            action["title"] == "Replace `recieve` with `receive` everywhere in the workspace"
+        })
+        .unwrap();
+    similar_asserts::assert_eq!(
+        replace["command"],
+        json!(
+          {
+            "arguments": ["recieve", "receive"],
+            "command": "codetypo.replaceInWorkspace",
+            "title": "Replace `recieve` with `receive` everywhere in the workspace"
+          }
+        )
+    );
+
+    let execute_command = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": replace["command"],
+        "id": 3
+      }
+    )
+    .to_string();
+    server.send(&execute_command).await;
+    let apply_edit = server.recv().await;
+    similar_asserts::assert_eq!(
+        apply_edit["params"],
+        json!(
+          {
+            "edit": {
+              "changes": {
+                a_uri.as_str(): [{ "newText": "receive", "range": range(0, 8, 15) }],
+                b_uri.as_str(): [
+                  { "newText": "receive", "range": range(0, 0, 7) },
+                  { "newText": "receive", "range": range(1, 0, 7) }
+                ]
+              }
+            }
+          }
+        )
+    );
+
+    let apply_edit_response = json!(
+      {
+        "jsonrpc": "2.0",
+        "result": { "applied": true },
+        "id": apply_edit["id"]
+      }
+    )
+    .to_string();
+    similar_asserts::assert_eq!(
+        server.request(&apply_edit_response).await,
+        json!({ "jsonrpc": "2.0", "result": null, "id": 3 })
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_code_lens() {
+    let code_lens = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/codeLens",
+        "params": {
+          "textDocument": { "uri": "file:///C%3A/diagnostics.txt" }
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let execute_command = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": {
+          "command": "codetypo.fixFile",
+          "arguments": ["file:///C%3A/diagnostics.txt"]
+        },
+        "id": 3
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open("no typos here\n")).await;
+
+    similar_asserts::assert_eq!(
+        server.request(&code_lens).await,
+        json!({ "jsonrpc": "2.0", "result": null, "id": 2 })
+    );
+
+    let _ = server.request(&did_open("teh cat\nrecieve\n")).await;
+
+    similar_asserts::assert_eq!(
+        server.request(&code_lens).await,
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "result": [
+              {
+                "command": {
+                  "arguments": ["file:///C%3A/diagnostics.txt"],
+                  "command": "codetypo.fixFile",
+                  "title": "2 typos — Fix all"
+                },
+                "range": {
+                  "end": { "character": 0, "line": 0 },
+                  "start": { "character": 0, "line": 0 }
+                }
+              }
+            ],
+            "id": 2
+          }
+        )
+    );
+
+    server.send(&execute_command).await;
+    let apply_edit = server.recv().await;
+    similar_asserts::assert_eq!(
+        apply_edit["params"],
+        json!(
+          {
+            "edit": {
+              "changes": {
+                "file:///C%3A/diagnostics.txt": [
+                  {
+                    "newText": "the",
+                    "range": {
+                      "end": { "character": 3, "line": 0 },
+                      "start": { "character": 0, "line": 0 }
+                    }
+                  },
+                  {
+                    "newText": "receive",
+                    "range": {
+                      "end": { "character": 7, "line": 1 },
+                      "start": { "character": 0, "line": 1 }
+                    }
+                  }
+                ]
+              }
+            }
+          }
+        )
+    );
+
+    let apply_edit_response = json!(
+      {
+        "jsonrpc": "2.0",
+        "result": { "applied": true },
+        "id": apply_edit["id"]
+      }
+    )
+    .to_string();
+    similar_asserts::assert_eq!(
+        server.request(&apply_edit_response).await,
+        json!({ "jsonrpc": "2.0", "result": null, "id": 3 })
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_formatting() {
+    let formatting = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/formatting",
+        "params": {
+          "textDocument": { "uri": "file:///C%3A/diagnostics.txt" },
+          "options": { "tabSize": 4, "insertSpaces": true }
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let range_formatting = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/rangeFormatting",
+        "params": {
+          "textDocument": { "uri": "file:///C%3A/diagnostics.txt" },
+          "range": {
+            "start": { "line": 1, "character": 0 },
+            "end": { "line": 2, "character": 0 }
+          },
+          "options": { "tabSize": 4, "insertSpaces": true }
+        },
+        "id": 3
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open("teh fo\nrecieve\n")).await;
+
+    // `fo` has several corrections so is left alone
+    similar_asserts::assert_eq!(
+        server.request(&formatting).await,
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "result": [
+              {
+                "newText": "the",
+                "range": {
+                  "end": { "character": 3, "line": 0 },
+                  "start": { "character": 0, "line": 0 }
+                }
+              },
+              {
+                "newText": "receive",
+                "range": {
+                  "end": { "character": 7, "line": 1 },
+                  "start": { "character": 0, "line": 1 }
+                }
+              }
+            ],
+            "id": 2
+          }
+        )
+    );
+
+    similar_asserts::assert_eq!(
+        server.request(&range_formatting).await,
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "result": [
+              {
+                "newText": "receive",
+                "range": {
+                  "end": { "character": 7, "line": 1 },
+                  "start": { "character": 0, "line": 1 }
+                }
+              }
+            ],
+            "id": 3
+          }
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_will_save_wait_until() {
+    let will_save_wait_until = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/willSaveWaitUntil",
+        "params": {
+          "textDocument": { "uri": "file:///C%3A/diagnostics.txt" },
+          "reason": 1
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open("teh fo\n")).await;
+
+    // disabled by default
+    similar_asserts::assert_eq!(
+        server.request(&will_save_wait_until).await,
+        json!({ "jsonrpc": "2.0", "result": null, "id": 2 })
+    );
+
+    let mut server = TestServer::new();
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["autoFixOnSave"] = json!(true);
+    let _ = server.request(&initialize.to_string()).await;
+    let _ = server.request(&did_open("teh fo\n")).await;
+
+    similar_asserts::assert_eq!(
+        server.request(&will_save_wait_until).await,
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "result": [
+              {
+                "newText": "the",
+                "range": {
+                  "end": { "character": 3, "line": 0 },
+                  "start": { "character": 0, "line": 0 }
+                }
+              }
+            ],
+            "id": 2
+          }
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_toggle() {
+    let workspace_folder = temp_dir("test_toggle");
+    std::fs::write(workspace_folder.join("typo.txt"), "teh\n").unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let typo_txt = Url::from_file_path(workspace_folder.join("typo.txt")).unwrap();
+    let open_txt = Url::from_file_path(workspace_folder.join("open.txt")).unwrap();
+
+    let execute_command = |command: &str| {
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "method": "workspace/executeCommand",
+            "params": { "command": command, "arguments": [] },
+            "id": 2
+          }
+        )
+        .to_string()
+    };
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+    let _ = server
+        .request(&did_open_with("recieve\n", Some(&open_txt)))
+        .await;
+    let _ = server
+        .request_with_messages(&execute_command("codetypo.scanWorkspace"), 1)
+        .await;
+
+    // pausing clears the diagnostics of scanned files and open documents
+    similar_asserts::assert_eq!(
+        server
+            .request_with_messages(&execute_command("codetypo.toggle"), 2)
+            .await,
+        (
+            json!({ "jsonrpc": "2.0", "result": { "enabled": false }, "id": 2 }),
+            vec![
+                json!({
+                  "jsonrpc": "2.0",
+                  "method": "textDocument/publishDiagnostics",
+                  "params": { "uri": typo_txt, "diagnostics": [] }
+                }),
+                publish_diagnostics_with(&[], Some(&open_txt))
+            ]
+        )
+    );
+
+    // changes aren't checked while paused
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("teh\n", Some(&open_txt)))
+            .await,
+        publish_diagnostics_with(&[], Some(&open_txt))
+    );
+
+    // resuming checks the open documents again
+    similar_asserts::assert_eq!(
+        server
+            .request_with_messages(&execute_command("codetypo.toggle"), 1)
+            .await,
+        (
+            json!({ "jsonrpc": "2.0", "result": { "enabled": true }, "id": 2 }),
+            vec![publish_diagnostics_with(
+                &[diag("`teh` should be `the`", 0, 0, 3)],
+                Some(&open_txt)
+            )]
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_scan_workspace() {
+    let workspace_folder = temp_dir("test_scan_workspace");
+    std::fs::write(workspace_folder.join("typo.txt"), "teh\n").unwrap();
+    std::fs::write(workspace_folder.join("clean.txt"), "the\n").unwrap();
+    std::fs::write(workspace_folder.join("open.txt"), "recieve\n").unwrap();
+    // ignored files aren't scanned
+    std::fs::create_dir_all(workspace_folder.join(".git")).unwrap();
+    std::fs::write(workspace_folder.join(".gitignore"), "target/\n").unwrap();
+    std::fs::write(workspace_folder.join(".ignore"), "vendor/\n").unwrap();
+    for dir in ["target", "vendor", ".hidden"] {
+        std::fs::create_dir_all(workspace_folder.join(dir)).unwrap();
+        std::fs::write(workspace_folder.join(dir).join("typo.txt"), "teh\n").unwrap();
+    }
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let typo_txt = Url::from_file_path(workspace_folder.join("typo.txt")).unwrap();
+    let open_txt = Url::from_file_path(workspace_folder.join("open.txt")).unwrap();
+
+    let scan_workspace = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": {
+          "command": "codetypo.scanWorkspace",
+          "arguments": []
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+    let _ = server
+        .request(&did_open_with("recieve\n", Some(&open_txt)))
+        .await;
+
+    // open documents are already reported, and clean files aren't
+    similar_asserts::assert_eq!(
+        server.request_with_messages(&scan_workspace, 1).await,
+        (
+            json!({ "jsonrpc": "2.0", "result": null, "id": 2 }),
+            vec![json!(
+              {
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": {
+                  "uri": typo_txt,
+                  "diagnostics": [diag("`teh` should be `the`", 0, 0, 3)]
+                }
+              }
+            )]
+        )
+    );
+
+    // diagnostics of fixed files are cleared by the next scan
+    std::fs::write(workspace_folder.join("typo.txt"), "the\n").unwrap();
+    similar_asserts::assert_eq!(
+        server.request_with_messages(&scan_workspace, 1).await,
+        (
+            json!({ "jsonrpc": "2.0", "result": null, "id": 2 }),
+            vec![json!(
+              {
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": typo_txt, "diagnostics": [] }
+              }
+            )]
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_scan_workspace_encoded_uri() {
+    let workspace_folder = temp_dir("test_scan_workspace_encoded_uri");
+    std::fs::write(workspace_folder.join("c++ notes.txt"), "teh\n").unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    // encoded like VS Code does, which differs from the URI of the file's path
+    let open_uri = Url::parse(&format!(
+        "{}/c%2B%2B%20notes.txt",
+        workspace_folder_uri.as_str()
+    ))
+    .unwrap();
+    assert_ne!(
+        Some(&open_uri),
+        Url::from_file_path(workspace_folder.join("c++ notes.txt"))
+            .ok()
+            .as_ref()
+    );
+
+    let scan_workspace = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": {
+          "command": "codetypo.scanWorkspace",
+          "arguments": []
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+    let _ = server
+        .request(&did_open_with("the\n", Some(&open_uri)))
+        .await;
+
+    // the open document isn't scanned from disk under another URI
+    similar_asserts::assert_eq!(
+        server.request_with_messages(&scan_workspace, 0).await,
+        (json!({ "jsonrpc": "2.0", "result": null, "id": 2 }), vec![])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_scan_workspace_encodings() {
+    let workspace_folder = temp_dir("test_scan_workspace_encodings");
+    let utf16: Vec<u8> = [0xFF, 0xFE]
+        .into_iter()
+        .chain("teh\n".encode_utf16().flat_map(u16::to_le_bytes))
+        .collect();
+    std::fs::write(workspace_folder.join("utf16.txt"), utf16).unwrap();
+    std::fs::write(workspace_folder.join("utf8.txt"), "\u{feff}teh\n").unwrap();
+    // not valid UTF-8, so it can't be decoded
+    std::fs::write(workspace_folder.join("latin1.txt"), b"caf\xe9 teh\n").unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+
+    let scan_workspace = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": {
+          "command": "codetypo.scanWorkspace",
+          "arguments": []
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+
+    // byte order marks aren't counted in positions, like editors hide them
+    let (response, mut messages) = server.request_with_messages(&scan_workspace, 2).await;
+    messages.sort_by_key(|message| message["params"]["uri"].to_string());
+    similar_asserts::assert_eq!(
+        (response, messages),
+        (
+            json!({ "jsonrpc": "2.0", "result": null, "id": 2 }),
+            ["utf16.txt", "utf8.txt"]
+                .map(|name| json!(
+                  {
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": {
+                      "uri": Url::from_file_path(workspace_folder.join(name)).unwrap(),
+                      "diagnostics": [diag("`teh` should be `the`", 0, 0, 3)]
+                    }
+                  }
+                ))
+                .to_vec()
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_scan_workspace_concurrency() {
+    let workspace_folder = temp_dir("test_scan_workspace_concurrency");
+    let mut typo_txts = (0..10)
+        .map(|i| {
+            let path = workspace_folder.join(format!("typo{}.txt", i));
+            std::fs::write(&path, format!("{}teh\n", "\n".repeat(i))).unwrap();
+            (Url::from_file_path(path).unwrap(), i as u32)
+        })
+        .collect::<Vec<_>>();
+    typo_txts.sort();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+
+    let scan_workspace = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": {
+          "command": "codetypo.scanWorkspace",
+          "arguments": []
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut initialize: Value =
+        serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+    initialize["params"]["initializationOptions"]["concurrency"] = json!(3);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // files are checked in parallel, each with its own diagnostics
+    let (_, mut messages) = server.request_with_messages(&scan_workspace, 10).await;
+    messages.sort_by_key(|message| message["params"]["uri"].as_str().unwrap().to_string());
+    similar_asserts::assert_eq!(
+        messages,
+        typo_txts
+            .iter()
+            .map(|(uri, line)| json!(
+              {
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": {
+                  "uri": uri,
+                  "diagnostics": [diag("`teh` should be `the`", *line, 0, 3)]
+                }
+              }
+            ))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_scan_workspace_large_file() {
+    let workspace_folder = temp_dir("test_scan_workspace_large_file");
+    // large files are mapped rather than read
+    let lines = 1024 * 1024 / "the\n".len();
+    std::fs::write(
+        workspace_folder.join("large.txt"),
+        format!("{}teh\n", "the\n".repeat(lines)),
+    )
+    .unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let large_txt = Url::from_file_path(workspace_folder.join("large.txt")).unwrap();
+
+    let scan_workspace = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": {
+          "command": "codetypo.scanWorkspace",
+          "arguments": []
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+
+    similar_asserts::assert_eq!(
+        server.request_with_messages(&scan_workspace, 1).await.1,
+        vec![json!(
+          {
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {
+              "uri": large_txt,
+              "diagnostics": [diag("`teh` should be `the`", lines as u32, 0, 3)]
+            }
+          }
+        )]
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_check_file_names() {
+    let workspace_folder = temp_dir("test_check_file_names");
+    std::fs::create_dir_all(workspace_folder.join("teh")).unwrap();
+    std::fs::write(workspace_folder.join("teh").join("fo.txt"), "the\n").unwrap();
+    std::fs::write(workspace_folder.join("clean.txt"), "the\n").unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let fo_txt = Url::from_file_path(workspace_folder.join("teh").join("fo.txt")).unwrap();
+
+    let check_file_names = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": {
+          "command": "codetypo.checkFileNames",
+          "arguments": []
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    // the diagnostics have no data, as there's nothing to fix in the file
+    let path_diag = |message: &str| {
+        let mut diag = diag(message, 0, 0, 0);
+        diag.as_object_mut().unwrap().remove("data");
+        diag
+    };
+    let path_diags = vec![
+        path_diag("`teh` should be `the` in the path `teh/fo.txt`"),
+        path_diag("`fo` should be `to`, `of`, `for`, `do`, `go` in the path `teh/fo.txt`"),
+    ];
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+
+    // typos in the names of files and their directories are reported at the top of the file
+    similar_asserts::assert_eq!(
+        server.request_with_messages(&check_file_names, 1).await,
+        (
+            json!({ "jsonrpc": "2.0", "result": null, "id": 2 }),
+            vec![json!(
+              {
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": fo_txt, "diagnostics": path_diags }
+              }
+            )]
+        )
+    );
+
+    // and in the diagnostics of open documents, when enabled
+    let mut server = TestServer::new();
+    let mut initialize: Value =
+        serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+    initialize["params"]["initializationOptions"]["checkFileNames"] = json!(true);
+    let _ = server.request(&initialize.to_string()).await;
+    let mut diagnostics = vec![diag("`teh` should be `the`", 0, 0, 3)];
+    diagnostics.extend(path_diags);
+    similar_asserts::assert_eq!(
+        server.request(&did_open_with("teh", Some(&fo_txt))).await,
+        publish_diagnostics_with(&diagnostics, Some(&fo_txt))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_work_done_progress() {
+    let workspace_folder = temp_dir("test_work_done_progress");
+    std::fs::write(workspace_folder.join("a.txt"), "teh\n").unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+
+    let scan_workspace = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/executeCommand",
+        "params": {
+          "command": "codetypo.scanWorkspace",
+          "arguments": []
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut initialize: Value =
+        serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+    initialize["params"]["capabilities"]["window"] = json!({ "workDoneProgress": true });
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // the server creates a token, then reports progress with it
+    server.send(&scan_workspace).await;
+    let create = server.recv().await;
+    similar_asserts::assert_eq!(
+        (&create["method"], &create["params"]),
+        (
+            &json!("window/workDoneProgress/create"),
+            &json!({ "token": "codetypo/1" })
+        )
+    );
+    let create_response = json!({ "jsonrpc": "2.0", "result": null, "id": create["id"] });
+    let (response, messages) = server
+        .request_with_messages(&create_response.to_string(), 4)
+        .await;
+
+    similar_asserts::assert_eq!(
+        response,
+        json!({ "jsonrpc": "2.0", "result": null, "id": 2 })
+    );
+    similar_asserts::assert_eq!(
+        messages
+            .iter()
+            .map(|msg| match msg["method"].as_str().unwrap() {
+                "$/progress" => format!(
+                    "{} {} {}",
+                    msg["params"]["token"].as_str().unwrap(),
+                    msg["params"]["value"]["kind"].as_str().unwrap(),
+                    msg["params"]["value"]["message"]
+                        .as_str()
+                        .or(msg["params"]["value"]["title"].as_str())
+                        .unwrap_or_default()
+                ),
+                method => method.to_string(),
+            })
+            .collect::<Vec<_>>(),
+        vec![
+            "codetypo/1 begin Scanning workspace",
+            "codetypo/1 report 0/1",
+            "textDocument/publishDiagnostics",
+            "codetypo/1 end ",
+        ]
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_did_change_configuration() {
+    let did_change_configuration = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/didChangeConfiguration",
+        "params": {
+          "settings": {
+            "codetypo": { "diagnosticSeverity": "Error" }
+          }
+        }
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open("fo")).await;
+
+    // open documents are re-checked with the new settings
+    let mut diag = diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 0, 0, 2);
+    diag["severity"] = json!(1);
+    server.send(&did_change_configuration).await;
+    similar_asserts::assert_eq!(server.recv().await, publish_diagnostics(&[diag]));
+}
+
+#[test_log::test(tokio::test)]
+async fn test_workspace_configuration() {
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["capabilities"]["workspace"] = json!({ "configuration": true });
+
+    let initialized = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "initialized",
+        "params": {}
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+    let _ = server.request(&did_open("fo")).await;
+
+    // settings are pulled from the client once initialized
+    server.send(&initialized).await;
+    let _log_message = server.recv().await;
+    let configuration = server.recv().await;
+    similar_asserts::assert_eq!(
+        configuration["params"],
+        json!({ "items": [ { "section": "codetypo" } ] })
+    );
+
+    let configuration_response = json!(
+      {
+        "jsonrpc": "2.0",
+        "result": [ { "diagnosticSeverity": "Hint" } ],
+        "id": configuration["id"]
+      }
+    )
+    .to_string();
+
+    let mut diag = diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 0, 0, 2);
+    diag["severity"] = json!(4);
+    similar_asserts::assert_eq!(
+        server.request(&configuration_response).await,
+        publish_diagnostics(&[diag])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_watch_config_files() {
+    let workspace_folder = temp_dir("test_watch_config_files");
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let diag_txt = Url::from_file_path(workspace_folder.join("diagnostics.txt")).unwrap();
+    let codetypo_toml = Url::from_file_path(workspace_folder.join("codetypo.toml")).unwrap();
+
+    let mut initialize: Value =
+        serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+    initialize["params"]["capabilities"]["workspace"] =
+        json!({ "didChangeWatchedFiles": { "dynamicRegistration": true } });
+
+    let initialized = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "initialized",
+        "params": {}
+      }
+    )
+    .to_string();
+
+    let did_change_watched_files = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/didChangeWatchedFiles",
+        "params": {
+          "changes": [ { "uri": codetypo_toml, "type": 1 } ]
+        }
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+    let _ = server
+        .request(&did_open_with("apropriate", Some(&diag_txt)))
+        .await;
+
+    // config files are watched once initialized
+    server.send(&initialized).await;
+    let _log_message = server.recv().await;
+    let register_capability = server.recv().await;
+    similar_asserts::assert_eq!(
+        register_capability["params"]["registrations"][0]["method"],
+        json!("workspace/didChangeWatchedFiles")
+    );
+    assert!(
+        register_capability["params"]["registrations"][0]["registerOptions"]["watchers"]
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "globPattern": "**/codetypo.toml" }))
+    );
+    server
+        .send(
+            &json!({ "jsonrpc": "2.0", "result": null, "id": register_capability["id"] })
+                .to_string(),
+        )
+        .await;
+
+    // changes take effect without restarting the server
+    std::fs::write(
+        workspace_folder.join("codetypo.toml"),
+        "[default.extend-words]\napropriate = \"apropriate\"\n",
+    )
+    .unwrap();
+    server.send(&did_change_watched_files).await;
+    similar_asserts::assert_eq!(
+        server.recv().await,
+        publish_diagnostics_with(&[], Some(&diag_txt))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_watch_referenced_files() {
+    let parent = temp_dir("test_watch_referenced_files");
+    let workspace_folder = parent.join("workspace");
+    std::fs::create_dir_all(&workspace_folder).unwrap();
+    std::fs::write(
+        parent.join("codetypo.toml"),
+        "[default.extend-words]\nfo = \"of\"\n",
+    )
+    .unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let diag_txt = Url::from_file_path(workspace_folder.join("diagnostics.txt")).unwrap();
+    let parent_codetypo_toml = Url::from_file_path(parent.join("codetypo.toml")).unwrap();
+
+    let mut initialize: Value =
+        serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+    initialize["params"]["capabilities"]["workspace"] =
+        json!({ "didChangeWatchedFiles": { "dynamicRegistration": true } });
+
+    let did_change_watched_files = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/didChangeWatchedFiles",
+        "params": {
+          "changes": [ { "uri": parent_codetypo_toml, "type": 2 } ]
+        }
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+    similar_asserts::assert_eq!(
+        server.request(&did_open_with("fo", Some(&diag_txt))).await,
+        publish_diagnostics_with(&[diag("`fo` should be `of`", 0, 0, 2)], Some(&diag_txt))
+    );
+
+    // the config file in the parent of the workspace folder is watched by its path
+    server
+        .send(&json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }).to_string())
+        .await;
+    let _log_message = server.recv().await;
+    let register_capability = server.recv().await;
+    assert!(
+        register_capability["params"]["registrations"][0]["registerOptions"]["watchers"]
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "globPattern": parent.join("codetypo.toml") }))
+    );
+    server
+        .send(
+            &json!({ "jsonrpc": "2.0", "result": null, "id": register_capability["id"] })
+                .to_string(),
+        )
+        .await;
+
+    // changes take effect without restarting the server
+    std::fs::write(
+        parent.join("codetypo.toml"),
+        "[default.extend-words]\nfo = \"go\"\n",
+    )
+    .unwrap();
+    similar_asserts::assert_eq!(
+        server.request(&did_change_watched_files).await,
+        publish_diagnostics_with(&[diag("`fo` should be `go`", 0, 0, 2)], Some(&diag_txt))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_create_delete_config_file() {
+    let workspace_folder = temp_dir("test_create_delete_config_file");
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let diag_txt = Url::from_file_path(workspace_folder.join("diagnostics.txt")).unwrap();
+    let codetypo_toml = Url::from_file_path(workspace_folder.join("codetypo.toml")).unwrap();
+
+    let did_create_files = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/didCreateFiles",
+        "params": { "files": [ { "uri": codetypo_toml } ] }
+      }
+    )
+    .to_string();
+
+    let did_delete_files = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/didDeleteFiles",
+        "params": { "files": [ { "uri": codetypo_toml } ] }
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+    similar_asserts::assert_eq!(
+        server.request(&did_open_with("fo", Some(&diag_txt))).await,
+        publish_diagnostics_with(
+            &[diag(
+                "`fo` should be `to`, `of`, `for`, `do`, `go`",
+                0,
+                0,
+                2
+            )],
+            Some(&diag_txt)
+        )
+    );
+
+    // a created config file takes effect without restarting the server
+    std::fs::write(
+        workspace_folder.join("codetypo.toml"),
+        "[default.extend-words]\nfo = \"go\"\n",
+    )
+    .unwrap();
+    similar_asserts::assert_eq!(
+        server.request(&did_create_files).await,
+        publish_diagnostics_with(&[diag("`fo` should be `go`", 0, 0, 2)], Some(&diag_txt))
+    );
+
+    // and so does deleting it
+    std::fs::remove_file(workspace_folder.join("codetypo.toml")).unwrap();
+    similar_asserts::assert_eq!(
+        server.request(&did_delete_files).await,
+        publish_diagnostics_with(
+            &[diag(
+                "`fo` should be `to`, `of`, `for`, `do`, `go`",
+                0,
+                0,
+                2
+            )],
+            Some(&diag_txt)
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_did_change() {
+    let did_change = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/didChange",
+        "params": {
+          "textDocument": {
+            "uri": "file:///C%3A/diagnostics.txt",
+            "version": 2
+          },
+          "contentChanges": [
+            { "text": "this is recieve\n" },
+            {
+              "range": {
+                "start": { "line": 0, "character": 0 },
+                "end": { "line": 0, "character": 4 }
+              },
+              "text": "𝐀 teh"
+            }
+          ]
+        }
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open("fo")).await;
+
+    // every change is applied in order, so the document is "𝐀 teh is recieve\n"
+    similar_asserts::assert_eq!(
+        server.request(&did_change).await["params"]["diagnostics"],
+        json!([
+            diag("`teh` should be `the`", 0, 3, 6),
+            diag("`recieve` should be `receive`", 0, 10, 17)
+        ])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_did_change_incremental() {
+    let did_change = |version: i32, start: (u32, u32), end: (u32, u32), text: &str| {
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+              "textDocument": {
+                "uri": "file:///C%3A/diagnostics.txt",
+                "version": version
+              },
+              "contentChanges": [
+                {
+                  "range": {
+                    "start": { "line": start.0, "character": start.1 },
+                    "end": { "line": end.0, "character": end.1 }
+                  },
+                  "text": text
+                }
+              ]
+            }
+          }
+        )
+        .to_string()
+    };
+
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["diagnosticDelay"] = json!(0);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open(
+                "teh\nok\nok\nok\nrecieve codetypo:disable-line\n"
+            ))
+            .await["params"]["diagnostics"],
+        json!([diag("`teh` should be `the`", 0, 0, 3)])
+    );
+
+    // only the changed lines are checked again, the typos of the others are moved
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_change(2, (4, 8), (4, 29), "\nfo"))
+            .await["params"]["diagnostics"],
+        json!([
+            diag("`teh` should be `the`", 0, 0, 3),
+            diag("`recieve` should be `receive`", 4, 0, 7),
+            diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 5, 0, 2)
+        ])
+    );
+    similar_asserts::assert_eq!(
+        server.request(&did_change(3, (0, 0), (2, 0), "")).await["params"]["diagnostics"],
+        json!([
+            diag("`recieve` should be `receive`", 2, 0, 7),
+            diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 3, 0, 2)
+        ])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_crlf_and_mixed_newlines() {
+    let did_change = json!({
+      "jsonrpc": "2.0",
+      "method": "textDocument/didChange",
+      "params": {
+        "textDocument": { "uri": "file:///C%3A/diagnostics.txt", "version": 2 },
+        "contentChanges": [{
+          // past the end of the line, so clamped to before its line break
+          "range": range(1, 100, 100),
+          "text": " teh"
+        }]
+      }
+    })
+    .to_string();
+
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["diagnosticDelay"] = json!(0);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // `\r\n` and a lone `\r` are line breaks, like `\n`
+    similar_asserts::assert_eq!(
+        server.request(&did_open("teh\r\nok teh\rfo\n")).await["params"]["diagnostics"],
+        json!([
+            diag("`teh` should be `the`", 0, 0, 3),
+            diag("`teh` should be `the`", 1, 3, 6),
+            diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 2, 0, 2)
+        ])
+    );
+
+    similar_asserts::assert_eq!(
+        server.request(&did_change).await["params"]["diagnostics"],
+        json!([
+            diag("`teh` should be `the`", 0, 0, 3),
+            diag("`teh` should be `the`", 1, 3, 6),
+            diag("`teh` should be `the`", 1, 7, 10),
+            diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 2, 0, 2)
+        ])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_did_change_debounce() {
+    let did_change = |text: &str, version: i32| {
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+              "textDocument": {
+                "uri": "file:///C%3A/diagnostics.txt",
+                "version": version
+              },
+              "contentChanges": [ { "text": text } ]
+            }
+          }
+        )
+        .to_string()
+    };
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+    let _ = server.request(&did_open("fo")).await;
+
+    // only the last of a burst of changes is checked
+    server.send(&did_change("teh", 2)).await;
+    server.send(&did_change("apropriate", 3)).await;
+    let publish_diagnostics = server.recv().await;
+    similar_asserts::assert_eq!(
+        (
+            &publish_diagnostics["params"]["version"],
+            &publish_diagnostics["params"]["diagnostics"]
+        ),
+        (
+            &json!(3),
+            &json!([diag("`apropriate` should be `appropriate`", 0, 0, 10)])
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_check_on_save_only() {
+    let did_change = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/didChange",
+        "params": {
+          "textDocument": {
+            "uri": "file:///C%3A/diagnostics.txt",
+            "version": 2
+          },
+          "contentChanges": [ { "text": "teh" } ]
+        }
+      }
+    )
+    .to_string();
+
+    let did_save = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/didSave",
+        "params": {
+          "textDocument": { "uri": "file:///C%3A/diagnostics.txt" }
+        }
+      }
+    )
+    .to_string();
+
+    let code_lens = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/codeLens",
+        "params": {
+          "textDocument": { "uri": "file:///C%3A/diagnostics.txt" }
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["checkOnSaveOnly"] = json!(true);
+    initialize["params"]["initializationOptions"]["diagnosticDelay"] = json!(0);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+    similar_asserts::assert_eq!(
+        server.request(&did_open("fo")).await,
+        publish_diagnostics(&[diag(
+            "`fo` should be `to`, `of`, `for`, `do`, `go`",
+            0,
+            0,
+            2
+        )])
+    );
+
+    // changes are synced but not checked, so the next message is the code lens response
+    server.send(&did_change).await;
+    similar_asserts::assert_eq!(
+        server.request(&code_lens).await["result"][0]["command"]["title"],
+        json!("1 typo — Fix all")
+    );
+
+    let publish_diagnostics = server.request(&did_save).await;
+    similar_asserts::assert_eq!(
+        (
+            &publish_diagnostics["params"]["version"],
+            &publish_diagnostics["params"]["diagnostics"]
+        ),
+        (&json!(2), &json!([diag("`teh` should be `the`", 0, 0, 3)]))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_disable_line() {
+    let did_open =
+        did_open("teh // codetypo:disable-line\n# codetypo:disable-next-line\nteh\nteh\n");
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+
+    // only the line without a suppression comment is reported
+    similar_asserts::assert_eq!(
+        server.request(&did_open).await,
+        publish_diagnostics(&[diag("`teh` should be `the`", 3, 0, 3)])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_disable_block() {
+    let did_open = did_open("teh\n// codetypo:off\nteh\nteh\n// codetypo:on\nteh\n");
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+
+    // lines between the markers aren't reported
+    similar_asserts::assert_eq!(
+        server.request(&did_open).await,
+        publish_diagnostics(&[
+            diag("`teh` should be `the`", 0, 0, 3),
+            diag("`teh` should be `the`", 5, 0, 3)
+        ])
+    );
+
+    // directives that are only the start of a word don't turn checking on or off
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with(
+                "// codetypo:offset
+teh
+// codetypo:off
+teh
+// codetypo:once
+teh
+\
+                 // codetypo:on
+teh // codetypo:disable-lines
+",
+                None
+            ))
+            .await,
+        publish_diagnostics(&[
+            diag("`teh` should be `the`", 1, 0, 3),
+            diag("`teh` should be `the`", 7, 0, 3)
+        ])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_syntax_aware() {
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["syntaxAware"] = json!(true);
+
+    let mut did_open: Value =
+        serde_json::from_str(&did_open("let teh = 1; // teh\nlet s = \"teh\";\n")).unwrap();
+    did_open["params"]["textDocument"]["languageId"] = json!("rust");
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // only comments and strings are checked, not code
+    similar_asserts::assert_eq!(
+        server.request(&did_open.to_string()).await,
+        publish_diagnostics(&[
+            diag("`teh` should be `the`", 0, 16, 19),
+            diag("`teh` should be `the`", 1, 9, 12)
+        ])
+    );
+
+    // languages that aren't known are checked in full
+    similar_asserts::assert_eq!(
+        server.request(&did_open_with("let teh", None)).await,
+        publish_diagnostics(&[diag("`teh` should be `the`", 0, 4, 7)])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_git_commit() {
+    let message =
+        "Fix teh bug\n\nSome teh details.\n\nSigned-off-by: Teh Author <teh@example.com>\n\
+                   # Please enter teh commit message\n\
+                   # ------------------------ >8 ------------------------\n\
+                   diff --git a/teh.txt b/teh.txt\n";
+    let mut did_open: Value = serde_json::from_str(&did_open(message)).unwrap();
+    did_open["params"]["textDocument"]["languageId"] = json!("git-commit");
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+
+    // comments, trailers and the diff are skipped
+    let expected = [
+        diag("`teh` should be `the`", 0, 4, 7),
+        diag("`teh` should be `the`", 2, 5, 8),
+    ];
+    similar_asserts::assert_eq!(
+        server.request(&did_open.to_string()).await,
+        publish_diagnostics(&expected)
+    );
+
+    // commit messages are also recognised by their file name
+    let commit_editmsg = Url::parse("file:///C%3A/repo/.git/COMMIT_EDITMSG").unwrap();
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with(message, Some(&commit_editmsg)))
+            .await,
+        publish_diagnostics_with(&expected, Some(&commit_editmsg))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_languages() {
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["languages"] = json!({
+      "markdown": { "diagnosticSeverity": "Hint", "extendIgnoreRe": ["ignored \\w+"] }
+    });
+
+    let mut did_open_markdown: Value =
+        serde_json::from_str(&did_open("teh\nignored teh\n")).unwrap();
+    did_open_markdown["params"]["textDocument"]["languageId"] = json!("markdown");
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // markdown has its own severity and ignored patterns
+    let mut hint = diag("`teh` should be `the`", 0, 0, 3);
+    hint["severity"] = json!(4);
+    similar_asserts::assert_eq!(
+        server.request(&did_open_markdown.to_string()).await,
+        publish_diagnostics(&[hint])
+    );
+
+    // other languages are unaffected
+    similar_asserts::assert_eq!(
+        server.request(&did_open("teh\nignored teh\n")).await,
+        publish_diagnostics(&[
+            diag("`teh` should be `the`", 0, 0, 3),
+            diag("`teh` should be `the`", 1, 8, 11)
+        ])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_disabled_language_ids() {
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["disabledLanguageIds"] = json!(["log"]);
+
+    let mut did_open_log: Value = serde_json::from_str(&did_open("teh\n")).unwrap();
+    did_open_log["params"]["textDocument"]["languageId"] = json!("log");
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    similar_asserts::assert_eq!(
+        server.request(&did_open_log.to_string()).await,
+        publish_diagnostics(&[])
+    );
+
+    // other languages are still checked
+    similar_asserts::assert_eq!(
+        server.request(&did_open("teh\n")).await,
+        publish_diagnostics(&[diag("`teh` should be `the`", 0, 0, 3)])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_config_file() {
+    let workspace_folder_uri =
+        Url::from_file_path(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")).unwrap();
+    let diag_txt = workspace_folder_uri.join("tests/diagnostics.txt").unwrap();
+    let changelog_md = workspace_folder_uri.join("tests/CHANGELOG.md").unwrap();
+    let skip_me = workspace_folder_uri.join("tests/skip_me.txt").unwrap();
+
+    let did_open_diag_txt = did_open_with("fo codetypo", Some(&diag_txt));
+    let did_open_changelog_md = did_open_with("fo codetypo", Some(&changelog_md));
+    let did_open_skip_me = did_open_with("fo codetypo # skip_me", Some(&skip_me));
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+
+    // check "fo" is corrected to "of" because of default.extend-words
+    similar_asserts::assert_eq!(
+        server.request(&did_open_diag_txt).await,
+        publish_diagnostics_with(&[diag("`fo` should be `of`", 0, 0, 2)], Some(&diag_txt))
+    );
+
+    // check changelog is excluded because of files.extend-exclude
+    similar_asserts::assert_eq!(
+        server.request(&did_open_changelog_md).await,
+        publish_diagnostics_with(&[], Some(&changelog_md)),
+    );
+
+    // check skip_line is excluded because of default.extend-ignore-re
+    similar_asserts::assert_eq!(
+        server.request(&did_open_skip_me).await,
+        publish_diagnostics_with(&[], Some(&skip_me)),
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_nested_workspace_folders() {
+    let outer = temp_dir("test_nested_workspace_folders");
+    let inner = outer.join("inner");
+    std::fs::create_dir_all(&inner).unwrap();
+    std::fs::write(
+        outer.join("codetypo.toml"),
+        "[default.extend-words]\nteh = \"teh\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        inner.join("codetypo.toml"),
+        "[default.extend-words]\nfo = \"fo\"\n",
+    )
+    .unwrap();
+    let outer_uri = Url::from_file_path(&outer).unwrap();
+    let inner_uri = Url::from_file_path(&inner).unwrap();
+
+    // the nested folder is listed first, so the order of the folders doesn't decide the route
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["workspaceFolders"] = json!([
+        { "uri": inner_uri, "name": "inner" },
+        { "uri": outer_uri, "name": "outer" }
+    ]);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    let outer_file = Url::from_file_path(outer.join("a.txt")).unwrap();
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("teh fo", Some(&outer_file)))
+            .await,
+        publish_diagnostics_with(
+            &[diag(
+                "`fo` should be `to`, `of`, `for`, `do`, `go`",
+                0,
+                4,
+                6
+            )],
+            Some(&outer_file)
+        )
+    );
+
+    // the most specific folder's config wins
+    let inner_file = Url::from_file_path(inner.join("a.txt")).unwrap();
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("teh fo", Some(&inner_file)))
+            .await,
+        publish_diagnostics_with(&[diag("`teh` should be `the`", 0, 0, 3)], Some(&inner_file))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_did_change_workspace_folders() {
+    let folder_a = temp_dir("test_did_change_workspace_folders_a");
+    let folder_b = temp_dir("test_did_change_workspace_folders_b");
+    std::fs::write(
+        folder_a.join("codetypo.toml"),
+        "[default.extend-words]\nteh = \"teh\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        folder_b.join("codetypo.toml"),
+        "[default.extend-words]\nfo = \"fo\"\n",
+    )
+    .unwrap();
+    let folder_a_uri = Url::from_file_path(&folder_a).unwrap();
+    let folder_b_uri = Url::from_file_path(&folder_b).unwrap();
+
+    let did_change_workspace_folders = |added: Value, removed: Value| {
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "method": "workspace/didChangeWorkspaceFolders",
+            "params": {
+              "event": { "added": added, "removed": removed }
+            }
+          }
+        )
+        .to_string()
+    };
+    let ping = json!({ "jsonrpc": "2.0", "method": "codetypo/ping", "id": 2 }).to_string();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&folder_a_uri), None))
+        .await;
+
+    // folder a's config isn't re-read when another folder is added
+    std::fs::write(folder_a.join("codetypo.toml"), "").unwrap();
+    server
+        .send(&did_change_workspace_folders(
+            json!([{ "uri": folder_b_uri, "name": "b" }]),
+            json!([]),
+        ))
+        .await;
+    let response = server.request(&ping).await;
+    similar_asserts::assert_eq!(
+        (
+            &response["result"]["workspaceFolders"],
+            &response["result"]["configFiles"]
+        ),
+        (
+            &json!(2),
+            &json!([
+                folder_a.join("codetypo.toml"),
+                folder_b.join("codetypo.toml")
+            ])
+        )
+    );
+
+    let file_a = Url::from_file_path(folder_a.join("a.txt")).unwrap();
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("teh fo", Some(&file_a)))
+            .await,
+        publish_diagnostics_with(
+            &[diag(
+                "`fo` should be `to`, `of`, `for`, `do`, `go`",
+                0,
+                4,
+                6
+            )],
+            Some(&file_a)
+        )
+    );
+    let file_b = Url::from_file_path(folder_b.join("a.txt")).unwrap();
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("teh fo", Some(&file_b)))
+            .await,
+        publish_diagnostics_with(&[diag("`teh` should be `the`", 0, 0, 3)], Some(&file_b))
+    );
+
+    // files in a removed folder fall back to the catch all route
+    server
+        .send(&did_change_workspace_folders(
+            json!([]),
+            json!([{ "uri": folder_b_uri, "name": "b" }]),
+        ))
+        .await;
+    let response = server.request(&ping).await;
+    similar_asserts::assert_eq!(
+        (
+            &response["result"]["workspaceFolders"],
+            &response["result"]["configFiles"]
+        ),
+        (&json!(1), &json!([folder_a.join("codetypo.toml")]))
+    );
+    let file_b = Url::from_file_path(folder_b.join("b.txt")).unwrap();
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("teh fo", Some(&file_b)))
+            .await,
+        publish_diagnostics_with(
+            &[
+                diag("`teh` should be `the`", 0, 0, 3),
+                diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 0, 4, 6)
+            ],
+            Some(&file_b)
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_path_mappings() {
+    let workspace_folder = temp_dir("test_path_mappings");
+    std::fs::write(
+        workspace_folder.join("codetypo.toml"),
+        "[default.extend-words]\nteh = \"teh\"\n",
+    )
+    .unwrap();
+
+    // the client's paths don't exist on the server, eg: in a dev container
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["workspaceFolders"] =
+        json!([{ "uri": "file:///home/me/project", "name": "project" }]);
+    initialize["params"]["initializationOptions"]["pathMappings"] = json!({
+        "/home/me/project/": workspace_folder,
+        "C:\\Users\\me\\project": workspace_folder
+    });
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    let fo = diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 0, 4, 6);
+    for uri in [
+        "file:///home/me/project/a.txt",
+        "file:///c%3A/Users/me/project/a.txt",
+    ] {
+        let uri = Url::parse(uri).unwrap();
+        similar_asserts::assert_eq!(
+            server.request(&did_open_with("teh fo", Some(&uri))).await,
+            publish_diagnostics_with(std::slice::from_ref(&fo), Some(&uri))
+        );
+    }
+
+    // paths that aren't mapped are the server's
+    let uri = Url::parse("file:///home/me/other/a.txt").unwrap();
+    similar_asserts::assert_eq!(
+        server.request(&did_open_with("teh fo", Some(&uri))).await,
+        publish_diagnostics_with(&[diag("`teh` should be `the`", 0, 0, 3), fo], Some(&uri))
+    );
+}
+
+#[cfg(unix)]
+#[test_log::test(tokio::test)]
+async fn test_symlinked_workspace_folder() {
+    let dir = temp_dir("test_symlinked_workspace_folder");
+    let target = dir.join("target");
+    let link = dir.join("link");
+    std::fs::create_dir(&target).unwrap();
+    std::os::unix::fs::symlink(&target, &link).unwrap();
+    std::fs::write(
+        target.join("codetypo.toml"),
+        "[files]\nextend-exclude = [\"excluded.txt\"]\n[default.extend-words]\nteh = \"teh\"\n",
+    )
+    .unwrap();
+    let link_uri = Url::from_file_path(&link).unwrap();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&link_uri), None))
+        .await;
+
+    // files are routed to the folder whether they're reached through the symlink or not
+    let fo = diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 0, 4, 6);
+    for path in [link.join("a.txt"), target.join("b.txt")] {
+        let uri = Url::from_file_path(path).unwrap();
+        similar_asserts::assert_eq!(
+            server.request(&did_open_with("teh fo", Some(&uri))).await,
+            publish_diagnostics_with(std::slice::from_ref(&fo), Some(&uri))
+        );
+    }
+    for path in [link.join("excluded.txt"), target.join("excluded.txt")] {
+        let uri = Url::from_file_path(path).unwrap();
+        similar_asserts::assert_eq!(
+            server.request(&did_open_with("teh fo", Some(&uri))).await,
+            publish_diagnostics_with(&[], Some(&uri))
+        );
+    }
+}
+
+#[test_log::test(tokio::test)]
+async fn test_nested_config_file() {
+    let workspace_folder = temp_dir("test_nested_config_file");
+    std::fs::create_dir_all(workspace_folder.join("nested")).unwrap();
+    std::fs::write(
+        workspace_folder.join("codetypo.toml"),
+        "[default.extend-words]\nfo = \"of\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        workspace_folder.join("nested").join("codetypo.toml"),
+        "[default.extend-words]\nfo = \"go\"\n",
+    )
+    .unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let top_txt = Url::from_file_path(workspace_folder.join("top.txt")).unwrap();
+    let nested_txt =
+        Url::from_file_path(workspace_folder.join("nested").join("nested.txt")).unwrap();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+
+    // files use the config of their nearest enclosing directory
+    similar_asserts::assert_eq!(
+        server.request(&did_open_with("fo", Some(&top_txt))).await,
+        publish_diagnostics_with(&[diag("`fo` should be `of`", 0, 0, 2)], Some(&top_txt))
+    );
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("fo", Some(&nested_txt)))
+            .await,
+        publish_diagnostics_with(&[diag("`fo` should be `go`", 0, 0, 2)], Some(&nested_txt))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_custom_config_file() {
+    let custom_config = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("custom_codetypo.toml");
+
+    let workspace_folder_uri =
+        Url::from_file_path(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")).unwrap();
+
+    let diag_txt = workspace_folder_uri.join("tests/diagnostics.txt").unwrap();
+
+    let did_open_diag_txt = did_open_with("fo codetypo", Some(&diag_txt));
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(
+            Some(&workspace_folder_uri),
+            Some(&custom_config),
+        ))
+        .await;
+
+    // check "fo" is corrected to "go" because of default.extend-words
+    // in custom_codetypo.toml which overrides codetypo.toml
+    similar_asserts::assert_eq!(
+        server.request(&did_open_diag_txt).await,
+        publish_diagnostics_with(&[diag("`fo` should be `go`", 0, 0, 2)], Some(&diag_txt))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_disallowed_severity() {
+    let workspace_folder = temp_dir("test_disallowed_severity");
+    std::fs::write(
+        workspace_folder.join("codetypo.toml"),
+        "[default.extend-words]\nbadword = \"\"\n",
+    )
+    .unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let diag_txt = Url::from_file_path(workspace_folder.join("diagnostics.txt")).unwrap();
+
+    let mut initialize: Value =
+        serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+    initialize["params"]["initializationOptions"]["disallowedSeverity"] = json!("Error");
+    initialize["params"]["capabilities"]["textDocument"]["publishDiagnostics"]
+        ["relatedInformation"] = json!(true);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // disallowed words are errors, while misspellings use the diagnostic severity
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("teh badword", Some(&diag_txt)))
+            .await,
+        publish_diagnostics_with(
+            &[
+                diag("`teh` should be `the`", 0, 0, 3),
+                json!({
+                  "code": "badword",
+                  "codeDescription": {
+                    "href": "https://github.com/khulnasoft/codetypo?tab=readme-ov-file#false-positives"
+                  },
+                  "message": "`badword` is disallowed",
+                  "range": range(0, 4, 11),
+                  "relatedInformation": [
+                    {
+                      "location": {
+                        "uri": Url::from_file_path(workspace_folder.join("codetypo.toml")).unwrap(),
+                        "range": range(1, 0, 7)
+                      },
+                      "message": "`badword` is defined in `[default.extend-words]`"
+                    }
+                  ],
+                  "severity": 1,
+                  "source": "codetypo"
+                })
+            ],
+            Some(&diag_txt)
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_config_file_names() {
+    let workspace_folder = temp_dir("test_config_file_names");
+    std::fs::write(
+        workspace_folder.join("spelling.toml"),
+        "[default.extend-words]\nfo = \"go\"\n",
+    )
+    .unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let diag_txt = Url::from_file_path(workspace_folder.join("diagnostics.txt")).unwrap();
+
+    let mut initialize: Value =
+        serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+    initialize["params"]["initializationOptions"]["configFileNames"] = json!(["spelling.toml"]);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // check "fo" is corrected to "go" because of default.extend-words in spelling.toml
+    similar_asserts::assert_eq!(
+        server.request(&did_open_with("fo", Some(&diag_txt))).await,
+        publish_diagnostics_with(&[diag("`fo` should be `go`", 0, 0, 2)], Some(&diag_txt))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_inline_config() {
+    let workspace_folder_uri =
+        Url::from_file_path(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")).unwrap();
+    let diag_txt = workspace_folder_uri.join("tests/diagnostics.txt").unwrap();
+
+    // as a JSON object or a TOML string, overriding "fo" = "of" in codetypo.toml
+    for inline_config in [
+        json!({ "default": { "extend-words": { "fo": "go" } } }),
+        json!("[default.extend-words]\nfo = \"go\"\n"),
+    ] {
+        let mut initialize: Value =
+            serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+        initialize["params"]["initializationOptions"]["inlineConfig"] = inline_config;
+
+        let mut server = TestServer::new();
+        let _ = server.request(&initialize.to_string()).await;
+
+        similar_asserts::assert_eq!(
+            server.request(&did_open_with("fo", Some(&diag_txt))).await,
+            publish_diagnostics_with(&[diag("`fo` should be `go`", 0, 0, 2)], Some(&diag_txt))
+        );
+    }
+}
+
+#[test_log::test(tokio::test)]
+async fn test_word_lists() {
+    let workspace_folder = temp_dir("test_word_lists");
+    std::fs::write(workspace_folder.join("words.txt"), "# domain words\n\nfo\n").unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let diag_txt = Url::from_file_path(workspace_folder.join("diagnostics.txt")).unwrap();
+
+    let mut initialize: Value =
+        serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+    initialize["params"]["initializationOptions"]["wordLists"] = json!(["words.txt"]);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // "fo" is accepted because it's in the word list, relative to the workspace folder
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("fo teh", Some(&diag_txt)))
+            .await,
+        publish_diagnostics_with(&[diag("`teh` should be `the`", 0, 3, 6)], Some(&diag_txt))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_hunspell_dictionary() {
+    let workspace_folder = temp_dir("test_hunspell_dictionary");
+    std::fs::write(workspace_folder.join("team.dic"), "2\npropriate/A\nfo\n").unwrap();
+    std::fs::write(
+        workspace_folder.join("team.aff"),
+        "SET UTF-8\n\nPFX A Y 1\nPFX A 0 a .\n",
+    )
+    .unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let diag_txt = Url::from_file_path(workspace_folder.join("diagnostics.txt")).unwrap();
+
+    let mut initialize: Value =
+        serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+    initialize["params"]["initializationOptions"]["wordLists"] = json!(["team.dic"]);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // "fo" is in the dictionary and "apropriate" is generated by its affix rule
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("fo apropriate teh", Some(&diag_txt)))
+            .await,
+        publish_diagnostics_with(&[diag("`teh` should be `the`", 0, 14, 17)], Some(&diag_txt))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_cspell_dictionary() {
+    use std::io::Write;
+
+    let workspace_folder = temp_dir("test_cspell_dictionary");
+    let mut terms = flate2::write::GzEncoder::new(
+        std::fs::File::create(workspace_folder.join("terms.txt.gz")).unwrap(),
+        flate2::Compression::default(),
+    );
+    terms.write_all(b"# team terms\nfo\n!teh\n").unwrap();
+    terms.finish().unwrap();
+    std::fs::write(workspace_folder.join("more.txt"), "~apropriate\n").unwrap();
+    std::fs::write(
+        workspace_folder.join("cspell-ext.json"),
+        json!({
+            "dictionaryDefinitions": [
+                { "name": "terms", "path": "./terms.txt.gz" },
+                { "name": "more", "path": "./more.txt" },
+                { "name": "compiled", "path": "./compiled.trie.gz" }
+            ]
+        })
+        .to_string(),
+    )
+    .unwrap();
+    let workspace_folder_uri = Url::from_file_path(&workspace_folder).unwrap();
+    let diag_txt = Url::from_file_path(workspace_folder.join("diagnostics.txt")).unwrap();
+
+    let mut initialize: Value =
+        serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+    initialize["params"]["initializationOptions"]["wordLists"] = json!(["cspell-ext.json"]);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // "teh" is forbidden by the dictionary, so isn't accepted
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("fo apropriate teh", Some(&diag_txt)))
+            .await,
+        publish_diagnostics_with(&[diag("`teh` should be `the`", 0, 14, 17)], Some(&diag_txt))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_locale() {
+    let workspace_folder = temp_dir("test_locale");
+    let gb_folder = workspace_folder.join("gb");
+    std::fs::create_dir_all(&gb_folder).unwrap();
+    std::fs::write(
+        gb_folder.join("codetypo.toml"),
+        "[default]\nlocale = \"en-gb\"\n",
+    )
+    .unwrap();
+
+    for (folder, expected) in [
+        (
+            &workspace_folder,
+            vec![diag("`colour` should be `color`", 0, 0, 6)],
+        ),
+        // the locale of the config file takes precedence over the setting
+        (&gb_folder, vec![]),
+    ] {
+        let workspace_folder_uri = Url::from_file_path(folder).unwrap();
+        let diag_txt = Url::from_file_path(folder.join("diagnostics.txt")).unwrap();
+
+        let mut initialize: Value =
+            serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+        initialize["params"]["initializationOptions"]["locale"] = json!("en-us");
+
+        let mut server = TestServer::new();
+        let _ = server.request(&initialize.to_string()).await;
+
+        similar_asserts::assert_eq!(
+            server
+                .request(&did_open_with("colour", Some(&diag_txt)))
+                .await,
+            publish_diagnostics_with(&expected, Some(&diag_txt))
+        );
+    }
+}
+
+#[test_log::test(tokio::test)]
+async fn test_min_word_length() {
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["minWordLength"] = json!(3);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // "fo" is shorter than 3 chars so isn't flagged
+    similar_asserts::assert_eq!(
+        server.request(&did_open("fo teh")).await,
+        publish_diagnostics(&[diag("`teh` should be `the`", 0, 3, 6)])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_ignore_all_caps_hex_base64() {
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["ignoreAllCaps"] = json!(true);
+    initialize["params"]["initializationOptions"]["ignoreHex"] = json!(true);
+    initialize["params"]["initializationOptions"]["ignoreBase64"] = json!(true);
+    initialize["params"]["initializationOptions"]["inlineConfig"] =
+        json!({ "default": { "extend-words": { "c0ffee00": "coffee" } } });
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // the all caps identifier, hex string, and base64 blob aren't checked
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open("TEH fo c0ffee00 dGVo/fo+aB3xYz9Qw1Lm teh"))
+            .await,
+        publish_diagnostics(&[
+            diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 0, 4, 6),
+            diag("`teh` should be `the`", 0, 37, 40)
+        ])
+    );
+
+    // paths and identifiers mixing cases and digits aren't blobs
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open("src/Components/Header2/recieve"))
+            .await,
+        publish_diagnostics(&[diag("`recieve` should be `receive`", 0, 23, 30)])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_diff_base() {
+    let dir = temp_dir("diff-base");
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(&dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    std::fs::write(dir.join("old.txt"), "teh\nfo\n").unwrap();
+    git(&["init", "--quiet"]);
+    git(&["add", "old.txt"]);
+    git(&["commit", "--quiet", "--message", "initial"]);
+    let old_txt = Url::from_file_path(dir.join("old.txt")).unwrap();
+    let new_txt = Url::from_file_path(dir.join("new.txt")).unwrap();
+
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["diffBase"] = json!("HEAD");
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // only the unsaved line added since HEAD is reported
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("teh\nteh added\nfo\n", Some(&old_txt)))
+            .await,
+        publish_diagnostics_with(&[diag("`teh` should be `the`", 1, 0, 3)], Some(&old_txt))
+    );
+
+    // all lines of files that aren't in HEAD are new
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("teh\n", Some(&new_txt)))
+            .await,
+        publish_diagnostics_with(&[diag("`teh` should be `the`", 0, 0, 3)], Some(&new_txt))
+    );
+
+    // the text at HEAD is read again once the file is saved, eg: after it's committed
+    std::fs::write(dir.join("old.txt"), "teh\nteh added\nfo\n").unwrap();
+    git(&["commit", "--quiet", "--all", "--message", "added"]);
+    let did_save = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/didSave",
+        "params": { "textDocument": { "uri": old_txt } }
+      }
+    )
+    .to_string();
+    let did_change = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "textDocument/didChange",
+        "params": {
+          "textDocument": { "uri": old_txt, "version": 2 },
+          "contentChanges": [{ "text": "teh\nteh added\nfo\n" }]
+        }
+      }
+    )
+    .to_string();
+    server.send(&did_save).await;
+    similar_asserts::assert_eq!(
+        server.request(&did_change).await,
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": old_txt, "diagnostics": [], "version": 2 }
+          }
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_binary_content() {
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+
+    // NUL chars
+    similar_asserts::assert_eq!(
+        server.request(&did_open("teh\0\u{1}teh\n")).await,
+        publish_diagnostics(&[])
+    );
+
+    // invalid UTF-8, decoded as replacement chars
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open("teh \u{fffd}\u{fffd}\u{fffd}\u{fffd}\u{fffd}\n"))
+            .await,
+        publish_diagnostics(&[])
+    );
+
+    // a few replacement chars in text are still checked
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open("teh \u{fffd} is not binary\n"))
+            .await,
+        publish_diagnostics(&[diag("`teh` should be `the`", 0, 0, 3)])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_max_file_size_and_line_length() {
+    let skipped = |line: u32, message: &str| {
+        json!({
+          "range": {
+            "start": { "line": line, "character": 0 },
+            "end": { "line": line, "character": 0 }
+          },
+          "severity": 3,
+          "source": "codetypo",
+          "message": message
+        })
+    };
+
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["maxFileSize"] = json!(40);
+    initialize["params"]["initializationOptions"]["maxLineLength"] = json!(10);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // long lines are skipped, with a single diagnostic explaining why
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open("teh\nteh teh teh\nfo\nteh teh teh\n"))
+            .await,
+        publish_diagnostics(&[
+            diag("`teh` should be `the`", 0, 0, 3),
+            diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 2, 0, 2),
+            skipped(
+                1,
+                "2 lines longer than 10 characters not checked for typos (`maxLineLength`)"
+            )
+        ])
+    );
+
+    // lines are counted the same with lone `\r` line breaks
+    similar_asserts::assert_eq!(
+        server.request(&did_open("fo\rteh teh teh\rteh\r")).await,
+        publish_diagnostics(&[
+            diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 0, 0, 2),
+            diag("`teh` should be `the`", 2, 0, 3),
+            skipped(
+                1,
+                "1 line longer than 10 characters not checked for typos (`maxLineLength`)"
+            )
+        ])
+    );
+
+    // large files aren't checked at all
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open(
+                "teh\nteh\nteh\nteh\nteh\nteh\nteh\nteh\nteh\nteh\nteh\n"
+            ))
+            .await,
+        publish_diagnostics(&[skipped(
+            0,
+            "Not checked for typos, as it's larger than 40 bytes (`maxFileSize`)"
+        )])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_correction_case() {
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+
+    // corrections are capitalized or uppercase like the typo
+    similar_asserts::assert_eq!(
+        server.request(&did_open("Teh TEH teh FO\n")).await,
+        publish_diagnostics(&[
+            diag("`Teh` should be `The`", 0, 0, 3),
+            diag("`TEH` should be `THE`", 0, 4, 7),
+            diag("`teh` should be `the`", 0, 8, 11),
+            diag("`FO` should be `TO`, `OF`, `FOR`, `DO`, `GO`", 0, 12, 14),
+        ])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_max_corrections_in_message() {
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["maxCorrectionsInMessage"] = json!(2);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // the data still has every correction, for code actions
+    let mut fo = diag("`fo` should be `to`, `of`, `for`, `do`, `go`", 1, 0, 2);
+    fo["message"] = json!("`fo` should be `to`, `of`, … and 3 more");
+    similar_asserts::assert_eq!(
+        server.request(&did_open("teh\nfo\n")).await,
+        publish_diagnostics(&[diag("`teh` should be `the`", 0, 0, 3), fo])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_custom_config_no_workspace_folder() {
+    // mimics Neovim opening a file outside the root dir
+    let custom_config = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("custom_codetypo.toml");
+
+    let workspace_folder_uri =
+        Url::from_file_path(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")).unwrap();
+
+    let diag_txt = workspace_folder_uri.join("tests/diagnostics.txt").unwrap();
+
+    let did_open_diag_txt = did_open_with("fo codetypo", Some(&diag_txt));
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(None, Some(&custom_config)))
+        .await;
+
+    // check "fo" is corrected to "go" because of default.extend-words
+    // in custom_codetypo.toml which overrides codetypo.toml
+    similar_asserts::assert_eq!(
+        server.request(&did_open_diag_txt).await,
+        publish_diagnostics_with(&[diag("`fo` should be `go`", 0, 0, 2)], Some(&diag_txt))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_workspace_diagnostic() {
+    let workspace_folder_uri = Url::from_file_path(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("workspace"),
+    )
+    .unwrap();
+    let diag_txt = workspace_folder_uri
+        .join("workspace/diagnostics.txt")
+        .unwrap();
+
+    let workspace_diagnostic = json!(
+      {
+        "jsonrpc": "2.0",
+        "method": "workspace/diagnostic",
+        "params": {
+          "previousResultIds": []
+        },
+        "id": 2
+      }
+    )
+    .to_string();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+
+    // check unopened files are reported, using the config from the parent folder
+    similar_asserts::assert_eq!(
+        server.request(&workspace_diagnostic).await,
+        json!(
+          {
+            "jsonrpc": "2.0",
+            "result": {
+              "items": [
+                {
+                  "kind": "full",
+                  "uri": diag_txt,
+                  "version": null,
+                  "items": [ diag("`fo` should be `of`", 1, 0, 2) ]
+                }
+              ]
+            },
+            "id": 2
+          }
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_non_file_uri() {
+    // a Neovim toggleterm uri
+    let term = Url::from_str("term://~/code/codetypo-lsp//59317:/bin/zsh;#toggleterm#1").unwrap();
+
+    let did_open_diag_txt = did_open_with("apropriate", Some(&term));
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize_with(None, None)).await;
+
+    similar_asserts::assert_eq!(
+        server.request(&did_open_diag_txt).await,
+        publish_diagnostics_with(
+            &[diag("`apropriate` should be `appropriate`", 0, 0, 10)],
+            Some(&term)
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_excluded_schemes() {
+    let term = Url::from_str("term://~/code/codetypo-lsp//59317:/bin/zsh;#toggleterm#1").unwrap();
+
+    let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+    initialize["params"]["initializationOptions"]["excludedSchemes"] = json!(["term"]);
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    similar_asserts::assert_eq!(
+        server
+            .request(&did_open_with("apropriate", Some(&term)))
+            .await,
+        publish_diagnostics_with(&[], Some(&term))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_untitled_path() {
+    let workspace_folder_uri =
+        Url::from_file_path(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")).unwrap();
+    let untitled = Url::from_str("untitled:Untitled-1").unwrap();
+
+    let mut initialize: Value =
+        serde_json::from_str(&initialize_with(Some(&workspace_folder_uri), None)).unwrap();
+    initialize["params"]["initializationOptions"]["untitledPath"] = json!("untitled.txt");
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize.to_string()).await;
+
+    // check "fo" is corrected to "of" because of default.extend-words in the workspace folder's
+    // codetypo.toml
+    similar_asserts::assert_eq!(
+        server.request(&did_open_with("fo", Some(&untitled))).await,
+        publish_diagnostics_with(&[diag("`fo` should be `of`", 0, 0, 2)], Some(&untitled))
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_virtual_document_uri() {
+    let workspace_folder_uri =
+        Url::from_file_path(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")).unwrap();
+    let diag_txt = workspace_folder_uri.join("tests/diagnostics.txt").unwrap();
+
+    let mut server = TestServer::new();
+    let _ = server
+        .request(&initialize_with(Some(&workspace_folder_uri), None))
+        .await;
+
+    // check "fo" is corrected to "of" because of default.extend-words in the workspace folder's
+    // codetypo.toml, which applies to the file the document is a cell or revision of
+    for uri in [
+        format!("vscode-notebook-cell:{}#W0sZmlsZQ%3D%3D", diag_txt.path()),
+        format!("git:{}?%7B%22ref%22%3A%22HEAD%22%7D", diag_txt.path()),
+    ] {
+        let uri = Url::from_str(&uri).unwrap();
+        similar_asserts::assert_eq!(
+            server.request(&did_open_with("fo", Some(&uri))).await,
+            publish_diagnostics_with(&[diag("`fo` should be `of`", 0, 0, 2)], Some(&uri))
+        );
+    }
+}
+
+#[test_log::test(tokio::test)]
+async fn test_empty_file_uri() {
+    // eg: when using nvim telescope
+    let term = Url::from_str("file:///").unwrap();
+
+    let did_open_diag_txt = did_open_with("apropriate", Some(&term));
+
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize_with(None, None)).await;
+
+    similar_asserts::assert_eq!(
+        server.request(&did_open_diag_txt).await,
+        publish_diagnostics_with(
+            &[diag("`apropriate` should be `appropriate`", 0, 0, 10)],
+            Some(&term)
+        )
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_position_with_unicode_text() {
+    let mut server = TestServer::new();
+    let _ = server.request(&initialize()).await;
+
+    // ¿ and é are two-byte code points in utf-8
+    let unicode_text = did_open("¿Qué hace él?");
+    similar_asserts::assert_eq!(
+        server.request(&unicode_text).await,
+        publish_diagnostics(&[diag("`hace` should be `have`", 0, 5, 9)])
+    );
+
+    // ẽ has two code points U+0065 U+0303 (latin small letter e, combining tilde)
+    let unicode_text = did_open("ẽ hace");
+    similar_asserts::assert_eq!(
+        server.request(&unicode_text).await,
+        publish_diagnostics(&[diag("`hace` should be `have`", 0, 2, 6)])
+    );
+
+    // 𝐀 is a surrogate pair in utf-16, and each typo's position continues from the previous one
+    let unicode_text = did_open("𝐀 teh 𝐀 teh\n𝐀 teh");
+    similar_asserts::assert_eq!(
+        server.request(&unicode_text).await,
+        publish_diagnostics(&[
+            diag("`teh` should be `the`", 0, 3, 6),
+            diag("`teh` should be `the`", 0, 10, 13),
+            diag("`teh` should be `the`", 1, 3, 6)
+        ])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_position_encoding() {
+    let initialize = |encodings: Value| {
+        let mut initialize: Value = serde_json::from_str(&initialize()).unwrap();
+        initialize["params"]["capabilities"]["general"] = json!({ "positionEncodings": encodings });
+        initialize["params"]["initializationOptions"]["diagnosticDelay"] = json!(0);
+        initialize.to_string()
+    };
+    let did_change = json!({
+      "jsonrpc": "2.0",
+      "method": "textDocument/didChange",
+      "params": {
+        "textDocument": { "uri": "file:///C%3A/diagnostics.txt", "version": 2 },
+        "contentChanges": [{ "range": range(0, 7, 11), "text": "teh" }]
+      }
+    })
+    .to_string();
+
+    // the first supported encoding the client prefers, counting bytes for UTF-8
+    let mut server = TestServer::new();
+    similar_asserts::assert_eq!(
+        server
+            .request(&initialize(json!(["utf-7", "utf-8", "utf-16"])))
+            .await["result"]["capabilities"]["positionEncoding"],
+        json!("utf-8")
+    );
+    similar_asserts::assert_eq!(
+        server.request(&did_open("¿Qué hace\n")).await,
+        publish_diagnostics(&[diag("`hace` should be `have`", 0, 7, 11)])
+    );
+    similar_asserts::assert_eq!(
+        server.request(&did_change).await["params"]["diagnostics"],
+        json!([diag("`teh` should be `the`", 0, 7, 10)])
+    );
+
+    // chars for UTF-32, so 𝐀 is a single unit
+    let mut server = TestServer::new();
+    similar_asserts::assert_eq!(
+        server.request(&initialize(json!(["utf-32"]))).await["result"]["capabilities"]
+            ["positionEncoding"],
+        json!("utf-32")
+    );
+    similar_asserts::assert_eq!(
+        server.request(&did_open("𝐀 teh")).await,
+        publish_diagnostics(&[diag("`teh` should be `the`", 0, 2, 5)])
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_ignore_codetypo_in_config_files() {
+    let term = Url::from_str("file:///C%3A/.codetypo.toml").unwrap();
+
+    let did_open = did_open_with("apropriate", Some(&term));
 
     let mut server = TestServer::new();
     let _ = server.request(&initialize_with(None, None)).await;
@@ -325,6 +4627,14 @@ async fn test_ignore_codetypo_in_config_files() {
     );
 }
 
+/// Creates an empty directory for tests that write files.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("codetypo-lsp-{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
 fn initialize() -> String {
     initialize_with(None, None)
 }
@@ -336,7 +4646,9 @@ fn initialize_with(workspace_folder_uri: Option<&Url>, custom_config: Option<&Pa
       "method": "initialize",
       "params": {
         "initializationOptions": {
-          "diagnosticSeverity": "Warning"
+          "diagnosticSeverity": "Warning",
+          // isolate tests from the user dictionary of whoever is running them
+          "userDictionary": PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("user_dictionary.toml")
         },
         "capabilities": {
           "textDocument": { "publishDiagnostics": { "dataSupport": true } }
@@ -378,14 +4690,18 @@ fn did_open_with(text: &str, uri: Option<&Url>) -> String {
 }
 
 fn diag(message: &str, line: u32, start: u32, end: u32) -> Value {
-    static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"`[^`]+` should be (.*)").unwrap());
+    static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"`([^`]+)` should be (.*)").unwrap());
 
     let caps = RE.captures(message).unwrap();
 
-    let corrections: Vec<&str> = caps[1].split(", ").map(|s| s.trim_matches('`')).collect();
+    let corrections: Vec<&str> = caps[2].split(", ").map(|s| s.trim_matches('`')).collect();
 
     json!({
-      "data": { "corrections": corrections },
+      "code": &caps[1],
+      "codeDescription": {
+        "href": "https://github.com/khulnasoft/codetypo?tab=readme-ov-file#false-positives"
+      },
+      "data": { "typo": &caps[1], "corrections": corrections },
       "message": message,
       "range": range(line,start,end),
       "severity": 2,
@@ -0,0 +1,33 @@
+#! /bin/bash
+# Generates src/frequency.txt, the most common words in the prose of The Rust Programming Language
+# book, from the copy installed with the Rust documentation, eg: `rustup component add rust-docs`.
+#
+# Usage: tools/frequency.sh [book directory] [number of words] > src/frequency.txt
+
+set -euo pipefail
+# so words with the same count are sorted the same everywhere
+export LC_ALL=C
+
+book=${1:-$(rustc --print sysroot)/share/doc/rust/html/book}
+count=${2:-1000}
+
+cat <<EOF
+# The $count most common words in the prose of The Rust Programming Language book, most common
+# first, generated by tools/frequency.sh.
+#
+# Source: https://doc.rust-lang.org/book/ by Steve Klabnik, Carol Nichols, and Chris Krycho, with
+# contributions from the Rust Community, licensed under Apache-2.0 OR MIT like the Rust project.
+# The words are one per line, and lines starting with \`#\` are comments.
+EOF
+
+# code and markup aren't prose, so they're dropped before counting, and curly apostrophes are made
+# straight so eg: `isn’t` is one word. Single letters other than `a` and `i` are left from plurals
+# of code, eg: `<code>String</code>s`, so they're dropped too
+cat "$book"/ch[0-9]*.html "$book"/appendix-[0-9]*.html |
+    perl -0777 -pe 's/<(pre|code|script|style)\b.*?<\/\1>/ /gs; s/<[^>]*>/ /g; s/&(#x27|#39|apos|rsquo);|\xe2\x80\x99/\x27/g; s/&#?\w+;/ /g' |
+    tr '[:upper:]' '[:lower:]' |
+    grep -oE "[a-z]+('[a-z]+)?" |
+    grep -vxE '[b-hj-z]' |
+    sort | uniq -c | sort -k1,1nr -k2,2 |
+    head -n "$count" |
+    awk '{ print $2 }'
@@ -27,9 +27,38 @@ export async function activate(
       async (e: vscode.ConfigurationChangeEvent) => {
         const restartTriggeredBy = [
           "codetypo.config",
+          "codetypo.diagnosticDelay",
           "codetypo.diagnosticSeverity",
+          "codetypo.disallowedSeverity",
           "codetypo.logLevel",
+          "codetypo.logFile",
+          "codetypo.logRotation",
           "codetypo.path",
+          "codetypo.syntaxAware",
+          "codetypo.minWordLength",
+          "codetypo.ignoreAllCaps",
+          "codetypo.ignoreHex",
+          "codetypo.ignoreBase64",
+          "codetypo.checkFileNames",
+          "codetypo.diffBase",
+          "codetypo.concurrency",
+          "codetypo.maxFileSize",
+          "codetypo.maxLineLength",
+          "codetypo.maxCorrectionsInMessage",
+          "codetypo.inlayHints",
+          "codetypo.autoFixOnSave",
+          "codetypo.checkOnSaveOnly",
+          "codetypo.userDictionary",
+          "codetypo.configFileNames",
+          "codetypo.inlineConfig",
+          "codetypo.wordLists",
+          "codetypo.locale",
+          "codetypo.languages",
+          "codetypo.disabledLanguageIds",
+          "codetypo.disabledCapabilities",
+          "codetypo.enable",
+          "codetypo.untitledPath",
+          "codetypo.pathMappings",
         ].find((s) => e.affectsConfiguration(s));
 
         if (restartTriggeredBy) {
@@ -73,18 +102,25 @@ async function createClient(
   name: string,
   outputChannel: vscode.OutputChannel,
 ): Promise<LanguageClient> {
-  const env = { ...process.env };
-
   let config = vscode.workspace.getConfiguration("codetypo");
   let path = await getServerPath(context, config);
 
   outputChannel.appendLine("Using codetypo server " + path);
 
-  env.RUST_LOG = config.get("logLevel");
+  const args = ["--log-level", config.get<string>("logLevel") ?? "warn"];
+  const logFile = config.get<string>("logFile");
+  if (logFile) {
+    args.push(
+      "--log-file",
+      logFile,
+      "--log-rotation",
+      config.get<string>("logRotation") ?? "never",
+    );
+  }
 
   const run: Executable = {
     command: path,
-    options: { env: env },
+    args: args,
   };
 
   const serverOptions: ServerOptions = {
@@ -105,7 +141,36 @@ async function createClient(
     traceOutputChannel: outputChannel,
     initializationOptions: {
       config: config.get("config") ? config.get("config") : null,
+      diagnosticDelay: config.get("diagnosticDelay"),
       diagnosticSeverity: config.get("diagnosticSeverity"),
+      disallowedSeverity: config.get("disallowedSeverity"),
+      syntaxAware: config.get("syntaxAware"),
+      minWordLength: config.get("minWordLength"),
+      ignoreAllCaps: config.get("ignoreAllCaps"),
+      ignoreHex: config.get("ignoreHex"),
+      ignoreBase64: config.get("ignoreBase64"),
+      checkFileNames: config.get("checkFileNames"),
+      diffBase: config.get("diffBase"),
+      concurrency: config.get("concurrency"),
+      maxFileSize: config.get("maxFileSize"),
+      maxLineLength: config.get("maxLineLength"),
+      maxCorrectionsInMessage: config.get("maxCorrectionsInMessage"),
+      inlayHints: config.get("inlayHints"),
+      autoFixOnSave: config.get("autoFixOnSave"),
+      checkOnSaveOnly: config.get("checkOnSaveOnly"),
+      configFileNames: config.get("configFileNames"),
+      inlineConfig: config.get("inlineConfig"),
+      wordLists: config.get("wordLists"),
+      locale: config.get("locale"),
+      languages: config.get("languages"),
+      disabledLanguageIds: config.get("disabledLanguageIds"),
+      disabledCapabilities: config.get("disabledCapabilities"),
+      enable: config.get("enable"),
+      untitledPath: config.get("untitledPath") ? config.get("untitledPath") : null,
+      pathMappings: config.get("pathMappings"),
+      userDictionary: config.get("userDictionary")
+        ? config.get("userDictionary")
+        : null,
     },
   };
 